@@ -0,0 +1,225 @@
+use bson::Bson;
+use colored::Colorize;
+use serde::de::DeserializeOwned;
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::scripting::params::Params;
+use crate::server::auth;
+use crate::server::protocol::{self, protocol_error, Request, Response, DEFAULT_BATCH_SIZE};
+
+use super::cursor::Cursor;
+
+// A session with a Blaze server, logged in as a user. Failures reported by the server
+// come back as I/O errors holding the `ErrorReport`, so `ErrorReport::from_error`
+// tells their kinds apart.
+pub struct Connection {
+    stream: TcpStream,
+    user: String,
+    roles: Vec<String>,
+    in_transaction: bool,
+    // Set once the stream fails, after which the connection can't be used.
+    broken: bool,
+}
+
+impl Connection {
+    pub fn connect(address: impl ToSocketAddrs, user: &str, password: &str) -> io::Result<Self> {
+        Self::connect_timeout(address, user, password, None)
+    }
+
+    // The timeout bounds connecting as well as waiting for each response.
+    pub fn connect_timeout(
+        address: impl ToSocketAddrs,
+        user: &str,
+        password: &str,
+        timeout: Option<Duration>,
+    ) -> io::Result<Self> {
+        let mut stream = open_stream(address, timeout)?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        stream.set_nodelay(true)?;
+        let roles = match auth::login(&mut stream, user, password)? {
+            Response::Success(Bson::Document(result)) => result
+                .get_array("roles")
+                .map(|roles| {
+                    roles
+                        .iter()
+                        .filter_map(|role| role.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Response::Success(_) => return Err(protocol_error("The login has no result document")),
+            Response::Failure(report) => return Err(report.into()),
+        };
+        Ok(Connection {
+            stream,
+            user: user.to_string(),
+            roles,
+            in_transaction: false,
+            broken: false,
+        })
+    }
+
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn roles(&self) -> &[String] {
+        &self.roles
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.in_transaction
+    }
+
+    // Whether the connection can still be used: it hasn't failed, and the server
+    // hasn't closed it, e.g. after it's been idle for too long.
+    pub fn is_open(&self) -> bool {
+        if self.broken || self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let peeked = self.stream.peek(&mut [0]);
+        let _ = self.stream.set_nonblocking(false);
+        matches!(peeked, Err(error) if error.kind() == io::ErrorKind::WouldBlock)
+    }
+
+    pub fn execute(&mut self, code: &str) -> io::Result<Bson> {
+        self.execute_with(code, Params::default())
+    }
+
+    // The parameters are bound to the placeholders of the script, e.g. `&notes(text = $text)`
+    // with `doc! { "text": "..." }`, or `&notes(text = ?)` with `vec![Bson::from("...")]`,
+    // so values never have to be spliced into code. The server keeps the scripts it
+    // has parsed, so a script run again with other parameters isn't parsed again.
+    pub fn execute_with(&mut self, code: &str, params: impl Into<Params>) -> io::Result<Bson> {
+        self.request(Request::Execute {
+            code: code.to_string(),
+            params: params.into(),
+        })
+    }
+
+    // Decodes the result into a Rust value, e.g. `Vec<String>`, or into serde types
+    // for rows.
+    pub fn query<T: DeserializeOwned>(
+        &mut self,
+        code: &str,
+        params: impl Into<Params>,
+    ) -> io::Result<T> {
+        let result = self.execute_with(code, params)?;
+        bson::from_bson(result).map_err(decode_error)
+    }
+
+    // Hands out the result of a script in batches of `batch` values, e.g. a query
+    // over a table too large to be sent at once. A query made of filters and fields is
+    // read from its table batch by batch, so the server never holds all of it either.
+    pub fn cursor(
+        &mut self,
+        code: &str,
+        params: impl Into<Params>,
+        batch: usize,
+    ) -> io::Result<Cursor<'_>> {
+        let batch = if batch == 0 {
+            DEFAULT_BATCH_SIZE
+        } else {
+            batch
+        };
+        Cursor::open(self, code, params.into(), batch)
+    }
+
+    pub fn begin(&mut self) -> io::Result<()> {
+        self.request(Request::Begin)?;
+        self.in_transaction = true;
+        Ok(())
+    }
+
+    pub fn commit(&mut self) -> io::Result<()> {
+        self.request(Request::Commit)?;
+        self.in_transaction = false;
+        Ok(())
+    }
+
+    pub fn rollback(&mut self) -> io::Result<()> {
+        self.request(Request::Rollback)?;
+        self.in_transaction = false;
+        Ok(())
+    }
+
+    // Commits what `work` writes when it succeeds and rolls it back when it fails.
+    pub fn transaction<T>(
+        &mut self,
+        work: impl FnOnce(&mut Self) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.begin()?;
+        match work(self) {
+            Ok(value) => self.commit().map(|_| value),
+            Err(error) => {
+                if self.in_transaction {
+                    let _ = self.rollback();
+                }
+                Err(error)
+            }
+        }
+    }
+
+    // Asks the server to shut down, which admins may do.
+    pub fn shutdown_server(&mut self) -> io::Result<()> {
+        self.request(Request::Shutdown).map(|_| ())
+    }
+
+    pub(super) fn request(&mut self, request: Request) -> io::Result<Bson> {
+        if self.broken {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "The connection has failed before",
+            ));
+        }
+        let response = self
+            .exchange(&request)
+            .inspect_err(|_| self.broken = true)?;
+        match response {
+            Response::Success(result) => Ok(result),
+            Response::Failure(report) => {
+                // The server rolls back the transaction a failing script runs in.
+                if matches!(request, Request::Execute { .. } | Request::Query { .. }) {
+                    self.in_transaction = false;
+                }
+                Err(report.into())
+            }
+        }
+    }
+
+    fn exchange(&mut self, request: &Request) -> io::Result<Response> {
+        protocol::write_frame(&mut self.stream, &request.to_document())?;
+        match protocol::read_frame(&mut self.stream)? {
+            Some(frame) => Response::from_document(&frame),
+            None => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "The server has closed the connection",
+            )),
+        }
+    }
+}
+
+fn open_stream(address: impl ToSocketAddrs, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let Some(timeout) = timeout else {
+        return TcpStream::connect(address);
+    };
+    let addresses: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+    let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, "No address to connect to");
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
+}
+
+// A result the server sent that doesn't fit the Rust type it's decoded into.
+pub fn decode_error(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", "Decoding Error".bright_red(), message),
+    )
+}
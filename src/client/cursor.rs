@@ -0,0 +1,130 @@
+use bson::{Bson, Document};
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+use std::io;
+
+use crate::scripting::params::Params;
+use crate::server::protocol::{protocol_error, Request};
+
+use super::connection::{decode_error, Connection};
+
+// The values of a result the server hands out in batches, fetched as they're iterated
+// over. The connection is held by the cursor until it's dropped; dropping a cursor
+// that isn't done cancels it on the server.
+pub struct Cursor<'a> {
+    connection: &'a mut Connection,
+    id: i64,
+    batch: usize,
+    rows: VecDeque<Bson>,
+    done: bool,
+}
+
+impl<'a> Cursor<'a> {
+    pub(super) fn open(
+        connection: &'a mut Connection,
+        code: &str,
+        params: Params,
+        batch: usize,
+    ) -> io::Result<Self> {
+        let result = connection.request(Request::Query {
+            code: code.to_string(),
+            params,
+            batch,
+        })?;
+        let mut cursor = Cursor {
+            connection,
+            id: 0,
+            batch,
+            rows: VecDeque::new(),
+            done: true,
+        };
+        cursor.receive(result)?;
+        Ok(cursor)
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    // Whether the server has handed out every value; some may still be waiting to be
+    // iterated over.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    // The values fetched but not iterated over yet, followed by the next batch when
+    // there are none. Returns `None` once every value has been handed out.
+    pub fn next_batch(&mut self) -> io::Result<Option<Vec<Bson>>> {
+        if self.rows.is_empty() && !self.done {
+            let result = self.connection.request(Request::Fetch {
+                cursor: self.id,
+                batch: self.batch,
+            });
+            // The server closes a cursor that fails, unless it's only kept waiting.
+            let result =
+                result.inspect_err(|err| self.done = err.kind() != io::ErrorKind::ResourceBusy)?;
+            self.receive(result)?;
+        }
+        if self.rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(self.rows.drain(..).collect()))
+    }
+
+    // Closes the cursor before it's done, dropping the values it hasn't handed out.
+    pub fn cancel(mut self) -> io::Result<()> {
+        self.rows.clear();
+        if self.done {
+            return Ok(());
+        }
+        self.done = true;
+        self.connection
+            .request(Request::Cancel { cursor: self.id })
+            .map(|_| ())
+    }
+
+    // Decodes each value into a Rust value, like `Connection::query`.
+    pub fn decode<T: DeserializeOwned>(self) -> impl Iterator<Item = io::Result<T>> + 'a {
+        self.map(|row| row.and_then(|row| bson::from_bson(row).map_err(decode_error)))
+    }
+
+    fn receive(&mut self, result: Bson) -> io::Result<()> {
+        let Bson::Document(result) = result else {
+            return Err(protocol_error("A batch of a cursor is a document"));
+        };
+        self.id = result.get_i64("cursor").unwrap_or(self.id);
+        self.done = result.get_bool("done").unwrap_or(true);
+        self.rows.extend(rows(result)?);
+        Ok(())
+    }
+}
+
+fn rows(mut result: Document) -> io::Result<Vec<Bson>> {
+    match result.remove("rows") {
+        Some(Bson::Array(rows)) => Ok(rows),
+        _ => Err(protocol_error("A batch of a cursor has no 'rows' array")),
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = io::Result<Bson>;
+
+    fn next(&mut self) -> Option<io::Result<Bson>> {
+        if self.rows.is_empty() {
+            match self.next_batch() {
+                Ok(Some(rows)) => self.rows.extend(rows),
+                Ok(None) => return None,
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        self.rows.pop_front().map(Ok)
+    }
+}
+
+impl Drop for Cursor<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.connection.request(Request::Cancel { cursor: self.id });
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod connection;
+pub mod cursor;
+pub mod pool;
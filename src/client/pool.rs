@@ -0,0 +1,180 @@
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::server::protocol::ErrorReport;
+
+use super::connection::Connection;
+
+// Connections to one server, logged in as the same user and shared by threads.
+// At most `size` connections are open at a time; asking for one while all of them
+// are in use waits for one to be given back, for the timeout at most. The timeout
+// also bounds connecting and waiting for responses.
+#[derive(Clone)]
+pub struct ClientPool {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    addresses: Vec<SocketAddr>,
+    user: String,
+    password: String,
+    size: usize,
+    timeout: Duration,
+    state: Mutex<State>,
+    given_back: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    idle: Vec<Connection>,
+    open: usize,
+}
+
+impl ClientPool {
+    pub fn new(
+        address: impl ToSocketAddrs,
+        user: &str,
+        password: &str,
+        size: usize,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        Ok(ClientPool {
+            shared: Arc::new(Shared {
+                addresses: address.to_socket_addrs()?.collect(),
+                user: user.to_string(),
+                password: password.to_string(),
+                size: size.max(1),
+                timeout,
+                state: Mutex::default(),
+                given_back: Condvar::new(),
+            }),
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.shared
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Connections the pool holds, in use or not.
+    pub fn open(&self) -> usize {
+        self.state().open
+    }
+
+    pub fn idle(&self) -> usize {
+        self.state().idle.len()
+    }
+
+    // An idle connection the server has closed in the meantime is replaced.
+    pub fn get(&self) -> io::Result<PooledConnection> {
+        let deadline = Instant::now() + self.shared.timeout;
+        let mut state = self.state();
+        loop {
+            while let Some(connection) = state.idle.pop() {
+                if connection.is_open() {
+                    return Ok(self.pooled(connection));
+                }
+                state.open -= 1;
+            }
+            if state.open < self.shared.size {
+                state.open += 1;
+                drop(state);
+                return match self.connect() {
+                    Ok(connection) => Ok(self.pooled(connection)),
+                    Err(error) => {
+                        self.forget();
+                        Err(error)
+                    }
+                };
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(ErrorReport::new(
+                    "Unavailable",
+                    format!(
+                        "All {} connections of the pool are in use",
+                        self.shared.size
+                    ),
+                )
+                .into());
+            }
+            state = self
+                .shared
+                .given_back
+                .wait_timeout(state, left)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        Connection::connect_timeout(
+            self.shared.addresses.as_slice(),
+            &self.shared.user,
+            &self.shared.password,
+            Some(self.shared.timeout),
+        )
+    }
+
+    fn pooled(&self, connection: Connection) -> PooledConnection {
+        PooledConnection {
+            connection: Some(connection),
+            pool: self.clone(),
+        }
+    }
+
+    // A connection given back goes idle, unless it can't be used anymore.
+    fn give_back(&self, mut connection: Connection) {
+        if connection.in_transaction() && connection.rollback().is_err() {
+            return self.forget();
+        }
+        if !connection.is_open() {
+            return self.forget();
+        }
+        self.state().idle.push(connection);
+        self.shared.given_back.notify_one();
+    }
+
+    fn forget(&self) {
+        self.state().open -= 1;
+        self.shared.given_back.notify_one();
+    }
+}
+
+// A connection lent by the pool, which gets it back when it's dropped. A transaction
+// left open is rolled back first.
+pub struct PooledConnection {
+    connection: Option<Connection>,
+    pool: ClientPool,
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection
+            .as_ref()
+            .expect("the connection is held until it's dropped")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.connection
+            .as_mut()
+            .expect("the connection is held until it's dropped")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool.give_back(connection);
+        }
+    }
+}
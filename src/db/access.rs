@@ -0,0 +1,319 @@
+use bson::Document;
+use colored::Colorize;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Result};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use strum_macros::{Display, EnumString};
+use subtle::ConstantTimeEq;
+
+use super::storage::storage_error;
+
+// Members of the `admin` role may do anything, including managing users and roles.
+pub const ADMIN_ROLE: &str = "admin";
+// The user a datablaze without users gets, and the one clients log in as by default.
+pub const ADMIN_USER: &str = "admin";
+
+// How many rounds of PBKDF2 passwords are hashed with.
+pub const HASH_ITERATIONS: u32 = 10_000;
+
+#[derive(Debug, Display, EnumString, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum Permission {
+    Read,
+    Write,
+    Execute,
+}
+
+// What a permission is granted on. Reading and writing a table cover its rows,
+// executing a function calls it, executing a package imports it and writing it
+// changes what it declares.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum Resource {
+    Package(String),
+    Table(String),
+    Function(String),
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Package(name) => write!(f, "package {}", name),
+            Resource::Table(name) => write!(f, "table {}", name),
+            Resource::Function(name) => write!(f, "function {}", name),
+        }
+    }
+}
+
+// `grant read, write on table sales to analysts`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Grant {
+    pub permission: Permission,
+    pub resource: Resource,
+}
+
+impl fmt::Display for Grant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on {}", self.permission, self.resource)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Role {
+    pub grants: Vec<Grant>,
+}
+
+// Passwords are never kept, only the SCRAM keys derived from them: a client proves
+// it knows the password with the client key, whose hash is the stored key, and the
+// server proves it knew the password with the server key.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub salt: String,
+    pub iterations: u32,
+    pub stored_key: String,
+    pub server_key: String,
+    pub roles: Vec<String>,
+}
+
+impl User {
+    pub fn new(name: &str, password: &str, roles: Vec<String>) -> Self {
+        let mut salt = [0; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let salt = hex::encode(salt);
+        let keys = Keys::derive(password, &salt, HASH_ITERATIONS);
+        User {
+            name: name.to_string(),
+            salt,
+            iterations: HASH_ITERATIONS,
+            stored_key: hex::encode(keys.stored_key),
+            server_key: hex::encode(keys.server_key),
+            roles,
+        }
+    }
+
+    // Compared in constant time, so the time it takes tells nothing about the key.
+    pub fn is_stored_key(&self, key: &[u8]) -> bool {
+        hex::decode(&self.stored_key).is_ok_and(|stored_key| bool::from(stored_key.ct_eq(key)))
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.roles.iter().any(|role| role == ADMIN_ROLE)
+    }
+}
+
+// The keys of SCRAM-SHA-256 (RFC 5802) derived from a password.
+pub struct Keys {
+    pub client_key: [u8; 32],
+    pub stored_key: [u8; 32],
+    pub server_key: [u8; 32],
+}
+
+impl Keys {
+    pub fn derive(password: &str, salt: &str, iterations: u32) -> Self {
+        let mut salted_password = [0; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(
+            password.as_bytes(),
+            salt.as_bytes(),
+            iterations,
+            &mut salted_password,
+        );
+        let client_key = hmac(&salted_password, b"Client Key");
+        Keys {
+            client_key,
+            stored_key: Sha256::digest(client_key).into(),
+            server_key: hmac(&salted_password, b"Server Key"),
+        }
+    }
+}
+
+pub fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+// The users and roles of a datablaze, stored in `model/access.bson`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Access {
+    pub users: BTreeMap<String, User>,
+    pub roles: BTreeMap<String, Role>,
+}
+
+impl Access {
+    pub fn read(path: &Path) -> Result<Self> {
+        let document =
+            Document::from_reader(BufReader::new(File::open(path)?)).map_err(storage_error)?;
+        bson::from_document(document).map_err(storage_error)
+    }
+
+    // Written next to the old file and put in its place, like the catalog.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let document = bson::to_document(self).map_err(storage_error)?;
+        let temporary_path = path.with_extension("tmp");
+        document
+            .to_writer(BufWriter::new(File::create(&temporary_path)?))
+            .map_err(storage_error)?;
+        fs::rename(temporary_path, path)
+    }
+
+    // Declaring an existing user replaces its password and roles.
+    pub fn set_user(&mut self, user: User) -> Result<()> {
+        if let Some(role) = user
+            .roles
+            .iter()
+            .find(|role| *role != ADMIN_ROLE && !self.roles.contains_key(*role))
+        {
+            return Err(access_error(format!("Role '{}' isn't declared", role)));
+        }
+        self.users.insert(user.name.clone(), user);
+        Ok(())
+    }
+
+    pub fn define_role(&mut self, name: &str) -> Result<()> {
+        if name == ADMIN_ROLE {
+            return Err(access_error(format!("Role '{}' is built in", name)));
+        }
+        self.roles.entry(name.to_string()).or_default();
+        Ok(())
+    }
+
+    pub fn grant(&mut self, role: &str, grant: Grant) -> Result<()> {
+        let role = self.get_role_mut(role)?;
+        if !role.grants.contains(&grant) {
+            role.grants.push(grant);
+        }
+        Ok(())
+    }
+
+    pub fn revoke(&mut self, role: &str, grant: &Grant) -> Result<()> {
+        self.get_role_mut(role)?
+            .grants
+            .retain(|granted| granted != grant);
+        Ok(())
+    }
+
+    fn get_role_mut(&mut self, name: &str) -> Result<&mut Role> {
+        self.roles
+            .get_mut(name)
+            .ok_or_else(|| access_error(format!("Role '{}' isn't declared", name)))
+    }
+
+    pub fn allows(&self, user: &str, permission: Permission, resource: &Resource) -> bool {
+        let Some(user) = self.users.get(user) else {
+            return false;
+        };
+        let wanted = Grant {
+            permission,
+            resource: resource.clone(),
+        };
+        user.is_admin()
+            || user
+                .roles
+                .iter()
+                .filter_map(|role| self.roles.get(role))
+                .any(|role| role.grants.contains(&wanted))
+    }
+
+    pub fn is_admin(&self, user: &str) -> bool {
+        self.users.get(user).is_some_and(User::is_admin)
+    }
+}
+
+// Failed logins after which a user is locked out, first for a second, then for twice
+// as long after each further failure, up to the longest lockout.
+pub const ALLOWED_FAILURES: u32 = 5;
+pub const LOCKOUT: Duration = Duration::from_secs(1);
+pub const LONGEST_LOCKOUT: Duration = Duration::from_secs(300);
+// How long a challenge handed out over HTTP may be answered.
+pub const CHALLENGE_LIFETIME: Duration = Duration::from_secs(60);
+
+// What logging in keeps while the server runs: the failed attempts of each user,
+// and the challenges handed out but not answered yet. A user's failures are
+// forgotten once it hasn't failed for as long as the longest lockout.
+pub struct Logins {
+    failures: HashMap<String, Failures>,
+    pending: HashMap<String, (String, Instant)>,
+    // Salts of users that don't exist are made up from it, so that a challenge
+    // doesn't tell whether a user exists.
+    secret: [u8; 16],
+}
+
+struct Failures {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Default for Logins {
+    fn default() -> Self {
+        let mut secret = [0; 16];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Logins {
+            failures: HashMap::new(),
+            pending: HashMap::new(),
+            secret,
+        }
+    }
+}
+
+impl Logins {
+    // How long the user has to wait before trying again, if it's locked out.
+    pub fn lockout(&self, user: &str) -> Option<Duration> {
+        let locked_until = self.failures.get(user)?.locked_until?;
+        locked_until.checked_duration_since(Instant::now())
+    }
+
+    pub fn fail(&mut self, user: &str) {
+        let now = Instant::now();
+        self.failures
+            .retain(|_, failures| now.duration_since(failures.last) < LONGEST_LOCKOUT);
+        let failures = self.failures.entry(user.to_string()).or_insert(Failures {
+            count: 0,
+            last: now,
+            locked_until: None,
+        });
+        failures.count += 1;
+        failures.last = now;
+        if failures.count >= ALLOWED_FAILURES {
+            let doublings = (failures.count - ALLOWED_FAILURES).min(16);
+            let lockout = (LOCKOUT * 2u32.pow(doublings)).min(LONGEST_LOCKOUT);
+            failures.locked_until = Some(now + lockout);
+        }
+    }
+
+    pub fn succeed(&mut self, user: &str) {
+        self.failures.remove(user);
+    }
+
+    pub fn made_up_salt(&self, user: &str) -> String {
+        hex::encode(&hmac(&self.secret, user.as_bytes())[..16])
+    }
+
+    pub fn hold(&mut self, nonce: &str, user: &str) {
+        let now = Instant::now();
+        self.pending
+            .retain(|_, (_, issued)| now.duration_since(*issued) < CHALLENGE_LIFETIME);
+        self.pending
+            .insert(nonce.to_string(), (user.to_string(), now));
+    }
+
+    // A challenge can be answered once; returns the user it was handed out for.
+    pub fn take(&mut self, nonce: &str) -> Option<String> {
+        let (user, issued) = self.pending.remove(nonce)?;
+        (issued.elapsed() < CHALLENGE_LIFETIME).then_some(user)
+    }
+}
+
+pub fn access_error(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{}: {}", "Access Error".bright_red(), message),
+    )
+}
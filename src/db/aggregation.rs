@@ -0,0 +1,187 @@
+use bson::{Bson, Document};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use strum_macros::{Display, EnumString};
+
+use super::index::IndexKey;
+use super::values;
+
+#[derive(Debug, Display, EnumString, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+// The running state of an aggregate, so values are folded in one at a time
+// instead of being collected first. Nulls are skipped, so missing values don't count.
+#[derive(Debug, Clone)]
+pub enum Accumulator {
+    Count(i64),
+    // Integers add up to an integer until the sum overflows or meets a float.
+    Sum {
+        integer: Option<i64>,
+        float: f64,
+    },
+    Avg {
+        total: f64,
+        count: i64,
+    },
+    Extremum {
+        wanted: Ordering,
+        best: Option<Bson>,
+    },
+}
+
+impl Accumulator {
+    pub fn new(aggregate: Aggregate) -> Self {
+        match aggregate {
+            Aggregate::Count => Accumulator::Count(0),
+            Aggregate::Sum => Accumulator::Sum {
+                integer: Some(0),
+                float: 0.0,
+            },
+            Aggregate::Avg => Accumulator::Avg {
+                total: 0.0,
+                count: 0,
+            },
+            Aggregate::Min => Accumulator::Extremum {
+                wanted: Ordering::Less,
+                best: None,
+            },
+            Aggregate::Max => Accumulator::Extremum {
+                wanted: Ordering::Greater,
+                best: None,
+            },
+        }
+    }
+
+    // Returns false for a value the aggregate can't take in.
+    pub fn add(&mut self, value: &Bson) -> bool {
+        if *value == Bson::Null {
+            return true;
+        }
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum { integer, float } => {
+                let Some(number) = values::as_f64(value) else {
+                    return false;
+                };
+                *float += number;
+                *integer = match value {
+                    Bson::Int32(addend) => integer.and_then(|sum| sum.checked_add(*addend as i64)),
+                    Bson::Int64(addend) => integer.and_then(|sum| sum.checked_add(*addend)),
+                    _ => None,
+                };
+            }
+            Accumulator::Avg { total, count } => {
+                let Some(number) = values::as_f64(value) else {
+                    return false;
+                };
+                *total += number;
+                *count += 1;
+            }
+            Accumulator::Extremum { wanted, best } => match best {
+                None => *best = Some(value.clone()),
+                Some(current) => match values::compare(value, current) {
+                    Some(ordering) if ordering == *wanted => *best = Some(value.clone()),
+                    Some(_) => {}
+                    None => return false,
+                },
+            },
+        }
+        true
+    }
+
+    pub fn finish(self) -> Bson {
+        match self {
+            Accumulator::Count(count) => Bson::Int64(count),
+            Accumulator::Sum {
+                integer: Some(sum), ..
+            } => Bson::Int64(sum),
+            Accumulator::Sum { float, .. } => Bson::Double(float),
+            Accumulator::Avg { count: 0, .. } => Bson::Null,
+            Accumulator::Avg { total, count } => Bson::Double(total / count as f64),
+            Accumulator::Extremum { best, .. } => best.unwrap_or(Bson::Null),
+        }
+    }
+}
+
+// `total: sum(amount)`, or `rows: count()` to count the rows themselves.
+#[derive(Debug, Clone)]
+pub struct Aggregation {
+    pub name: String,
+    pub aggregate: Aggregate,
+    pub column: Option<String>,
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let column = self.column.as_deref().unwrap_or_default();
+        write!(f, "{}: {}({})", self.name, self.aggregate, column)
+    }
+}
+
+// `.group(seller, year){total: sum(amount), deals: count()}` turns the selected rows
+// into one document per distinct combination of the key columns, holding the keys
+// and the aggregated values.
+#[derive(Debug, Clone)]
+pub struct Grouping {
+    pub keys: Vec<String>,
+    pub aggregations: Vec<Aggregation>,
+}
+
+impl Grouping {
+    pub fn new(keys: Vec<String>, aggregations: Vec<Aggregation>) -> Self {
+        Grouping { keys, aggregations }
+    }
+
+    // Folds the rows into the groups as they come, keeping only an accumulator per
+    // aggregation of each group. The groups are ordered by their keys.
+    // A value an aggregate can't take in is reported by the error.
+    pub fn group<'a>(
+        &self,
+        rows: impl Iterator<Item = &'a Document>,
+    ) -> Result<Vec<Document>, String> {
+        let mut groups: BTreeMap<Vec<IndexKey>, Vec<Accumulator>> = BTreeMap::new();
+        for row in rows {
+            let key = self
+                .keys
+                .iter()
+                .map(|column| IndexKey(row.get(column).cloned().unwrap_or(Bson::Null)))
+                .collect();
+            let accumulators = groups.entry(key).or_insert_with(|| {
+                self.aggregations
+                    .iter()
+                    .map(|aggregation| Accumulator::new(aggregation.aggregate))
+                    .collect()
+            });
+            for (aggregation, accumulator) in self.aggregations.iter().zip(accumulators) {
+                let value = match &aggregation.column {
+                    Some(column) => row.get(column).unwrap_or(&Bson::Null),
+                    None => &Bson::Boolean(true),
+                };
+                if !accumulator.add(value) {
+                    return Err(format!("'{}' can't be applied to {}", aggregation, value));
+                }
+            }
+        }
+        Ok(groups
+            .into_iter()
+            .map(|(key, accumulators)| {
+                let mut group = Document::new();
+                for (column, value) in self.keys.iter().zip(key) {
+                    group.insert(column, value.0);
+                }
+                for (aggregation, accumulator) in self.aggregations.iter().zip(accumulators) {
+                    group.insert(&aggregation.name, accumulator.finish());
+                }
+                group
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,85 @@
+use bson::Document;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Result};
+use std::path::Path;
+
+use super::events::Event;
+use super::schema::{EnumDefinition, FunctionDefinition, TableSchema};
+use super::storage::storage_error;
+
+// The version of the catalog format; catalogs written by newer versions aren't read.
+pub const CATALOG_VERSION: u32 = 1;
+
+// Everything declared in a datablaze, stored in `model/catalog.bson`, so that
+// the declarations don't have to be parsed again when the server starts.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Catalog {
+    pub version: u32,
+    // Increased with every change of the declarations.
+    pub revision: u64,
+    pub tables: Vec<TableSchema>,
+    pub enums: BTreeMap<String, EnumDefinition>,
+    pub functions: BTreeMap<String, FunctionDefinition>,
+    pub events: BTreeMap<String, Event>,
+}
+
+impl Catalog {
+    pub fn read(path: &Path) -> Result<Self> {
+        let document =
+            Document::from_reader(BufReader::new(File::open(path)?)).map_err(storage_error)?;
+        let version = document.get_i64("version").unwrap_or_default();
+        if version > CATALOG_VERSION as i64 {
+            return Err(storage_error(format!(
+                "The catalog of version {} is newer than the supported version {}",
+                version, CATALOG_VERSION
+            )));
+        }
+        bson::from_document(document).map_err(storage_error)
+    }
+
+    // The catalog is written next to the old one and then put in its place,
+    // so that an interrupted write doesn't leave a broken catalog behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let document = bson::to_document(self).map_err(storage_error)?;
+        let temporary_path = path.with_extension("tmp");
+        document
+            .to_writer(BufWriter::new(File::create(&temporary_path)?))
+            .map_err(storage_error)?;
+        fs::rename(temporary_path, path)
+    }
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Catalog {
+            version: CATALOG_VERSION,
+            revision: 0,
+            tables: vec![],
+            enums: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            events: BTreeMap::new(),
+        }
+    }
+}
+
+impl fmt::Display for Catalog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "catalog v{} revision {}", self.version, self.revision)?;
+        for table in &self.tables {
+            write!(f, "\n{}", table)?;
+        }
+        for definition in self.enums.values() {
+            write!(f, "\n{}", definition)?;
+        }
+        for function in self.functions.values() {
+            write!(f, "\n{}", function)?;
+        }
+        for event in self.events.values() {
+            write!(f, "\n{}", event)?;
+        }
+        Ok(())
+    }
+}
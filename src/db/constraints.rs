@@ -0,0 +1,155 @@
+use bson::{Bson, Document};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use super::geo;
+use super::query::Comparison;
+use super::schema::{ColumnType, KeyStrategy, TableSchema};
+use super::values;
+
+// A bound declared after a column type, e.g. `<=30` in `str <=30`.
+// Strings are bounded by their length, numbers by their value, and the items of
+// an array column one by one, e.g. every score of `int[] >0`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Constraint {
+    pub comparison: Comparison,
+    pub bound: f64,
+}
+
+impl Constraint {
+    pub fn new(comparison: Comparison, bound: f64) -> Self {
+        Constraint { comparison, bound }
+    }
+
+    fn is_satisfied_by(&self, value: &Bson) -> bool {
+        let measure = match value {
+            Bson::String(string) => string.chars().count() as f64,
+            value => match values::as_f64(value) {
+                Some(number) => number,
+                None => return true,
+            },
+        };
+        self.comparison
+            .is_satisfied(measure.partial_cmp(&self.bound))
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.comparison, self.bound)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstraintViolation {
+    pub table: String,
+    pub column: String,
+    pub rule: String,
+}
+
+impl ConstraintViolation {
+    pub fn new(table: &str, column: &str, rule: impl ToString) -> Self {
+        ConstraintViolation {
+            table: table.to_string(),
+            column: column.to_string(),
+            rule: rule.to_string(),
+        }
+    }
+
+    // Extracts a violation from an error returned by the storage.
+    pub fn from_error(error: &io::Error) -> Option<&Self> {
+        error.get_ref()?.downcast_ref::<Self>()
+    }
+}
+
+impl fmt::Display for ConstraintViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: '{}.{}' violates the '{}' rule",
+            "Constraint Error".bright_red(),
+            self.table,
+            self.column,
+            self.rule
+        )
+    }
+}
+
+impl Error for ConstraintViolation {}
+
+impl From<ConstraintViolation> for io::Error {
+    fn from(violation: ConstraintViolation) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, violation)
+    }
+}
+
+pub fn matches_type(datatype: &str, value: &Bson) -> bool {
+    match datatype {
+        "str" => matches!(value, Bson::String(_)),
+        "int" => matches!(value, Bson::Int32(_) | Bson::Int64(_)),
+        "float" => values::as_f64(value).is_some(),
+        "bool" => matches!(value, Bson::Boolean(_)),
+        "datetime" => matches!(value, Bson::DateTime(_)),
+        "geo" => geo::is_geometry(value),
+        // Other types are checked by the features introducing them.
+        _ => true,
+    }
+}
+
+// Checks a row about to be written against the columns declared by a schema.
+// Tables declared with no columns accept any rows.
+pub fn validate_row(schema: &TableSchema, row: &Document) -> Result<(), ConstraintViolation> {
+    let violation = |column: &str, rule: &dyn fmt::Display| {
+        Err(ConstraintViolation::new(&schema.name, column, rule))
+    };
+    if let KeyStrategy::Natural(datatype) = &schema.key {
+        match row.get("id") {
+            None | Some(Bson::Null) => return violation("id", &"required"),
+            Some(id) if !matches_type(datatype, id) => {
+                return violation("id", &format!("type {}", datatype))
+            }
+            _ => {}
+        }
+    }
+    if schema.columns.is_empty() {
+        return Ok(());
+    }
+    for key in row.keys() {
+        if key != "id" && schema.get_column(key).is_none() {
+            return violation(key, &"declared");
+        }
+    }
+    for column in &schema.columns {
+        let value = row.get(&column.name).unwrap_or(&Bson::Null);
+        if column.required && !row.contains_key(&column.name) {
+            return violation(&column.name, &"required");
+        }
+        if *value == Bson::Null {
+            if column.nullable {
+                continue;
+            }
+            return violation(&column.name, &"not null");
+        }
+        let items = match (value, column.is_array) {
+            (Bson::Array(items), true) => items.as_slice(),
+            (_, true) => return violation(&column.name, &"array"),
+            (value, false) => std::slice::from_ref(value),
+        };
+        if let ColumnType::Scalar(datatype) = &column.column_type {
+            if !items.iter().all(|item| matches_type(datatype, item)) {
+                return violation(&column.name, &format!("type {}", datatype));
+            }
+        }
+        if let Some(constraint) = column
+            .constraints
+            .iter()
+            .find(|constraint| !items.iter().all(|item| constraint.is_satisfied_by(item)))
+        {
+            return violation(&column.name, constraint);
+        }
+    }
+    Ok(())
+}
@@ -21,7 +21,7 @@ pub fn create_db_structure(path_to_db: &str) -> Result<()> {
 }
 
 fn create_db_folders(db_path_buf: &Path) -> Result<()> {
-    for folder in ["data", "model"] {
+    for folder in ["data", "model", "packages"] {
         let mut cloned_db_path_buf = db_path_buf.to_path_buf();
         cloned_db_path_buf.push(folder);
         fs::create_dir_all(cloned_db_path_buf)?;
@@ -32,7 +32,7 @@ fn create_db_folders(db_path_buf: &Path) -> Result<()> {
 fn create_manage_file(path_to_db_buf: &Path) -> Result<bool> {
     let mut managing_file_path_buf = path_to_db_buf.to_path_buf();
     let manage_file_content = br#"manage (
-    tmax_connections = 3,
+    max_connections = 3,
     work_dir = "/",
     backups_dir = "backups/"
 );
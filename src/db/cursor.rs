@@ -0,0 +1,114 @@
+use bson::Bson;
+use std::collections::VecDeque;
+use std::io::Result;
+
+use super::index::IndexKey;
+use super::query::{Query, Step};
+use super::storage::Storage;
+
+// How far a cursor has scanned the table of its query: where the last row it's seen
+// stands in the order of the scan, as told by `Table::scan_position`.
+#[derive(Debug, Default)]
+pub struct Resume {
+    pub last_row: Option<(IndexKey, Bson)>,
+    pub is_done: bool,
+}
+
+// A result handed out in batches. A query whose steps are filters and fields,
+// optionally followed by `.offset(...)` and `.limit(...)`, is run lazily: each batch
+// scans the table on from where the previous one stopped, so rows written in between
+// are seen when they come after it. Other results are computed whole when the cursor
+// is opened.
+pub struct Cursor {
+    query: Option<(Query, Resume)>,
+    buffered: VecDeque<Bson>,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl Cursor {
+    pub fn for_query(query: &Query) -> Option<Self> {
+        let mut query = query.clone();
+        // Only an offset coming before the limit can be applied to the values as they
+        // come, `.limit(...).offset(...)` is computed whole.
+        let mut limit = None;
+        if let Some(Step::Limit(last)) = query.steps.last() {
+            limit = Some(*last);
+            query.steps.pop();
+        }
+        let mut offset = 0;
+        if let Some(Step::Offset(last)) = query.steps.last() {
+            offset = *last;
+            query.steps.pop();
+        }
+        if !query
+            .steps
+            .iter()
+            .all(|step| matches!(step, Step::Filter(_) | Step::Field(_)))
+        {
+            return None;
+        }
+        Some(Cursor {
+            query: Some((query, Resume::default())),
+            buffered: VecDeque::new(),
+            offset,
+            limit,
+        })
+    }
+
+    pub fn for_values(values: Vec<Bson>) -> Self {
+        Cursor {
+            query: None,
+            buffered: values.into(),
+            offset: 0,
+            limit: None,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.limit == Some(0)
+            || self.buffered.is_empty()
+                && self.query.as_ref().is_none_or(|(_, resume)| resume.is_done)
+    }
+
+    // The next `size` values at most. One more is looked for ahead, so that the batch
+    // holding the last value already tells the cursor is done.
+    pub fn fetch(&mut self, storage: &Storage, size: usize) -> Result<Vec<Bson>> {
+        let mut values = vec![];
+        while values.len() < size && self.limit != Some(0) {
+            let Some(value) = self.buffered.pop_front() else {
+                if !self.scan(storage, size - values.len())? {
+                    break;
+                }
+                continue;
+            };
+            if self.offset > 0 {
+                self.offset -= 1;
+                continue;
+            }
+            if let Some(limit) = &mut self.limit {
+                *limit -= 1;
+            }
+            values.push(value);
+        }
+        if self.buffered.is_empty() && self.limit != Some(0) {
+            self.scan(storage, 1)?;
+        }
+        Ok(values)
+    }
+
+    // Buffers the next values of the query, skipped ones included. Returns false when
+    // the table has been scanned to the end.
+    fn scan(&mut self, storage: &Storage, wanted: usize) -> Result<bool> {
+        let Some((query, resume)) = &mut self.query else {
+            return Ok(false);
+        };
+        if resume.is_done {
+            return Ok(false);
+        }
+        let wanted = wanted.saturating_add(self.offset);
+        self.buffered
+            .extend(storage.select_batch(query, resume, wanted)?);
+        Ok(true)
+    }
+}
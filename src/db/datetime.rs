@@ -0,0 +1,85 @@
+use bson::{Bson, DateTime, Document};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+
+use super::schema::{ColumnType, TableSchema};
+
+// Durations are integers of milliseconds, so that they're added to and subtracted from
+// datetimes like numbers, and the difference of two datetimes is a duration as well.
+pub const MILLISECONDS_IN_SECOND: i64 = 1000;
+pub const MILLISECONDS_IN_MINUTE: i64 = 60 * MILLISECONDS_IN_SECOND;
+pub const MILLISECONDS_IN_HOUR: i64 = 60 * MILLISECONDS_IN_MINUTE;
+pub const MILLISECONDS_IN_DAY: i64 = 24 * MILLISECONDS_IN_HOUR;
+
+// Accepts ISO-8601 datetimes with or without an offset, and dates alone.
+// Datetimes with no offset are considered to be in UTC.
+pub fn parse(text: &str) -> Option<DateTime> {
+    let text = text.trim();
+    if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(text) {
+        return Some(DateTime::from_millis(datetime.timestamp_millis()));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(DateTime::from_millis(datetime.and_utc().timestamp_millis()));
+        }
+    }
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+    Some(DateTime::from_millis(
+        date.and_hms_opt(0, 0, 0)?.and_utc().timestamp_millis(),
+    ))
+}
+
+// The `"now"` string stands for the time it's read at, e.g. the time a row is written at.
+pub fn from_text(text: &str) -> Option<DateTime> {
+    match text {
+        "now" => Some(DateTime::now()),
+        text => parse(text),
+    }
+}
+
+pub fn coerce(value: &Bson) -> Option<Bson> {
+    match value {
+        Bson::DateTime(_) | Bson::Null => Some(value.clone()),
+        Bson::String(text) => from_text(text).map(Bson::DateTime),
+        _ => None,
+    }
+}
+
+// Turns the strings written to `datetime` columns into datetimes. Values that aren't
+// datetimes are left as they are for the validation to report them.
+pub fn coerce_row(schema: &TableSchema, row: &mut Document) {
+    for column in &schema.columns {
+        if column.column_type != ColumnType::Scalar("datetime".to_string()) {
+            continue;
+        }
+        match row.get_mut(&column.name) {
+            Some(Bson::Array(items)) => {
+                for item in items.iter_mut() {
+                    if let Some(datetime) = coerce(item) {
+                        *item = datetime;
+                    }
+                }
+            }
+            Some(value) => {
+                if let Some(datetime) = coerce(value) {
+                    *value = datetime;
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+// Formats a datetime in UTC with `strftime` specifiers, e.g. `%Y-%m-%d`.
+pub fn format(datetime: DateTime, pattern: &str) -> Option<String> {
+    let datetime = chrono::DateTime::<Utc>::from_timestamp_millis(datetime.timestamp_millis())?;
+    let mut formatted = String::new();
+    std::fmt::write(&mut formatted, format_args!("{}", datetime.format(pattern))).ok()?;
+    Some(formatted)
+}
+
+pub fn add(datetime: DateTime, milliseconds: i64) -> Option<DateTime> {
+    datetime
+        .timestamp_millis()
+        .checked_add(milliseconds)
+        .map(DateTime::from_millis)
+}
@@ -0,0 +1,67 @@
+use bson::Document;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use strum_macros::{Display, EnumString};
+
+#[derive(Debug, Display, EnumString, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum Operation {
+    Insert,
+    Change,
+    Delete,
+}
+
+// An `event` declaration: code run whenever rows of a table, or a column of them, are written.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Event {
+    pub name: String,
+    pub table: String,
+    pub column: Option<String>,
+    pub operation: Operation,
+    // The source code of the body; the written row is available in it as `self`.
+    pub body: String,
+    // The package that declared the event, if any; only it may declare the event again.
+    #[serde(default)]
+    pub package: Option<String>,
+}
+
+impl Event {
+    // A column event is triggered only by writes that touch the column.
+    pub fn is_triggered_by(
+        &self,
+        table: &str,
+        operation: Operation,
+        old: Option<&Document>,
+        new: Option<&Document>,
+    ) -> bool {
+        if self.table != table || self.operation != operation {
+            return false;
+        }
+        let Some(column) = &self.column else {
+            return operation != Operation::Change || old != new;
+        };
+        let value_of = |row: Option<&Document>| row.and_then(|row| row.get(column)).cloned();
+        match operation {
+            Operation::Insert => value_of(new).is_some(),
+            Operation::Change => value_of(old) != value_of(new),
+            Operation::Delete => value_of(old).is_some(),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "event {} (&{}", self.name, self.table)?;
+        if let Some(column) = &self.column {
+            write!(f, ".{}", column)?;
+        }
+        write!(f, ", \"{}\")", self.operation)
+    }
+}
+
+// An event triggered by a write, waiting to be run by the executor.
+pub struct Firing {
+    pub event: String,
+    pub body: String,
+    pub row: Document,
+}
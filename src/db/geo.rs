@@ -0,0 +1,210 @@
+use bson::{doc, Bson};
+
+// Geometries are stored as GeoJSON: `{"type": "Point", "coordinates": [lon, lat]}`
+// and `{"type": "Polygon", "coordinates": [[[lon, lat], ...]]}`. Only the outer ring
+// of a polygon is considered.
+pub fn point(longitude: f64, latitude: f64) -> Bson {
+    Bson::Document(doc! {
+        "type": "Point",
+        "coordinates": [longitude, latitude],
+    })
+}
+
+pub fn polygon(ring: &[(f64, f64)]) -> Bson {
+    let mut coordinates: Vec<Bson> = ring
+        .iter()
+        .map(|(longitude, latitude)| Bson::Array(vec![(*longitude).into(), (*latitude).into()]))
+        .collect();
+    // GeoJSON rings end with their first position.
+    if ring.first() != ring.last() {
+        coordinates.push(coordinates[0].clone());
+    }
+    Bson::Document(doc! {
+        "type": "Polygon",
+        "coordinates": [Bson::Array(coordinates)],
+    })
+}
+
+// A polygon covering the area between two corners.
+pub fn bounding_box(min: (f64, f64), max: (f64, f64)) -> Bson {
+    polygon(&[min, (max.0, min.1), max, (min.0, max.1)])
+}
+
+fn as_position(value: &Bson) -> Option<(f64, f64)> {
+    let Bson::Array(position) = value else {
+        return None;
+    };
+    match &position[..] {
+        [longitude, latitude] => {
+            let longitude = super::values::as_f64(longitude)?;
+            let latitude = super::values::as_f64(latitude)?;
+            let is_valid =
+                (-180.0..=180.0).contains(&longitude) && (-90.0..=90.0).contains(&latitude);
+            is_valid.then_some((longitude, latitude))
+        }
+        _ => None,
+    }
+}
+
+pub fn as_point(value: &Bson) -> Option<(f64, f64)> {
+    let Bson::Document(geometry) = value else {
+        return None;
+    };
+    if geometry.get_str("type").ok()? != "Point" {
+        return None;
+    }
+    as_position(geometry.get("coordinates")?)
+}
+
+pub fn as_polygon(value: &Bson) -> Option<Vec<(f64, f64)>> {
+    let Bson::Document(geometry) = value else {
+        return None;
+    };
+    if geometry.get_str("type").ok()? != "Polygon" {
+        return None;
+    }
+    let rings = geometry.get_array("coordinates").ok()?;
+    let Some(Bson::Array(ring)) = rings.first() else {
+        return None;
+    };
+    let ring: Vec<(f64, f64)> = ring.iter().map(as_position).collect::<Option<_>>()?;
+    (ring.len() >= 4).then_some(ring)
+}
+
+pub fn is_geometry(value: &Bson) -> bool {
+    as_point(value).is_some() || as_polygon(value).is_some()
+}
+
+// The point a geometry is indexed and measured by: a point itself or the average
+// of the vertices of a polygon.
+pub fn center(value: &Bson) -> Option<(f64, f64)> {
+    if let Some(point) = as_point(value) {
+        return Some(point);
+    }
+    let ring = as_polygon(value)?;
+    let vertices = &ring[..ring.len() - 1];
+    let count = vertices.len() as f64;
+    Some((
+        vertices.iter().map(|vertex| vertex.0).sum::<f64>() / count,
+        vertices.iter().map(|vertex| vertex.1).sum::<f64>() / count,
+    ))
+}
+
+const EARTH_RADIUS_IN_METERS: f64 = 6_371_008.8;
+
+// The great-circle distance between the centers of two geometries in meters.
+pub fn distance(from: &Bson, to: &Bson) -> Option<f64> {
+    let ((from_longitude, from_latitude), (to_longitude, to_latitude)) =
+        (center(from)?, center(to)?);
+    let (from_latitude, to_latitude) = (from_latitude.to_radians(), to_latitude.to_radians());
+    let latitude_delta = to_latitude - from_latitude;
+    let longitude_delta = (to_longitude - from_longitude).to_radians();
+    let haversine = (latitude_delta / 2.0).sin().powi(2)
+        + from_latitude.cos() * to_latitude.cos() * (longitude_delta / 2.0).sin().powi(2);
+    Some(2.0 * EARTH_RADIUS_IN_METERS * haversine.sqrt().asin())
+}
+
+// Whether a geometry lies in a polygon: a point has to be inside of it,
+// while a polygon has to have all of its vertices inside.
+pub fn within(value: &Bson, area: &Bson) -> bool {
+    let Some(area) = as_polygon(area) else {
+        return false;
+    };
+    match (as_point(value), as_polygon(value)) {
+        (Some(point), _) => contains(&area, point),
+        (_, Some(ring)) => ring.iter().all(|vertex| contains(&area, *vertex)),
+        _ => false,
+    }
+}
+
+// Casts a ray from the point and counts the edges it crosses.
+fn contains(ring: &[(f64, f64)], (x, y): (f64, f64)) -> bool {
+    let mut is_inside = false;
+    for edge in ring.windows(2) {
+        let ((x1, y1), (x2, y2)) = (edge[0], edge[1]);
+        if (y1 > y) != (y2 > y) && x < (x2 - x1) * (y - y1) / (y2 - y1) + x1 {
+            is_inside = !is_inside;
+        }
+    }
+    is_inside
+}
+
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+pub const GEOHASH_PRECISION: usize = 12;
+// How many cells a spatial lookup may scan; larger areas are covered by coarser cells.
+const MAX_COVERING_CELLS: usize = 32;
+
+// Geohashes of close points share their prefixes, which makes them index keys
+// with nearby points next to each other.
+pub fn geohash((longitude, latitude): (f64, f64), precision: usize) -> String {
+    let (mut longitude_range, mut latitude_range) = ((-180.0, 180.0), (-90.0, 90.0));
+    let mut hash = String::with_capacity(precision);
+    let mut is_longitude_bit = true;
+    let (mut character, mut bits) = (0, 0);
+    while hash.len() < precision {
+        let (range, coordinate): (&mut (f64, f64), f64) = if is_longitude_bit {
+            (&mut longitude_range, longitude)
+        } else {
+            (&mut latitude_range, latitude)
+        };
+        let middle = (range.0 + range.1) / 2.0;
+        character <<= 1;
+        if coordinate >= middle {
+            character |= 1;
+            range.0 = middle;
+        } else {
+            range.1 = middle;
+        }
+        is_longitude_bit = !is_longitude_bit;
+        bits += 1;
+        if bits == 5 {
+            hash.push(GEOHASH_ALPHABET[character] as char);
+            (character, bits) = (0, 0);
+        }
+    }
+    hash
+}
+
+// The size of a geohash cell in degrees of longitude and latitude.
+fn cell_size(precision: usize) -> (f64, f64) {
+    let bits = 5 * precision as i32;
+    let longitude_bits = (bits + 1) / 2;
+    (
+        360.0 / 2f64.powi(longitude_bits),
+        180.0 / 2f64.powi(bits - longitude_bits),
+    )
+}
+
+// Geohash prefixes of the cells covering the bounding box of an area, as precise
+// as they can be while there are few enough of them.
+pub fn covering_cells(area: &Bson) -> Option<Vec<String>> {
+    let ring = as_polygon(area)?;
+    let fold = |select: fn(&(f64, f64)) -> f64, pick: fn(f64, f64) -> f64| {
+        ring.iter().map(select).reduce(pick).unwrap_or_default()
+    };
+    let (min_longitude, max_longitude) = (fold(|v| v.0, f64::min), fold(|v| v.0, f64::max));
+    let (min_latitude, max_latitude) = (fold(|v| v.1, f64::min), fold(|v| v.1, f64::max));
+    let mut cells: Vec<String> = vec![String::new()];
+    for precision in 1..=GEOHASH_PRECISION {
+        let (width, height) = cell_size(precision);
+        let columns =
+            ((max_longitude / width).floor() - (min_longitude / width).floor()) as usize + 1;
+        let rows = ((max_latitude / height).floor() - (min_latitude / height).floor()) as usize + 1;
+        if columns * rows > MAX_COVERING_CELLS {
+            break;
+        }
+        cells = vec![];
+        for row in 0..rows {
+            for column in 0..columns {
+                let longitude = ((min_longitude / width).floor() + column as f64 + 0.5) * width;
+                let latitude = ((min_latitude / height).floor() + row as f64 + 0.5) * height;
+                cells.push(geohash(
+                    (longitude.clamp(-180.0, 180.0), latitude.clamp(-90.0, 90.0)),
+                    precision,
+                ));
+            }
+        }
+        cells.dedup();
+    }
+    Some(cells)
+}
@@ -0,0 +1,257 @@
+use bson::{doc, Bson, Document};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Result, Write};
+use std::ops::Bound;
+use std::path::Path;
+
+use super::query::Comparison;
+use super::storage::storage_error;
+use super::{geo, values};
+
+// A totally ordered wrapper around BSON values, so that they can be used as B-tree keys.
+// Values are ordered by their type first and by the values themselves then.
+#[derive(Debug, Clone)]
+pub struct IndexKey(pub Bson);
+
+fn type_rank(value: &Bson) -> u8 {
+    match value {
+        Bson::Null => 0,
+        Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) => 1,
+        Bson::String(_) => 2,
+        Bson::Boolean(_) => 3,
+        Bson::DateTime(_) => 4,
+        _ => 5,
+    }
+}
+
+// The order of index keys, which is also the order rows are sorted in by queries.
+pub fn compare_keys(left: &Bson, right: &Bson) -> Ordering {
+    type_rank(left).cmp(&type_rank(right)).then_with(|| {
+        match (values::as_f64(left), values::as_f64(right)) {
+            (Some(left), Some(right)) => left.total_cmp(&right),
+            _ => values::compare(left, right)
+                .unwrap_or_else(|| left.to_string().cmp(&right.to_string())),
+        }
+    })
+}
+
+impl Ord for IndexKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_keys(&self.0, &other.0)
+    }
+}
+
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for IndexKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for IndexKey {}
+
+// A secondary index mapping values of a column to the ids of the rows holding them.
+// Spatial indexes of `geo` columns map geohashes of the geometries instead.
+#[derive(Clone)]
+pub struct Index {
+    pub column: String,
+    pub spatial: bool,
+    entries: BTreeMap<IndexKey, Vec<Bson>>,
+}
+
+// Where a scan of an index stopped: the key of the entry it was in and the id of the
+// last row it yielded.
+pub type Position<'a> = (&'a IndexKey, &'a Bson);
+
+impl Index {
+    pub fn new(column: String, spatial: bool) -> Self {
+        Index {
+            column,
+            spatial,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn key(&self, value: Bson) -> IndexKey {
+        if !self.spatial {
+            return IndexKey(value);
+        }
+        match geo::center(&value) {
+            Some(center) => IndexKey(Bson::String(geo::geohash(center, geo::GEOHASH_PRECISION))),
+            None => IndexKey(Bson::Null),
+        }
+    }
+
+    pub fn supports(&self, comparison: Comparison) -> bool {
+        match comparison {
+            Comparison::NotEqual => false,
+            Comparison::Equal => true,
+            Comparison::Within => self.spatial,
+            _ => !self.spatial,
+        }
+    }
+
+    pub fn add(&mut self, value: Bson, id: Bson) {
+        let key = self.key(value);
+        let ids = self.entries.entry(key).or_default();
+        let position =
+            ids.partition_point(|indexed_id| compare_keys(indexed_id, &id) == Ordering::Less);
+        ids.insert(position, id);
+    }
+
+    pub fn remove(&mut self, value: Bson, id: &Bson) {
+        let key = self.key(value);
+        if let Some(ids) = self.entries.get_mut(&key) {
+            ids.retain(|indexed_id| !values::equals(indexed_id, id));
+            if ids.is_empty() {
+                self.entries.remove(&key);
+            }
+        }
+    }
+
+    // Yields the ids of rows whose column satisfies the comparison, in the index order.
+    // A scan going on after a position skips the entries up to it, so that entries
+    // added or removed before it in the meantime don't shift the scan.
+    pub fn lookup<'a>(
+        &'a self,
+        comparison: Comparison,
+        value: &Bson,
+        after: Option<Position<'a>>,
+    ) -> Box<dyn Iterator<Item = &'a Bson> + 'a> {
+        let value = values::as_reference(value).map_or(value, |(_, id)| id);
+        let ranges = match (comparison, value) {
+            (Comparison::Equal, Bson::Array(options)) => {
+                let mut keys: Vec<IndexKey> = options
+                    .iter()
+                    .map(|option| values::as_reference(option).map_or(option, |(_, id)| id))
+                    .map(|option| self.key(option.clone()))
+                    .collect();
+                // Each option once, in the index order.
+                keys.sort();
+                keys.dedup();
+                keys.into_iter()
+                    .map(|key| (Bound::Included(key.clone()), Bound::Included(key)))
+                    .collect()
+            }
+            (Comparison::Within, _) => within_ranges(value),
+            _ => {
+                let key = self.key(value.clone());
+                vec![match comparison {
+                    Comparison::Equal => (Bound::Included(key.clone()), Bound::Included(key)),
+                    Comparison::Greater => (Bound::Excluded(key), Bound::Unbounded),
+                    Comparison::GreaterOrEqual => (Bound::Included(key), Bound::Unbounded),
+                    Comparison::Less => (Bound::Unbounded, Bound::Excluded(key)),
+                    Comparison::LessOrEqual => (Bound::Unbounded, Bound::Included(key)),
+                    Comparison::NotEqual | Comparison::Within => {
+                        (Bound::Unbounded, Bound::Unbounded)
+                    }
+                }]
+            }
+        };
+        let entries = ranges
+            .into_iter()
+            .filter_map(move |range| match after {
+                Some((key, _)) => bounds_from(range, key),
+                None => Some(range),
+            })
+            .flat_map(move |range| self.entries.range(range));
+        Box::new(entries.flat_map(move |(key, ids)| ids_after(key, ids, after)))
+    }
+
+    // Yields the ids of all rows ordered by the indexed values, going on after a
+    // position when there's one. Rows holding equal values keep the order of their ids
+    // either way.
+    pub fn ordered<'a>(
+        &'a self,
+        descending: bool,
+        after: Option<Position<'a>>,
+    ) -> Box<dyn Iterator<Item = &'a Bson> + 'a> {
+        let entries: Box<dyn Iterator<Item = (&IndexKey, &Vec<Bson>)>> = match (descending, after) {
+            (true, Some((key, _))) => Box::new(
+                self.entries
+                    .range((Bound::Unbounded, Bound::Included(key.clone())))
+                    .rev(),
+            ),
+            (true, None) => Box::new(self.entries.iter().rev()),
+            (false, Some((key, _))) => Box::new(self.entries.range(key.clone()..)),
+            (false, None) => Box::new(self.entries.iter()),
+        };
+        Box::new(entries.flat_map(move |(key, ids)| ids_after(key, ids, after)))
+    }
+
+    pub fn save(&self, writer: &mut impl Write) -> Result<()> {
+        for (key, ids) in &self.entries {
+            let entry = doc! { "key": key.0.clone(), "ids": ids.clone() };
+            entry.to_writer(&mut *writer).map_err(storage_error)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(column: String, spatial: bool, path: &Path) -> Result<Self> {
+        let mut index = Index::new(column, spatial);
+        let mut reader = BufReader::new(File::open(path)?);
+        while !reader.fill_buf()?.is_empty() {
+            let mut entry = Document::from_reader(&mut reader).map_err(storage_error)?;
+            let key = entry.remove("key").unwrap_or(Bson::Null);
+            let ids = match entry.remove("ids") {
+                Some(Bson::Array(ids)) => ids,
+                _ => vec![],
+            };
+            index.entries.insert(IndexKey(key), ids);
+        }
+        Ok(index)
+    }
+}
+
+// The ranges of geohashes starting with the ones of the cells covering an area, in order.
+fn within_ranges(area: &Bson) -> Vec<(Bound<IndexKey>, Bound<IndexKey>)> {
+    let mut cells = geo::covering_cells(area).unwrap_or_default();
+    cells.sort();
+    cells.dedup();
+    cells
+        .into_iter()
+        .map(|cell| {
+            // Geohashes are made of digits and lowercase letters, all sorted before '~'.
+            let end = format!("{}~", cell);
+            (
+                Bound::Included(IndexKey(Bson::String(cell))),
+                Bound::Excluded(IndexKey(Bson::String(end))),
+            )
+        })
+        .collect()
+}
+
+// Narrows the bounds of a lookup to the keys from the one a scan stopped at on.
+fn bounds_from(
+    (lower, upper): (Bound<IndexKey>, Bound<IndexKey>),
+    key: &IndexKey,
+) -> Option<(Bound<IndexKey>, Bound<IndexKey>)> {
+    match &upper {
+        Bound::Included(bound) if bound < key => return None,
+        Bound::Excluded(bound) if bound <= key => return None,
+        _ => {}
+    }
+    let lower = match &lower {
+        Bound::Included(bound) | Bound::Excluded(bound) if bound >= key => lower,
+        _ => Bound::Included(key.clone()),
+    };
+    Some((lower, upper))
+}
+
+// The ids of an entry, leaving out the ones a scan yielded already when it stopped in
+// this entry.
+fn ids_after<'a>(key: &IndexKey, ids: &'a [Bson], after: Option<Position>) -> &'a [Bson] {
+    match after {
+        Some((last_key, last_id)) if last_key == key => {
+            &ids[ids.partition_point(|id| compare_keys(id, last_id) != Ordering::Greater)..]
+        }
+        _ => ids,
+    }
+}
@@ -0,0 +1,205 @@
+use bson::{Bson, Document};
+use std::fmt;
+
+use super::schema::{Column, ColumnType, KeyStrategy, TableSchema};
+use super::values;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Add(String),
+    Drop(String),
+    Rename {
+        from: String,
+        to: String,
+    },
+    // A change of the type of a column or of whether it holds an array.
+    Retype {
+        column: String,
+        from: String,
+        to: String,
+    },
+    // A change of modifiers, constraints, or the default of a column.
+    Alter(String),
+    Rekey {
+        from: KeyStrategy,
+        to: KeyStrategy,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Add(column) => write!(f, "add {}", column),
+            Change::Drop(column) => write!(f, "drop {}", column),
+            Change::Rename { from, to } => write!(f, "rename {} to {}", from, to),
+            Change::Retype { column, from, to } => {
+                write!(f, "retype {} from {} to {}", column, from, to)
+            }
+            Change::Alter(column) => write!(f, "alter {}", column),
+            Change::Rekey { from, to } => write!(f, "rekey from {} to {}", from, to),
+        }
+    }
+}
+
+// The changes turning the stored schema of a table into a newly declared one.
+// Columns are matched by their names, unless a column says it's renamed with `from <name>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    pub table: String,
+    pub changes: Vec<Change>,
+    old: TableSchema,
+    new: TableSchema,
+}
+
+impl Migration {
+    pub fn plan(old: &TableSchema, new: &TableSchema) -> Self {
+        let mut changes: Vec<Change> = vec![];
+        if old.key != new.key {
+            changes.push(Change::Rekey {
+                from: old.key.clone(),
+                to: new.key.clone(),
+            });
+        }
+        for column in &new.columns {
+            let Some(old_column) = Self::previous_column(old, column) else {
+                changes.push(Change::Add(column.name.clone()));
+                continue;
+            };
+            if old_column.name != column.name {
+                changes.push(Change::Rename {
+                    from: old_column.name.clone(),
+                    to: column.name.clone(),
+                });
+            }
+            let (old_type, new_type) = (type_name(old_column), type_name(column));
+            if old_type != new_type {
+                changes.push(Change::Retype {
+                    column: column.name.clone(),
+                    from: old_type,
+                    to: new_type,
+                });
+            } else {
+                let mut unrenamed = column.clone();
+                unrenamed.name.clone_from(&old_column.name);
+                unrenamed.renamed_from = None;
+                if unrenamed != *old_column {
+                    changes.push(Change::Alter(column.name.clone()));
+                }
+            }
+        }
+        for old_column in &old.columns {
+            let is_kept = new
+                .columns
+                .iter()
+                .any(|column| Self::previous_column(old, column) == Some(old_column));
+            if !is_kept {
+                changes.push(Change::Drop(old_column.name.clone()));
+            }
+        }
+        Migration {
+            table: new.name.clone(),
+            changes,
+            old: old.clone(),
+            new: new.clone(),
+        }
+    }
+
+    // A renamed column is only matched while the column it's renamed from exists.
+    fn previous_column<'a>(old: &'a TableSchema, column: &Column) -> Option<&'a Column> {
+        old.get_column(&column.name).or_else(|| {
+            column
+                .renamed_from
+                .as_ref()
+                .and_then(|previous_name| old.get_column(previous_name))
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    // The name of a column of the new schema in the old one.
+    pub fn previous_name(&self, column: &str) -> Option<&str> {
+        let column = self.new.get_column(column)?;
+        Self::previous_column(&self.old, column).map(|column| column.name.as_str())
+    }
+
+    // Moves a row to the new schema. Values that can't be kept are described
+    // in the returned losses, so that destructive migrations can be refused.
+    pub fn migrate_row(&self, mut row: Document) -> (Document, Vec<String>) {
+        let mut losses: Vec<String> = vec![];
+        let id = row.get("id").cloned().unwrap_or(Bson::Null);
+        let mut migrated = Document::new();
+        migrated.insert("id", id.clone());
+        for column in &self.new.columns {
+            // Rows of tables declared with no columns keep the values of the same names.
+            let Some(old_column) = Self::previous_column(&self.old, column) else {
+                if let Some(value) = row.remove(&column.name) {
+                    migrated.insert(&column.name, value);
+                }
+                continue;
+            };
+            let Some(value) = row.remove(&old_column.name) else {
+                continue;
+            };
+            match convert_column_value(&value, old_column, column) {
+                Some(value) => {
+                    migrated.insert(&column.name, value);
+                }
+                None => losses.push(format!(
+                    "lose {} of '{}' in the row {}",
+                    value, old_column.name, id
+                )),
+            }
+        }
+        for (column, value) in row {
+            if column != "id" && value != Bson::Null {
+                losses.push(format!("drop {} of '{}' in the row {}", value, column, id));
+            }
+        }
+        (migrated, losses)
+    }
+}
+
+impl fmt::Display for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let changes: Vec<String> = self.changes.iter().map(Change::to_string).collect();
+        write!(f, "migrate {}: {}", self.table, changes.join(", "))
+    }
+}
+
+fn type_name(column: &Column) -> String {
+    let mut name = match &column.column_type {
+        ColumnType::Scalar(datatype) => datatype.clone(),
+        ColumnType::Reference(table) => format!("&{}", table),
+    };
+    if column.is_array {
+        name.push_str("[]");
+    }
+    name
+}
+
+// Values of references and other types with no conversions are kept only while
+// the type stays the same. An array becomes a single value when it holds one.
+fn convert_column_value(value: &Bson, old: &Column, new: &Column) -> Option<Bson> {
+    let convert = |value: &Bson| match (&old.column_type, &new.column_type) {
+        (old_type, new_type) if old_type == new_type => Some(value.clone()),
+        (ColumnType::Scalar(_), ColumnType::Scalar(datatype)) => values::convert(value, datatype),
+        _ => (*value == Bson::Null).then_some(Bson::Null),
+    };
+    match (value, old.is_array, new.is_array) {
+        (Bson::Null, ..) => Some(Bson::Null),
+        (Bson::Array(items), true, true) => items
+            .iter()
+            .map(convert)
+            .collect::<Option<Vec<Bson>>>()
+            .map(Bson::Array),
+        (Bson::Array(items), true, false) => match &items[..] {
+            [] => Some(Bson::Null),
+            [item] => convert(item),
+            _ => None,
+        },
+        (value, false, true) => convert(value).map(|value| Bson::Array(vec![value])),
+        (value, ..) => convert(value),
+    }
+}
@@ -1 +1,16 @@
+pub mod access;
+pub mod aggregation;
+pub mod catalog;
+pub mod constraints;
 pub mod create_db;
+pub mod cursor;
+pub mod datetime;
+pub mod events;
+pub mod geo;
+pub mod index;
+pub mod migration;
+pub mod planner;
+pub mod query;
+pub mod schema;
+pub mod storage;
+pub mod values;
@@ -0,0 +1,152 @@
+use bson::Bson;
+use std::fmt;
+
+use super::datetime;
+use super::query::{Comparison, Query, Step};
+use super::storage::Table;
+
+pub enum Plan {
+    FullScan {
+        table: String,
+    },
+    IndexScan {
+        table: String,
+        column: String,
+        comparison: Comparison,
+        value: Bson,
+    },
+    // Walks an index in the order a query sorts its rows in.
+    IndexOrder {
+        table: String,
+        column: String,
+        descending: bool,
+    },
+    // A scan whose conditions on referenced rows, e.g. `{self.seller.country.name = "Norway"}`,
+    // were resolved by running the inner plans first. The keys of the rows they found
+    // are then looked up through the reference columns, using their indexes when there are ones.
+    Join {
+        scan: Box<Plan>,
+        joins: Vec<Join>,
+    },
+}
+
+pub struct Join {
+    pub column: String,
+    pub plan: Plan,
+}
+
+impl Plan {
+    // The scan fetching the rows, leaving out the plans of the joined tables.
+    pub fn base(&self) -> &Plan {
+        match self {
+            Plan::Join { scan, .. } => scan.base(),
+            plan => plan,
+        }
+    }
+
+    fn write_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let Plan::Join { scan, joins } = self else {
+            return write!(f, "{}", self);
+        };
+        scan.write_tree(f, depth)?;
+        for join in joins {
+            write!(f, "\n{}join {}: ", "  ".repeat(depth + 1), join.column)?;
+            join.plan.write_tree(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Plan::FullScan { table } => write!(f, "FullScan {}", table),
+            Plan::IndexScan {
+                table,
+                column,
+                comparison,
+                value,
+            } => write!(f, "IndexScan {}.{} {} {}", table, column, comparison, value),
+            Plan::IndexOrder {
+                table,
+                column,
+                descending: true,
+            } => write!(f, "IndexOrder {}.{} desc", table, column),
+            Plan::IndexOrder { table, column, .. } => write!(f, "IndexOrder {}.{}", table, column),
+            Plan::Join { .. } => self.write_tree(f, 0),
+        }
+    }
+}
+
+// Picks an index for the leading filter of a query. Equality conditions are preferred
+// over ranges since they narrow the scan the most. Every condition is still checked
+// against the fetched rows, so the plan only decides which rows are fetched.
+// Without such a filter, an index of the first column a query sorts by is walked in order.
+pub fn plan(table: &Table, query: &Query) -> Plan {
+    let first_step = query.steps.first();
+    let conditions = match first_step {
+        Some(Step::Filter(conditions)) => conditions.as_slice(),
+        _ => &[],
+    };
+    // Indexes of array columns hold whole arrays, so they can't find single items.
+    let indexed_conditions = conditions.iter().filter(|condition| {
+        condition.path.len() == 1
+            && !table
+                .schema
+                .get_column(&condition.path[0])
+                .is_some_and(|column| column.is_array)
+            && table
+                .indexes
+                .get(&condition.path[0])
+                .is_some_and(|index| index.supports(condition.comparison))
+    });
+    let chosen_condition = indexed_conditions
+        .clone()
+        .find(|condition| condition.comparison == Comparison::Equal)
+        .or_else(|| indexed_conditions.clone().next());
+    if let Some(condition) = chosen_condition {
+        return Plan::IndexScan {
+            table: table.name.clone(),
+            column: condition.path[0].clone(),
+            comparison: condition.comparison,
+            value: index_value(table, &condition.path[0], &condition.value),
+        };
+    }
+    let sort_key = query
+        .steps
+        .iter()
+        .find(|step| !matches!(step, Step::Filter(_)))
+        .and_then(|step| match step {
+            Step::Order(keys) => keys.first(),
+            _ => None,
+        });
+    match sort_key {
+        Some(key)
+            if table
+                .indexes
+                .get(&key.column)
+                .is_some_and(|index| !index.spatial) =>
+        {
+            Plan::IndexOrder {
+                table: table.name.clone(),
+                column: key.column.clone(),
+                descending: key.descending,
+            }
+        }
+        _ => Plan::FullScan {
+            table: table.name.clone(),
+        },
+    }
+}
+
+// Indexes of datetime columns hold datetimes, so the strings compared with them are
+// looked up as the datetimes they stand for.
+fn index_value(table: &Table, column: &str, value: &Bson) -> Bson {
+    let is_datetime = table.schema.column_has_type(column, "datetime");
+    let coerce = |value: &Bson| datetime::coerce(value).unwrap_or_else(|| value.clone());
+    match value {
+        _ if !is_datetime => value.clone(),
+        Bson::Array(items) => Bson::Array(items.iter().map(coerce).collect()),
+        value => coerce(value),
+    }
+}
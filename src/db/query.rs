@@ -0,0 +1,170 @@
+use bson::{Bson, Document};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use strum_macros::Display;
+
+use super::aggregation::Grouping;
+use super::{geo, index, values};
+
+#[derive(Debug, Display, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    #[strum(serialize = "=")]
+    Equal,
+    #[strum(serialize = "!=")]
+    NotEqual,
+    #[strum(serialize = ">")]
+    Greater,
+    #[strum(serialize = "<")]
+    Less,
+    #[strum(serialize = ">=")]
+    GreaterOrEqual,
+    #[strum(serialize = "<=")]
+    LessOrEqual,
+    // A geometry lying in an area, e.g. `{self.location within box(...)}`.
+    #[strum(serialize = "within")]
+    Within,
+}
+
+impl Comparison {
+    pub fn is_satisfied(&self, ordering: Option<Ordering>) -> bool {
+        match ordering {
+            None => *self == Comparison::NotEqual,
+            Some(ordering) => match self {
+                Comparison::Equal => ordering == Ordering::Equal,
+                Comparison::NotEqual => ordering != Ordering::Equal,
+                Comparison::Greater => ordering == Ordering::Greater,
+                Comparison::Less => ordering == Ordering::Less,
+                Comparison::GreaterOrEqual => ordering != Ordering::Less,
+                Comparison::LessOrEqual => ordering != Ordering::Greater,
+                Comparison::Within => false,
+            },
+        }
+    }
+}
+
+// A resolved `self.<path> <comparison> <value>` condition of a selector.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    pub path: Vec<String>,
+    pub comparison: Comparison,
+    pub value: Bson,
+}
+
+impl Condition {
+    pub fn new(path: Vec<String>, comparison: Comparison, value: Bson) -> Self {
+        Condition {
+            path,
+            comparison,
+            value,
+        }
+    }
+
+    pub fn matches(&self, row: &Document) -> bool {
+        let field = get_path(row, &self.path);
+        // Arrays of references are among the selected values when any of their items is.
+        let is_among = |options: &[Bson]| match &field {
+            Bson::Array(items) => items
+                .iter()
+                .any(|item| options.iter().any(|option| values::equals(item, option))),
+            field => options.iter().any(|option| values::equals(field, option)),
+        };
+        match (&self.value, self.comparison) {
+            // Comparing with a selection, e.g. `self.country = &countries.{...}.id`,
+            // checks whether the field is among the selected values.
+            (Bson::Array(options), Comparison::Equal) => is_among(options),
+            (Bson::Array(options), Comparison::NotEqual) => !is_among(options),
+            (area, Comparison::Within) => geo::within(&field, area),
+            (value, comparison) => comparison.is_satisfied(values::compare(&field, value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Step {
+    Filter(Vec<Condition>),
+    Field(String),
+    Group(Grouping),
+    Order(Vec<SortKey>),
+    Limit(usize),
+    Offset(usize),
+    // `.referenced_by(products.seller)`
+    ReferencedBy { table: String, column: String },
+}
+
+// A key of `.order(created_at desc, name)`.
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl SortKey {
+    pub fn value<'a>(&self, row: &'a Document) -> &'a Bson {
+        row.get(&self.column).unwrap_or(&Bson::Null)
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.descending {
+            true => write!(f, "{} desc", self.column),
+            false => write!(f, "{}", self.column),
+        }
+    }
+}
+
+// Values are sorted in the order of index keys, so sorting agrees with index scans.
+pub fn compare_rows(keys: &[SortKey], left: &Document, right: &Document) -> Ordering {
+    keys.iter()
+        .map(|key| {
+            let ordering = index::compare_keys(key.value(left), key.value(right));
+            match key.descending {
+                true => ordering.reverse(),
+                false => ordering,
+            }
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+// How many sorted rows the `.limit(...)` and `.offset(...)` steps following a sort
+// can keep at most, e.g. 30 for `.offset(20).limit(10)`.
+pub fn row_bound(steps: &[Step]) -> Option<usize> {
+    let mut skipped: usize = 0;
+    let mut bound: Option<usize> = None;
+    for step in steps {
+        match step {
+            Step::Offset(offset) => skipped = skipped.saturating_add(*offset),
+            Step::Limit(limit) => {
+                let kept = skipped.saturating_add(*limit);
+                bound = Some(bound.map_or(kept, |bound| bound.min(kept)));
+            }
+            _ => break,
+        }
+    }
+    bound
+}
+
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub table: String,
+    pub steps: Vec<Step>,
+}
+
+impl Query {
+    pub fn new(table: String, steps: Vec<Step>) -> Self {
+        Query { table, steps }
+    }
+}
+
+pub fn get_path(row: &Document, path: &[String]) -> Bson {
+    let Some((key, rest)) = path.split_first() else {
+        return Bson::Document(row.clone());
+    };
+    match (row.get(key), rest.is_empty()) {
+        (Some(value), true) => value.clone(),
+        (Some(Bson::Document(document)), false) => get_path(document, rest),
+        _ => Bson::Null,
+    }
+}
@@ -0,0 +1,246 @@
+use bson::Document;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+
+use super::constraints::Constraint;
+
+#[derive(Debug, Display, EnumString, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[strum(serialize_all = "snake_case")]
+pub enum OnDelete {
+    // Deleting a referenced row fails while it's referenced.
+    #[default]
+    Restrict,
+    // Referencing rows are deleted along with the referenced one.
+    Cascade,
+    // References to the deleted row are replaced with null.
+    Nullify,
+}
+
+// How the `id` of a new row is produced, declared as `table <name>: <strategy>`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub enum KeyStrategy {
+    // Integers increasing with every insert; the default strategy.
+    #[default]
+    Increment,
+    Uuid4,
+    // Time-ordered UUIDs, which keep recently inserted rows close to each other.
+    Uuid7,
+    // Keys supplied by the user, e.g. `table countries: str`.
+    Natural(String),
+}
+
+impl FromStr for KeyStrategy {
+    type Err = String;
+
+    fn from_str(strategy: &str) -> Result<Self, Self::Err> {
+        match strategy {
+            "increment" => Ok(KeyStrategy::Increment),
+            "uuid" | "uuid4" => Ok(KeyStrategy::Uuid4),
+            "uuid7" => Ok(KeyStrategy::Uuid7),
+            "str" | "int" => Ok(KeyStrategy::Natural(strategy.to_string())),
+            _ => Err(format!("'{}' isn't a key strategy", strategy)),
+        }
+    }
+}
+
+impl fmt::Display for KeyStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyStrategy::Increment => write!(f, "increment"),
+            KeyStrategy::Uuid4 => write!(f, "uuid4"),
+            KeyStrategy::Uuid7 => write!(f, "uuid7"),
+            KeyStrategy::Natural(datatype) => write!(f, "{}", datatype),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum ColumnType {
+    Scalar(String),
+    // A link to a row of another table, stored as its primary key.
+    Reference(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub column_type: ColumnType,
+    pub is_array: bool,
+    pub nullable: bool,
+    pub required: bool,
+    pub on_delete: OnDelete,
+    #[serde(default)]
+    pub constraints: Vec<Constraint>,
+    // The source code of the default value; it's evaluated on every insert.
+    pub default: Option<String>,
+    // The name the column had before, given with `from <name>` to migrate it by renaming.
+    #[serde(skip)]
+    pub renamed_from: Option<String>,
+}
+
+impl Column {
+    pub fn new(name: String, column_type: ColumnType) -> Self {
+        Column {
+            name,
+            column_type,
+            is_array: false,
+            nullable: false,
+            required: false,
+            on_delete: OnDelete::default(),
+            constraints: vec![],
+            default: None,
+            renamed_from: None,
+        }
+    }
+
+    pub fn referenced_table(&self) -> Option<&str> {
+        match &self.column_type {
+            ColumnType::Reference(table) => Some(table),
+            ColumnType::Scalar(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.column_type {
+            ColumnType::Scalar(datatype) => write!(f, "{}: {}", self.name, datatype)?,
+            ColumnType::Reference(table) => write!(f, "{}: &{}", self.name, table)?,
+        }
+        if self.is_array {
+            write!(f, "[]")?;
+        }
+        if self.nullable {
+            write!(f, "?")?;
+        }
+        if self.required {
+            write!(f, "!")?;
+        }
+        for constraint in &self.constraints {
+            write!(f, " {}", constraint)?;
+        }
+        if self.referenced_table().is_some() && self.on_delete != OnDelete::Restrict {
+            write!(f, " {}", self.on_delete)?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " = {}", default)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TableSchema {
+    pub name: String,
+    #[serde(default)]
+    pub key: KeyStrategy,
+    pub columns: Vec<Column>,
+    // The package that declared the table, if any; only it may declare the table again.
+    #[serde(default)]
+    pub package: Option<String>,
+}
+
+impl TableSchema {
+    pub fn new(name: String, columns: Vec<Column>) -> Self {
+        TableSchema {
+            name,
+            key: KeyStrategy::default(),
+            columns,
+            package: None,
+        }
+    }
+
+    pub fn with_key(mut self, key: KeyStrategy) -> Self {
+        self.key = key;
+        self
+    }
+
+    pub fn get_column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+
+    pub fn column_has_type(&self, name: &str, datatype: &str) -> bool {
+        self.get_column(name).is_some_and(|column| {
+            matches!(&column.column_type, ColumnType::Scalar(column_type) if column_type == datatype)
+        })
+    }
+
+    pub fn references(&self) -> impl Iterator<Item = (&Column, &str)> {
+        self.columns
+            .iter()
+            .filter_map(|column| Some((column, column.referenced_table()?)))
+    }
+}
+
+impl fmt::Display for TableSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "table {}", self.name)?;
+        if self.key != KeyStrategy::Increment {
+            write!(f, ": {}", self.key)?;
+        }
+        let columns: Vec<String> = self.columns.iter().map(Column::to_string).collect();
+        write!(f, " {{ {} }}", columns.join(", "))
+    }
+}
+
+// `enum <name>[: <type>] { ... }`; variants of `int` enums are stored as their positions
+// and variants of other ones as their names.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EnumDefinition {
+    pub name: String,
+    pub datatype: Option<String>,
+    pub variants: Vec<String>,
+}
+
+impl EnumDefinition {
+    // The value of an enum is a document of its variants, so `Gender.Male` is a field access.
+    pub fn to_document(&self) -> Document {
+        let mut document = Document::new();
+        for (position, variant) in self.variants.iter().enumerate() {
+            match self.datatype.as_deref() {
+                Some("int") => document.insert(variant, position as i64),
+                _ => document.insert(variant, variant),
+            };
+        }
+        document
+    }
+}
+
+impl fmt::Display for EnumDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "enum {}", self.name)?;
+        if let Some(datatype) = &self.datatype {
+            write!(f, ": {}", datatype)?;
+        }
+        write!(f, " {{ {} }}", self.variants.join(", "))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub parameters: Vec<(String, Option<String>)>,
+    pub returns: Option<String>,
+    // The source code of the body, if the function has one.
+    pub body: Option<String>,
+}
+
+impl fmt::Display for FunctionDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parameters: Vec<String> = self
+            .parameters
+            .iter()
+            .map(|(name, datatype)| match datatype {
+                Some(datatype) => format!("{}: {}", name, datatype),
+                None => name.clone(),
+            })
+            .collect();
+        write!(f, "function {}({})", self.name, parameters.join(", "))?;
+        if let Some(returns) = &self.returns {
+            write!(f, ": {}", returns)?;
+        }
+        Ok(())
+    }
+}
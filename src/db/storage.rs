@@ -0,0 +1,1518 @@
+use bson::{doc, Bson, Document};
+use colored::Colorize;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Result, Seek, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use super::access::{Access, Logins};
+use super::aggregation::Grouping;
+use super::catalog::Catalog;
+use super::constraints;
+use super::cursor::Resume;
+use super::datetime;
+use super::events::{Event, Firing, Operation};
+use super::index::{self, Index, IndexKey, Position};
+use super::migration::Migration;
+use super::planner::{self, Join, Plan};
+use super::query::{self, Comparison, Condition, Query, Step};
+use super::schema::{EnumDefinition, FunctionDefinition, KeyStrategy, OnDelete, TableSchema};
+use super::values;
+
+pub type SharedStorage = Arc<Mutex<Storage>>;
+
+// A table's log is folded into its file once it holds more records than the table has
+// rows, and at least this many.
+const MIN_COMPACTED_LOG: usize = 1000;
+
+pub struct Table {
+    pub name: String,
+    pub schema: TableSchema,
+    pub rows: BTreeMap<IndexKey, Document>,
+    pub indexes: BTreeMap<String, Index>,
+    next_id: i64,
+    // Log records of the rows written since the table was last saved.
+    unsaved: Vec<Document>,
+    // How many records the log file of the table holds.
+    logged: usize,
+    // Counts the writes to the table, so that whoever read its rows can tell whether
+    // they've changed since.
+    writes: u64,
+}
+
+impl Table {
+    pub fn new(schema: TableSchema) -> Self {
+        Table {
+            name: schema.name.clone(),
+            schema,
+            rows: BTreeMap::new(),
+            indexes: BTreeMap::new(),
+            next_id: 1,
+            unsaved: vec![],
+            logged: 0,
+            writes: 0,
+        }
+    }
+
+    // Yields the rows a plan fetches without copying them.
+    fn scan<'a>(&'a self, plan: &Plan) -> Box<dyn Iterator<Item = &'a Document> + 'a> {
+        self.scan_from(plan, None)
+    }
+
+    // Scans the rest of a table a cursor has scanned part of, going on after the last
+    // row it's seen in the order of the plan, so rows added or removed in the meantime
+    // don't shift it.
+    fn scan_from<'a>(
+        &'a self,
+        plan: &Plan,
+        after: Option<Position<'a>>,
+    ) -> Box<dyn Iterator<Item = &'a Document> + 'a> {
+        let get = |id: &Bson| self.rows.get(&IndexKey(id.clone()));
+        match plan.base() {
+            Plan::IndexScan {
+                column,
+                comparison,
+                value,
+                ..
+            } => Box::new(
+                self.indexes[column]
+                    .lookup(*comparison, value, after)
+                    .filter_map(get),
+            ),
+            Plan::IndexOrder {
+                column, descending, ..
+            } => Box::new(
+                self.indexes[column]
+                    .ordered(*descending, after)
+                    .filter_map(get),
+            ),
+            _ => match after {
+                Some((key, _)) => Box::new(
+                    self.rows
+                        .range((Bound::Excluded(key.clone()), Bound::Unbounded))
+                        .map(|(_, row)| row),
+                ),
+                None => Box::new(self.rows.values()),
+            },
+        }
+    }
+
+    // Where a row stands in the order a plan scans the table in: the key it's found
+    // under in the index the plan walks, or its id, followed by its id.
+    fn scan_position(&self, plan: &Plan, row: &Document) -> (IndexKey, Bson) {
+        let id = row.get("id").cloned().unwrap_or(Bson::Null);
+        match plan.base() {
+            Plan::IndexScan { column, .. } | Plan::IndexOrder { column, .. } => {
+                let value = row.get(column).cloned().unwrap_or(Bson::Null);
+                (self.indexes[column].key(value), id)
+            }
+            _ => (IndexKey(id.clone()), id),
+        }
+    }
+
+    fn put_row(&mut self, row: Document) {
+        let id = row.get("id").cloned().unwrap_or(Bson::Null);
+        if let Some(id) = id.as_i64() {
+            self.next_id = self.next_id.max(id + 1);
+        }
+        for (column, index) in self.indexes.iter_mut() {
+            index.add(row.get(column).cloned().unwrap_or(Bson::Null), id.clone());
+        }
+        self.rows.insert(IndexKey(id), row);
+    }
+
+    fn remove_row(&mut self, id: &Bson) -> Option<Document> {
+        let row = self.rows.remove(&IndexKey(id.clone()))?;
+        for (column, index) in self.indexes.iter_mut() {
+            index.remove(row.get(column).cloned().unwrap_or(Bson::Null), id);
+        }
+        Some(row)
+    }
+
+    fn rebuild_indexes(&mut self) {
+        for (column, index) in self.indexes.iter_mut() {
+            *index = Index::new(column.clone(), index.spatial);
+            for (id, row) in &self.rows {
+                index.add(row.get(column).cloned().unwrap_or(Bson::Null), id.0.clone());
+            }
+        }
+    }
+
+    pub fn writes(&self) -> u64 {
+        self.writes
+    }
+
+    // Puts a written row in place of the one with the same id, keeping a log record of
+    // it for `save_table`. Returns the replaced row.
+    fn write_row(&mut self, row: Document) -> Option<Document> {
+        let id = row.get("id").cloned().unwrap_or(Bson::Null);
+        let old = self.remove_row(&id);
+        self.writes += 1;
+        self.unsaved.push(doc! { "put": row.clone() });
+        self.put_row(row);
+        old
+    }
+
+    fn delete_row(&mut self, id: &Bson) -> Option<Document> {
+        let old = self.remove_row(id)?;
+        self.writes += 1;
+        self.unsaved.push(doc! { "delete": id.clone() });
+        Some(old)
+    }
+
+    // Applies a record of the table's log. Records may be applied twice when the table
+    // file was rewritten but the log wasn't removed yet, which leaves the same rows.
+    fn replay(&mut self, mut record: Document) -> Result<()> {
+        match (record.remove("put"), record.remove("delete")) {
+            (Some(Bson::Document(row)), _) => {
+                self.remove_row(row.get("id").unwrap_or(&Bson::Null));
+                self.put_row(row);
+            }
+            (_, Some(id)) => {
+                self.remove_row(&id);
+            }
+            _ => {
+                return Err(storage_error(format!(
+                    "The log of '{}' holds an invalid record",
+                    self.name
+                )))
+            }
+        }
+        self.logged += 1;
+        Ok(())
+    }
+
+    // Replaces the stored keys of reference columns with links to the referenced rows.
+    fn link_references(&self, mut row: Document) -> Document {
+        for (column, referenced_table) in self.schema.references() {
+            let Some(value) = row.get_mut(&column.name) else {
+                continue;
+            };
+            *value = match value.clone() {
+                Bson::Null => Bson::Null,
+                Bson::Array(ids) => Bson::Array(
+                    ids.into_iter()
+                        .map(|id| values::reference(referenced_table, id))
+                        .collect(),
+                ),
+                id => values::reference(referenced_table, id),
+            };
+        }
+        row
+    }
+}
+
+// An element of a selection being processed: either a row of a table or any other value.
+enum Selected {
+    Row(String, Document),
+    Value(Bson),
+}
+
+impl Selected {
+    // Rows and grouped documents can be sorted by their columns.
+    fn document(&self) -> Option<&Document> {
+        match self {
+            Selected::Row(_, row) => Some(row),
+            Selected::Value(Bson::Document(document)) => Some(document),
+            Selected::Value(_) => None,
+        }
+    }
+}
+
+// How to restore a table a transaction wrote: the rows it wrote, in order, with what
+// they were before, and the counters it had then.
+struct Undo {
+    rows: Vec<(Bson, Option<Document>)>,
+    next_id: i64,
+    unsaved: usize,
+}
+
+pub struct Storage {
+    data_path: PathBuf,
+    model_path: PathBuf,
+    tables: HashMap<String, Table>,
+    enums: BTreeMap<String, EnumDefinition>,
+    functions: BTreeMap<String, FunctionDefinition>,
+    events: BTreeMap<String, Event>,
+    revision: u64,
+    access: Access,
+    logins: Logins,
+    fired: Vec<Firing>,
+    // What the current transaction changed in each table it wrote. Changed tables are
+    // written to disk on commit and restored on rollback.
+    transaction: Option<HashMap<String, Undo>>,
+    // The session of the executor that began the transaction. Other sessions wait for
+    // a transaction made for a single write to end before they use the storage, and are
+    // turned away while one a session has begun is open.
+    transaction_owner: Option<u64>,
+    is_begun_by_session: bool,
+}
+
+impl Storage {
+    // Opens a datablaze created by `create_db_structure` and loads the catalog
+    // from its `model` folder and rows and indexes from its `data` folder.
+    pub fn open(datablaze_path: &Path) -> Result<Self> {
+        let data_path = datablaze_path.join("data");
+        let model_path = datablaze_path.join("model");
+        fs::create_dir_all(&data_path)?;
+        fs::create_dir_all(&model_path)?;
+        let mut storage = Storage {
+            data_path,
+            model_path,
+            tables: HashMap::new(),
+            enums: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            events: BTreeMap::new(),
+            revision: 0,
+            access: Access::default(),
+            logins: Logins::default(),
+            fired: vec![],
+            transaction: None,
+            transaction_owner: None,
+            is_begun_by_session: false,
+        };
+        let catalog_path = storage.catalog_path();
+        if catalog_path.exists() {
+            let catalog = Catalog::read(&catalog_path)?;
+            for schema in catalog.tables {
+                storage
+                    .tables
+                    .insert(schema.name.clone(), Table::new(schema));
+            }
+            storage.enums = catalog.enums;
+            storage.functions = catalog.functions;
+            storage.events = catalog.events;
+            storage.revision = catalog.revision;
+        }
+        let access_path = storage.access_path();
+        if access_path.exists() {
+            storage.access = Access::read(&access_path)?;
+        }
+        let mut index_paths: Vec<PathBuf> = vec![];
+        let mut sequence_paths: Vec<PathBuf> = vec![];
+        let mut log_paths: Vec<PathBuf> = vec![];
+        for entry in fs::read_dir(&storage.data_path)? {
+            let path = entry?.path();
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("bson") => storage.read_rows(&path, false)?,
+                Some("idx") => index_paths.push(path),
+                Some("seq") => sequence_paths.push(path),
+                Some("log") => log_paths.push(path),
+                _ => continue,
+            }
+        }
+        // Ids of deleted rows aren't reused, so the counter outlives the rows it produced.
+        for path in sequence_paths {
+            let Some(table_name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let document =
+                Document::from_reader(BufReader::new(File::open(&path)?)).map_err(storage_error)?;
+            let next_id = document.get_i64("next_id").map_err(storage_error)?;
+            let table = storage.get_table_mut(table_name)?;
+            table.next_id = table.next_id.max(next_id);
+        }
+        // Index files are named `<table>.<column>.idx`, so they are read once tables are loaded.
+        for path in index_paths {
+            let name = path.file_stem().and_then(|stem| stem.to_str());
+            let Some((table_name, column)) = name.and_then(|name| name.split_once('.')) else {
+                continue;
+            };
+            let table = storage.get_table_mut(table_name)?;
+            let spatial = table.schema.column_has_type(column, "geo");
+            let index = Index::read(column.to_string(), spatial, &path)?;
+            table.indexes.insert(column.to_string(), index);
+        }
+        // Logs hold the writes made since the table files were written, indexes included.
+        for path in log_paths {
+            storage.read_rows(&path, true)?;
+        }
+        Ok(storage)
+    }
+
+    pub fn shared(self) -> SharedStorage {
+        Arc::new(Mutex::new(self))
+    }
+
+    pub fn create_table(&mut self, name: &str) -> Result<()> {
+        if self.tables.contains_key(name) {
+            return Err(storage_error(format!("Table '{}' already exists", name)));
+        }
+        self.define_table(TableSchema::new(name.to_string(), vec![]))
+    }
+
+    // Creates a table described by a schema or replaces the schema of an existing one.
+    pub fn define_table(&mut self, schema: TableSchema) -> Result<()> {
+        self.check_declarable()?;
+        let name = schema.name.clone();
+        match self.tables.get_mut(&name) {
+            Some(table) => {
+                check_package("Table", &name, &table.schema.package, &schema.package)?;
+                table.schema = schema;
+            }
+            None => {
+                self.tables.insert(name.clone(), Table::new(schema));
+            }
+        }
+        self.save_catalog()?;
+        self.compact_table(&name)
+    }
+
+    // Replaces the schema and the rows of a table with migrated ones. The rows are validated
+    // against the new schema first, so that a failing migration leaves the table untouched.
+    pub fn migrate_table(
+        &mut self,
+        migration: &Migration,
+        schema: TableSchema,
+        rows: Vec<Document>,
+    ) -> Result<()> {
+        self.check_declarable()?;
+        for row in &rows {
+            constraints::validate_row(&schema, row)?;
+        }
+        let name = schema.name.clone();
+        let renamed = |column: &str| {
+            schema
+                .columns
+                .iter()
+                .find(|new_column| migration.previous_name(&new_column.name) == Some(column))
+                .map(|new_column| new_column.name.clone())
+        };
+        for event in self.events.values_mut() {
+            if event.table == name {
+                if let Some(column) = event.column.as_deref().and_then(renamed) {
+                    event.column = Some(column);
+                }
+            }
+        }
+        let table = self.get_table_mut(&name)?;
+        let old_columns: Vec<String> = table.indexes.keys().cloned().collect();
+        table.indexes.clear();
+        for column in &old_columns {
+            if let Some(column) = renamed(column) {
+                let spatial = schema.column_has_type(&column, "geo");
+                table
+                    .indexes
+                    .insert(column.clone(), Index::new(column, spatial));
+            }
+        }
+        table.rows.clear();
+        for row in rows {
+            table.put_row(row);
+        }
+        table.schema = schema;
+        for column in old_columns {
+            let index_path = self.data_path.join(format!("{}.{}.idx", name, column));
+            if let Err(err) = fs::remove_file(index_path) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    return Err(err);
+                }
+            }
+        }
+        self.save_catalog()?;
+        self.compact_table(&name)
+    }
+
+    pub fn get_table(&self, name: &str) -> Result<&Table> {
+        self.tables
+            .get(name)
+            .ok_or_else(|| storage_error(format!("Table '{}' doesn't exist", name)))
+    }
+
+    fn get_table_mut(&mut self, name: &str) -> Result<&mut Table> {
+        let table = self
+            .tables
+            .get_mut(name)
+            .ok_or_else(|| storage_error(format!("Table '{}' doesn't exist", name)))?;
+        if let Some(changes) = &mut self.transaction {
+            changes.entry(name.to_string()).or_insert_with(|| Undo {
+                rows: vec![],
+                next_id: table.next_id,
+                unsaved: table.unsaved.len(),
+            });
+        }
+        Ok(table)
+    }
+
+    // Creates an event or replaces the one with the same name.
+    pub fn define_event(&mut self, event: Event) -> Result<()> {
+        self.check_declarable()?;
+        let table = self.get_table(&event.table)?;
+        if let Some(column) = &event.column {
+            if !table.schema.columns.is_empty() && table.schema.get_column(column).is_none() {
+                return Err(storage_error(format!(
+                    "Column '{}.{}' doesn't exist",
+                    event.table, column
+                )));
+            }
+        }
+        if let Some(existing) = self.events.get(&event.name) {
+            check_package("Event", &event.name, &existing.package, &event.package)?;
+        }
+        self.events.insert(event.name.clone(), event);
+        self.save_catalog()
+    }
+
+    pub fn define_enum(&mut self, definition: EnumDefinition) -> Result<()> {
+        self.check_declarable()?;
+        self.enums.insert(definition.name.clone(), definition);
+        self.save_catalog()
+    }
+
+    pub fn get_enum(&self, name: &str) -> Option<&EnumDefinition> {
+        self.enums.get(name)
+    }
+
+    pub fn get_function(&self, name: &str) -> Option<&FunctionDefinition> {
+        self.functions.get(name)
+    }
+
+    pub fn define_function(&mut self, function: FunctionDefinition) -> Result<()> {
+        self.check_declarable()?;
+        self.functions.insert(function.name.clone(), function);
+        self.save_catalog()
+    }
+
+    // A snapshot of everything declared, as it's stored in the `model` folder.
+    pub fn catalog(&self) -> Catalog {
+        let mut tables: Vec<TableSchema> = self
+            .tables
+            .values()
+            .map(|table| table.schema.clone())
+            .collect();
+        tables.sort_by(|left, right| left.name.cmp(&right.name));
+        Catalog {
+            revision: self.revision,
+            tables,
+            enums: self.enums.clone(),
+            functions: self.functions.clone(),
+            events: self.events.clone(),
+            ..Catalog::default()
+        }
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    // Package files lie in the `packages` folder of the datablaze.
+    pub fn packages_path(&self) -> PathBuf {
+        self.data_path.with_file_name("packages")
+    }
+
+    fn catalog_path(&self) -> PathBuf {
+        self.model_path.join("catalog.bson")
+    }
+
+    fn access_path(&self) -> PathBuf {
+        self.model_path.join("access.bson")
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    pub fn logins(&mut self) -> &mut Logins {
+        &mut self.logins
+    }
+
+    // Users and roles are written to disk as soon as they change.
+    // A change failing halfway leaves them as they were.
+    pub fn change_access<T>(&mut self, change: impl FnOnce(&mut Access) -> Result<T>) -> Result<T> {
+        self.check_declarable()?;
+        let mut access = self.access.clone();
+        let result = change(&mut access)?;
+        access.save(&self.access_path())?;
+        self.access = access;
+        Ok(result)
+    }
+
+    fn save_catalog(&mut self) -> Result<()> {
+        self.revision += 1;
+        self.catalog().save(&self.catalog_path())
+    }
+
+    // Queues the events triggered by a written row; the executor runs them after the write.
+    fn fire(
+        &mut self,
+        table_name: &str,
+        operation: Operation,
+        old: Option<&Document>,
+        new: Option<&Document>,
+    ) {
+        let (Some(table), Some(row)) = (self.tables.get(table_name), new.or(old)) else {
+            return;
+        };
+        for event in self.events.values() {
+            if event.is_triggered_by(table_name, operation, old, new) {
+                self.fired.push(Firing {
+                    event: event.name.clone(),
+                    body: event.body.clone(),
+                    row: table.link_references(row.clone()),
+                });
+            }
+        }
+    }
+
+    pub fn take_fired(&mut self) -> Vec<Firing> {
+        std::mem::take(&mut self.fired)
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    pub fn transaction_owner(&self) -> Option<u64> {
+        self.transaction_owner
+    }
+
+    pub fn is_begun_by_session(&self) -> bool {
+        self.is_begun_by_session
+    }
+
+    pub fn begin(&mut self, session: u64, is_begun_by_session: bool) {
+        self.transaction = Some(HashMap::new());
+        self.transaction_owner = Some(session);
+        self.is_begun_by_session = is_begun_by_session;
+    }
+
+    // Writes a row of a table, keeping the one it replaces for a rollback.
+    fn write_row(&mut self, table_name: &str, row: Document) -> Result<Option<Document>> {
+        let id = row.get("id").cloned().unwrap_or(Bson::Null);
+        let old = self.get_table_mut(table_name)?.write_row(row);
+        self.keep_for_rollback(table_name, id, &old);
+        Ok(old)
+    }
+
+    fn delete_row(&mut self, table_name: &str, id: &Bson) -> Result<Option<Document>> {
+        let old = self.get_table_mut(table_name)?.delete_row(id);
+        if old.is_some() {
+            self.keep_for_rollback(table_name, id.clone(), &old);
+        }
+        Ok(old)
+    }
+
+    fn keep_for_rollback(&mut self, table_name: &str, id: Bson, old: &Option<Document>) {
+        let undo = self
+            .transaction
+            .as_mut()
+            .and_then(|changes| changes.get_mut(table_name));
+        if let Some(undo) = undo {
+            undo.rows.push((id, old.clone()));
+        }
+    }
+
+    // Declarations are written to disk as soon as they're made, so they can't be undone
+    // along with a transaction.
+    fn check_declarable(&self) -> Result<()> {
+        match self.in_transaction() {
+            true => Err(storage_error(
+                "Declarations can't be made inside a transaction",
+            )),
+            false => Ok(()),
+        }
+    }
+
+    pub fn commit(&mut self) -> Result<()> {
+        self.transaction_owner = None;
+        self.is_begun_by_session = false;
+        let Some(changes) = self.transaction.take() else {
+            return Ok(());
+        };
+        for name in changes.keys() {
+            self.save_table(name)?;
+        }
+        Ok(())
+    }
+
+    pub fn rollback(&mut self) {
+        self.transaction_owner = None;
+        self.is_begun_by_session = false;
+        self.fired.clear();
+        let Some(changes) = self.transaction.take() else {
+            return;
+        };
+        for (name, undo) in changes {
+            let Some(table) = self.tables.get_mut(&name) else {
+                continue;
+            };
+            for (id, old) in undo.rows.into_iter().rev() {
+                table.remove_row(&id);
+                if let Some(old) = old {
+                    table.put_row(old);
+                }
+            }
+            table.next_id = undo.next_id;
+            table.unsaved.truncate(undo.unsaved);
+        }
+    }
+
+    // Makes sure everything committed has reached the disk, e.g. before the server stops.
+    // A transaction still open is rolled back, since no session is left to end it.
+    pub fn flush(&mut self) -> Result<()> {
+        self.rollback();
+        for folder in [&self.data_path, &self.model_path] {
+            for entry in fs::read_dir(folder)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    File::open(&path)?.sync_all()?;
+                }
+            }
+            File::open(folder)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    // Produces the key of a new row; tables with natural keys expect it to be given.
+    pub fn generate_key(&mut self, table_name: &str) -> Result<Bson> {
+        let table = self.get_table_mut(table_name)?;
+        Ok(match table.schema.key {
+            KeyStrategy::Increment => {
+                table.next_id += 1;
+                Bson::Int64(table.next_id - 1)
+            }
+            KeyStrategy::Uuid4 => Bson::String(Uuid::new_v4().to_string()),
+            KeyStrategy::Uuid7 => Bson::String(Uuid::now_v7().to_string()),
+            KeyStrategy::Natural(_) => Bson::Null,
+        })
+    }
+
+    pub fn insert(&mut self, table_name: &str, mut row: Document) -> Result<Bson> {
+        if !row.contains_key("id") {
+            let key = self.generate_key(table_name)?;
+            if key != Bson::Null {
+                row.insert("id", key);
+            }
+        }
+        let table = self.get_table(table_name)?;
+        let id = row.get("id").cloned().unwrap_or(Bson::Null);
+        if table.rows.contains_key(&IndexKey(id.clone())) {
+            return Err(storage_error(format!(
+                "Row with id {} already exists in '{}'",
+                id, table_name
+            )));
+        }
+        self.unlink_references(table_name, &mut row)?;
+        let schema = &self.get_table(table_name)?.schema;
+        datetime::coerce_row(schema, &mut row);
+        constraints::validate_row(schema, &row)?;
+        self.write_row(table_name, row.clone())?;
+        self.fire(table_name, Operation::Insert, None, Some(&row));
+        self.save_table(table_name)?;
+        Ok(id)
+    }
+
+    pub fn update(&mut self, table_name: &str, id: &Bson, changes: Document) -> Result<()> {
+        let table = self.get_table(table_name)?;
+        let Some(mut row) = table.rows.get(&IndexKey(id.clone())).cloned() else {
+            return Err(storage_error(format!(
+                "Row with id {} doesn't exist in '{}'",
+                id, table_name
+            )));
+        };
+        if changes.contains_key("id") {
+            return Err(storage_error("Row ids can't be changed"));
+        }
+        row.extend(changes);
+        self.unlink_references(table_name, &mut row)?;
+        let schema = &self.get_table(table_name)?.schema;
+        datetime::coerce_row(schema, &mut row);
+        constraints::validate_row(schema, &row)?;
+        let old = self.write_row(table_name, row.clone())?;
+        self.fire(table_name, Operation::Change, old.as_ref(), Some(&row));
+        self.save_table(table_name)
+    }
+
+    // Stores links as the keys of the referenced rows and makes sure these rows exist.
+    fn unlink_references(&self, table_name: &str, row: &mut Document) -> Result<()> {
+        let table = self.get_table(table_name)?;
+        for (column, referenced_table) in table.schema.references() {
+            let Some(value) = row.get_mut(&column.name) else {
+                continue;
+            };
+            let ids = match value {
+                Bson::Null => continue,
+                Bson::Array(ids) => ids,
+                value => std::slice::from_mut(value),
+            };
+            for id in ids.iter_mut() {
+                if let Some((_, referenced_id)) = values::as_reference(id) {
+                    *id = referenced_id.clone();
+                }
+                let is_dangling = !self
+                    .get_table(referenced_table)?
+                    .rows
+                    .contains_key(&IndexKey(id.clone()));
+                if is_dangling {
+                    return Err(storage_error(format!(
+                        "'{}.{}' references {} which doesn't exist in '{}'",
+                        table_name, column.name, id, referenced_table
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Deletes rows along with the rows referencing them according to the `on_delete`
+    // policies of the referencing columns. Returns the number of deleted rows.
+    pub fn delete(&mut self, table_name: &str, ids: Vec<Bson>) -> Result<usize> {
+        let mut deletions: Vec<(String, Bson)> = vec![];
+        let mut nullifications: Vec<(String, Bson, String, Bson)> = vec![];
+        let mut restrictions: Vec<(String, Bson, String, Bson)> = vec![];
+        let mut pending: Vec<(String, Bson)> = ids
+            .into_iter()
+            .map(|id| (table_name.to_string(), id))
+            .collect();
+        let is_deleted = |deletions: &[(String, Bson)], table_name: &str, id: &Bson| {
+            deletions.iter().any(|(deleted_table, deleted_id)| {
+                deleted_table == table_name && values::equals(deleted_id, id)
+            })
+        };
+        while let Some((table_name, id)) = pending.pop() {
+            let is_stored = self
+                .get_table(&table_name)?
+                .rows
+                .contains_key(&IndexKey(id.clone()));
+            if !is_stored || is_deleted(&deletions, &table_name, &id) {
+                continue;
+            }
+            for table in self.tables.values() {
+                let referencing_columns = table
+                    .schema
+                    .references()
+                    .filter(|(_, referenced_table)| *referenced_table == table_name);
+                for (column, _) in referencing_columns {
+                    for row in table.rows.values() {
+                        let is_referencing = match row.get(&column.name) {
+                            Some(Bson::Array(ids)) => {
+                                ids.iter().any(|item| values::equals(item, &id))
+                            }
+                            Some(value) => values::equals(value, &id),
+                            None => false,
+                        };
+                        if !is_referencing {
+                            continue;
+                        }
+                        let row_id = row.get("id").cloned().unwrap_or(Bson::Null);
+                        let action = (table.name.clone(), row_id, column.name.clone(), id.clone());
+                        match column.on_delete {
+                            OnDelete::Restrict => restrictions.push(action),
+                            OnDelete::Cascade => pending.push((action.0, action.1)),
+                            OnDelete::Nullify => nullifications.push(action),
+                        }
+                    }
+                }
+            }
+            deletions.push((table_name, id));
+        }
+
+        for (table_name, row_id, column, id) in &restrictions {
+            if !is_deleted(&deletions, table_name, row_id) {
+                return Err(storage_error(format!(
+                    "{} can't be deleted since it's referenced by '{}.{}' of {}",
+                    id, table_name, column, row_id
+                )));
+            }
+        }
+        // Nullified rows are validated before anything is written, so that a failing
+        // deletion leaves the tables untouched.
+        let mut nullified_rows: Vec<(String, Document)> = vec![];
+        for (table_name, row_id, column, id) in nullifications {
+            if is_deleted(&deletions, &table_name, &row_id) {
+                continue;
+            }
+            let position = nullified_rows.iter().position(|(nullified_table, row)| {
+                *nullified_table == table_name
+                    && row
+                        .get("id")
+                        .is_some_and(|nullified_id| values::equals(nullified_id, &row_id))
+            });
+            let row = match position {
+                Some(position) => &mut nullified_rows[position].1,
+                None => {
+                    let Some(row) = self
+                        .get_table(&table_name)?
+                        .rows
+                        .get(&IndexKey(row_id.clone()))
+                        .cloned()
+                    else {
+                        continue;
+                    };
+                    nullified_rows.push((table_name.clone(), row));
+                    &mut nullified_rows.last_mut().unwrap().1
+                }
+            };
+            match row.get_mut(&column) {
+                Some(Bson::Array(ids)) => ids.retain(|item| !values::equals(item, &id)),
+                Some(value) => *value = Bson::Null,
+                None => {}
+            }
+        }
+        for (table_name, row) in &nullified_rows {
+            constraints::validate_row(&self.get_table(table_name)?.schema, row)?;
+        }
+        let mut affected_tables: Vec<String> = vec![];
+        for (table_name, row) in nullified_rows {
+            let old = self.write_row(&table_name, row.clone())?;
+            self.fire(&table_name, Operation::Change, old.as_ref(), Some(&row));
+            affected_tables.push(table_name);
+        }
+        for (table_name, id) in &deletions {
+            let old = self.delete_row(table_name, id)?;
+            self.fire(table_name, Operation::Delete, old.as_ref(), None);
+            affected_tables.push(table_name.clone());
+        }
+        affected_tables.sort();
+        affected_tables.dedup();
+        for table_name in affected_tables {
+            self.save_table(&table_name)?;
+        }
+        Ok(deletions.len())
+    }
+
+    pub fn create_index(&mut self, table_name: &str, column: &str) -> Result<()> {
+        self.check_declarable()?;
+        let table = self.get_table_mut(table_name)?;
+        if table.indexes.contains_key(column) {
+            return Err(storage_error(format!(
+                "Index on '{}.{}' already exists",
+                table_name, column
+            )));
+        }
+        let spatial = table.schema.column_has_type(column, "geo");
+        table
+            .indexes
+            .insert(column.to_string(), Index::new(column.to_string(), spatial));
+        table.rebuild_indexes();
+        self.compact_table(table_name)
+    }
+
+    pub fn plan(&self, query: &Query) -> Result<Plan> {
+        Ok(self.resolve_joins(query)?.1)
+    }
+
+    // Conditions of the leading filters on the rows a reference column links to become
+    // conditions on the column itself: the keys of the linked rows matching the rest of
+    // the path are selected first, so `{self.seller.country.name = "Norway"}` turns into
+    // `{self.seller = [<keys of accounts from Norway>]}`. Returns the rewritten query with its plan.
+    fn resolve_joins(&self, query: &Query) -> Result<(Query, Plan)> {
+        let table = self.get_table(&query.table)?;
+        let mut resolved = query.clone();
+        let mut joins: Vec<Join> = vec![];
+        for step in resolved.steps.iter_mut() {
+            let Step::Filter(conditions) = step else {
+                break;
+            };
+            for condition in conditions.iter_mut() {
+                let Some((column, rest)) = condition.path.split_first() else {
+                    continue;
+                };
+                let referenced_table = table
+                    .schema
+                    .get_column(column)
+                    .and_then(|column| column.referenced_table());
+                let Some(referenced_table) = referenced_table.filter(|_| !rest.is_empty()) else {
+                    continue;
+                };
+                let inner_condition =
+                    Condition::new(rest.to_vec(), condition.comparison, condition.value.clone());
+                let inner_query = Query::new(
+                    referenced_table.to_string(),
+                    vec![Step::Filter(vec![inner_condition])],
+                );
+                let (inner_query, inner_plan) = self.resolve_joins(&inner_query)?;
+                let keys = self
+                    .get_table(referenced_table)?
+                    .scan(&inner_plan)
+                    .filter(|row| passes_filters(&inner_query.steps, row))
+                    .map(|row| row.get("id").cloned().unwrap_or(Bson::Null))
+                    .collect();
+                joins.push(Join {
+                    column: format!("{}.{}", table.name, column),
+                    plan: inner_plan,
+                });
+                *condition =
+                    Condition::new(vec![column.clone()], Comparison::Equal, Bson::Array(keys));
+            }
+        }
+        let plan = planner::plan(table, &resolved);
+        let plan = match joins.is_empty() {
+            true => plan,
+            false => Plan::Join {
+                scan: Box::new(plan),
+                joins,
+            },
+        };
+        Ok((resolved, plan))
+    }
+
+    // The tables a query reads: its own, the ones its conditions and fields follow
+    // references to, and the ones whose rows it selects with `referenced_by`.
+    pub fn tables_read_by(&self, query: &Query) -> Vec<String> {
+        let mut tables = vec![query.table.clone()];
+        let mut current = Some(query.table.clone());
+        for step in &query.steps {
+            let Some(table_name) = current.clone() else {
+                break;
+            };
+            match step {
+                Step::Filter(conditions) => {
+                    for condition in conditions {
+                        let mut table_name = table_name.clone();
+                        for column in &condition.path {
+                            let Some(referenced_table) = self.referenced_table(&table_name, column)
+                            else {
+                                break;
+                            };
+                            tables.push(referenced_table.clone());
+                            table_name = referenced_table;
+                        }
+                    }
+                }
+                Step::Field(column) => {
+                    current = self.referenced_table(&table_name, column);
+                    tables.extend(current.clone());
+                }
+                Step::ReferencedBy { table, .. } => {
+                    tables.push(table.clone());
+                    current = Some(table.clone());
+                }
+                Step::Group(_) => current = None,
+                Step::Order(_) | Step::Limit(_) | Step::Offset(_) => {}
+            }
+        }
+        tables.dedup();
+        tables
+    }
+
+    fn referenced_table(&self, table_name: &str, column: &str) -> Option<String> {
+        let table = self.tables.get(table_name)?;
+        Some(
+            table
+                .schema
+                .get_column(column)?
+                .referenced_table()?
+                .to_string(),
+        )
+    }
+
+    // Checks a condition against a row of a table, following the links of reference
+    // columns the condition's path goes through. Rows linked by arrays of references
+    // match when any of them does.
+    fn matches(&self, table_name: &str, row: &Document, condition: &Condition) -> Result<bool> {
+        let table = self.get_table(table_name)?;
+        let referenced_table = condition
+            .path
+            .split_first()
+            .filter(|(_, rest)| !rest.is_empty())
+            .and_then(|(column, _)| table.schema.get_column(column)?.referenced_table());
+        let Some(referenced_table) = referenced_table else {
+            return Ok(condition.matches(row));
+        };
+        let inner_condition = Condition::new(
+            condition.path[1..].to_vec(),
+            condition.comparison,
+            condition.value.clone(),
+        );
+        let keys = match row.get(&condition.path[0]) {
+            Some(Bson::Array(keys)) => keys.clone(),
+            Some(key) => vec![key.clone()],
+            None => vec![],
+        };
+        let referenced = self.get_table(referenced_table)?;
+        for key in keys {
+            if let Some(linked_row) = referenced.rows.get(&IndexKey(key)) {
+                if self.matches(referenced_table, linked_row, &inner_condition)? {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    // Returns the row a link points to, with its own references linked as well.
+    pub fn dereference(&self, reference: &Bson) -> Result<Bson> {
+        match self.resolve_row(reference)? {
+            Some((table_name, row)) => Ok(Bson::Document(
+                self.get_table(&table_name)?.link_references(row),
+            )),
+            None => Ok(Bson::Null),
+        }
+    }
+
+    fn resolve_row(&self, reference: &Bson) -> Result<Option<(String, Document)>> {
+        let Some((table_name, id)) = values::as_reference(reference) else {
+            return Ok(None);
+        };
+        let row = self.get_table(table_name)?.rows.get(&IndexKey(id.clone()));
+        Ok(row.map(|row| (table_name.to_string(), row.clone())))
+    }
+
+    fn run_selection(&self, query: &Query) -> Result<Vec<Selected>> {
+        let table = self.get_table(&query.table)?;
+        // The filters leading a query are checked while the table is scanned,
+        // so only the matching rows are copied, or none when they're grouped.
+        let leading_filters = query
+            .steps
+            .iter()
+            .take_while(|step| matches!(step, Step::Filter(_)))
+            .count();
+        let (resolved, plan) = self.resolve_joins(query)?;
+        let (filters, mut steps) = resolved.steps.split_at(leading_filters);
+        let mut rows: Box<dyn Iterator<Item = &Document>> =
+            Box::new(table.scan(&plan).filter(|row| passes_filters(filters, row)));
+        // Rows coming in the order of the first sort key can stop coming once a limit
+        // is reached, after the rows sharing the last key with the kept ones.
+        if let (Plan::IndexOrder { .. }, Some((Step::Order(keys), rest))) =
+            (plan.base(), steps.split_first())
+        {
+            if let Some(bound) = query::row_bound(rest) {
+                let mut kept: Vec<&Document> = vec![];
+                for row in rows {
+                    let is_tied = kept.last().is_some_and(|last| {
+                        index::compare_keys(keys[0].value(last), keys[0].value(row)).is_eq()
+                    });
+                    if kept.len() >= bound && !is_tied {
+                        break;
+                    }
+                    kept.push(row);
+                }
+                rows = Box::new(kept.into_iter());
+            }
+        }
+        let mut selection: Vec<Selected> = match steps.split_first() {
+            Some((Step::Group(grouping), rest)) => {
+                steps = rest;
+                self.group(Some(table), grouping, rows)?
+            }
+            _ => rows
+                .map(|row| Selected::Row(table.name.clone(), row.clone()))
+                .collect(),
+        };
+        for step in steps {
+            // These steps work on the selection as a whole.
+            match step {
+                Step::Group(grouping) => {
+                    let mut rows = vec![];
+                    for selected in selection {
+                        rows.push(match selected {
+                            Selected::Row(_, row) => row,
+                            Selected::Value(Bson::Document(document)) => document,
+                            Selected::Value(value) => {
+                                return Err(storage_error(format!("{} can't be grouped", value)))
+                            }
+                        });
+                    }
+                    selection = self.group(None, grouping, rows.iter())?;
+                    continue;
+                }
+                Step::Order(keys) => {
+                    selection.sort_by(|left, right| match (left.document(), right.document()) {
+                        (Some(left), Some(right)) => query::compare_rows(keys, left, right),
+                        _ => Ordering::Equal,
+                    });
+                    continue;
+                }
+                Step::Limit(limit) => {
+                    selection.truncate(*limit);
+                    continue;
+                }
+                Step::Offset(offset) => {
+                    selection.drain(..selection.len().min(*offset));
+                    continue;
+                }
+                Step::ReferencedBy { table, column } => {
+                    selection = self.referencing_rows(selection, table, column)?;
+                    continue;
+                }
+                Step::Filter(_) | Step::Field(_) => {}
+            }
+            selection = self.step_each(step, selection)?;
+        }
+        Ok(selection)
+    }
+
+    // Runs a filter or field step on each element of a selection.
+    fn step_each(&self, step: &Step, selection: Vec<Selected>) -> Result<Vec<Selected>> {
+        let mut next_selection: Vec<Selected> = vec![];
+        for selected in selection {
+            // Links selected by a previous step are followed to the rows they point to.
+            let selected = match selected {
+                Selected::Value(value) => match self.resolve_row(&value)? {
+                    Some((table_name, row)) => Selected::Row(table_name, row),
+                    None => Selected::Value(value),
+                },
+                row => row,
+            };
+            match (step, selected) {
+                (Step::Filter(conditions), Selected::Row(table_name, row)) => {
+                    let mut is_matching = true;
+                    for condition in conditions {
+                        if !self.matches(&table_name, &row, condition)? {
+                            is_matching = false;
+                            break;
+                        }
+                    }
+                    if is_matching {
+                        next_selection.push(Selected::Row(table_name, row));
+                    }
+                }
+                // Groups are filtered like rows, e.g. `.group(seller){...}.{self.total > 100}`.
+                (Step::Filter(conditions), Selected::Value(Bson::Document(document))) => {
+                    if conditions
+                        .iter()
+                        .all(|condition| condition.matches(&document))
+                    {
+                        next_selection.push(Selected::Value(Bson::Document(document)));
+                    }
+                }
+                (Step::Filter(_), Selected::Value(_)) => {}
+                (Step::Field(name), Selected::Row(table_name, row)) => {
+                    let mut row = self.get_table(&table_name)?.link_references(row);
+                    match row.remove(name).unwrap_or(Bson::Null) {
+                        Bson::Array(items) => {
+                            next_selection.extend(items.into_iter().map(Selected::Value))
+                        }
+                        value => next_selection.push(Selected::Value(value)),
+                    }
+                }
+                (Step::Field(name), Selected::Value(Bson::Document(mut document))) => {
+                    next_selection
+                        .push(Selected::Value(document.remove(name).unwrap_or(Bson::Null)))
+                }
+                (Step::Field(_), Selected::Value(_)) => {
+                    next_selection.push(Selected::Value(Bson::Null))
+                }
+                (
+                    Step::Group(_)
+                    | Step::Order(_)
+                    | Step::Limit(_)
+                    | Step::Offset(_)
+                    | Step::ReferencedBy { .. },
+                    _,
+                ) => {}
+            }
+        }
+        Ok(next_selection)
+    }
+
+    // `.referenced_by(products.seller)` selects the rows of `products` whose `seller`
+    // links to any of the selected rows, looked up in the index of `seller` when there is one.
+    fn referencing_rows(
+        &self,
+        selection: Vec<Selected>,
+        table_name: &str,
+        column: &str,
+    ) -> Result<Vec<Selected>> {
+        let referenced_table = self
+            .get_table(table_name)?
+            .schema
+            .get_column(column)
+            .and_then(|column| column.referenced_table())
+            .ok_or_else(|| {
+                storage_error(format!(
+                    "'{}.{}' isn't a reference column",
+                    table_name, column
+                ))
+            })?;
+        let mut keys: Vec<Bson> = vec![];
+        for selected in selection {
+            let (selected_table, key) = match selected {
+                Selected::Row(selected_table, row) => {
+                    (selected_table, row.get("id").cloned().unwrap_or(Bson::Null))
+                }
+                Selected::Value(value) => match values::as_reference(&value) {
+                    Some((selected_table, key)) => (selected_table.to_string(), key.clone()),
+                    None => continue,
+                },
+            };
+            if selected_table == referenced_table {
+                keys.push(key);
+            }
+        }
+        let condition = Condition::new(
+            vec![column.to_string()],
+            Comparison::Equal,
+            Bson::Array(keys),
+        );
+        self.run_selection(&Query::new(
+            table_name.to_string(),
+            vec![Step::Filter(vec![condition])],
+        ))
+    }
+
+    // Group keys taken from reference columns of a scanned table are linked like the columns.
+    fn group<'a>(
+        &self,
+        table: Option<&Table>,
+        grouping: &Grouping,
+        rows: impl Iterator<Item = &'a Document>,
+    ) -> Result<Vec<Selected>> {
+        let groups = grouping.group(rows).map_err(storage_error)?;
+        Ok(groups
+            .into_iter()
+            .map(|group| match table {
+                Some(table) => Selected::Value(Bson::Document(table.link_references(group))),
+                None => Selected::Value(Bson::Document(group)),
+            })
+            .collect())
+    }
+
+    pub fn select(&self, query: &Query) -> Result<Vec<Bson>> {
+        self.run_selection(query)?
+            .into_iter()
+            .map(|selected| self.selected_value(selected))
+            .collect()
+    }
+
+    // Selects the next values of a query a cursor runs lazily, whose steps are all
+    // filters and fields, stopping once there are `size` of them. Only the rows of the
+    // batch are copied, however large the table is.
+    pub fn select_batch(
+        &self,
+        query: &Query,
+        resume: &mut Resume,
+        size: usize,
+    ) -> Result<Vec<Bson>> {
+        let table = self.get_table(&query.table)?;
+        let leading_filters = query
+            .steps
+            .iter()
+            .take_while(|step| matches!(step, Step::Filter(_)))
+            .count();
+        let (resolved, plan) = self.resolve_joins(query)?;
+        let (filters, steps) = resolved.steps.split_at(leading_filters);
+        let mut values = vec![];
+        let last_row = resume.last_row.take();
+        let after = last_row.as_ref().map(|(key, id)| (key, id));
+        for row in table.scan_from(&plan, after) {
+            resume.last_row = Some(table.scan_position(&plan, row));
+            if !passes_filters(filters, row) {
+                continue;
+            }
+            let mut selection = vec![Selected::Row(table.name.clone(), row.clone())];
+            for step in steps {
+                selection = self.step_each(step, selection)?;
+            }
+            for selected in selection {
+                values.push(self.selected_value(selected)?);
+            }
+            if values.len() >= size {
+                return Ok(values);
+            }
+        }
+        resume.is_done = true;
+        Ok(values)
+    }
+
+    fn selected_value(&self, selected: Selected) -> Result<Bson> {
+        match selected {
+            Selected::Row(table_name, row) => Ok(Bson::Document(
+                self.get_table(&table_name)?.link_references(row),
+            )),
+            Selected::Value(value) => Ok(value),
+        }
+    }
+
+    // Sets a column of rows picked by `selected_rows`. Returns the number of updated rows.
+    pub fn update_rows(
+        &mut self,
+        rows: &[(String, Bson)],
+        column: &str,
+        value: Bson,
+    ) -> Result<usize> {
+        for (table_name, id) in rows {
+            self.update(table_name, id, doc! { column: value.clone() })?;
+        }
+        Ok(rows.len())
+    }
+
+    // Deletes rows picked by `selected_rows`.
+    pub fn delete_rows(&mut self, rows: Vec<(String, Bson)>) -> Result<usize> {
+        let mut deleted_count = 0;
+        for (table_name, id) in rows {
+            deleted_count += self.delete(&table_name, vec![id])?;
+        }
+        Ok(deleted_count)
+    }
+
+    // The tables and ids of the rows a query selects, including rows of other tables
+    // reached through links, e.g. the accounts of `&products.seller`.
+    pub fn selected_rows(&self, query: &Query) -> Result<Vec<(String, Bson)>> {
+        let mut rows: Vec<(String, Bson)> = vec![];
+        for selected in self.run_selection(query)? {
+            let (table_name, row) = match selected {
+                Selected::Row(table_name, row) => (table_name, row),
+                Selected::Value(Bson::Null) => continue,
+                Selected::Value(value) => match self.resolve_row(&value)? {
+                    Some(resolved) => resolved,
+                    None => return Err(storage_error(format!("{} isn't a row of a table", value))),
+                },
+            };
+            rows.push((table_name, row.get("id").cloned().unwrap_or(Bson::Null)));
+        }
+        Ok(rows)
+    }
+
+    // Appends the rows written to a table to its log, so a write costs as much as the
+    // rows it changes. A log grown longer than the table is folded into the table file.
+    fn save_table(&mut self, name: &str) -> Result<()> {
+        let is_deferred = self
+            .transaction
+            .as_ref()
+            .is_some_and(|changes| changes.contains_key(name));
+        if is_deferred {
+            return Ok(());
+        }
+        let log_path = self.data_path.join(format!("{}.log", name));
+        let table = self
+            .tables
+            .get_mut(name)
+            .ok_or_else(|| storage_error(format!("Table '{}' doesn't exist", name)))?;
+        if table.logged + table.unsaved.len() > table.rows.len().max(MIN_COMPACTED_LOG) {
+            return self.compact_table(name);
+        }
+        let log_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_path)?;
+        let mut writer = BufWriter::new(log_file);
+        for record in &table.unsaved {
+            record.to_writer(&mut writer).map_err(storage_error)?;
+        }
+        writer.flush()?;
+        table.logged += table.unsaved.len();
+        table.unsaved.clear();
+        Ok(())
+    }
+
+    // Writes the rows, indexes and sequence of a table whole, and drops its log once
+    // they've all taken the place of the old files.
+    fn compact_table(&mut self, name: &str) -> Result<()> {
+        let table = self.get_table(name)?;
+        replace_file(&self.data_path.join(format!("{}.bson", name)), |writer| {
+            for row in table.rows.values() {
+                row.to_writer(&mut *writer).map_err(storage_error)?;
+            }
+            Ok(())
+        })?;
+        for (column, index) in &table.indexes {
+            let index_path = self.data_path.join(format!("{}.{}.idx", name, column));
+            replace_file(&index_path, |writer| index.save(writer))?;
+        }
+        if table.schema.key == KeyStrategy::Increment {
+            let sequence_path = self.data_path.join(format!("{}.seq", name));
+            replace_file(&sequence_path, |writer| {
+                doc! {"next_id": table.next_id}
+                    .to_writer(writer)
+                    .map_err(storage_error)
+            })?;
+        }
+        if let Err(err) = fs::remove_file(self.data_path.join(format!("{}.log", name))) {
+            if err.kind() != io::ErrorKind::NotFound {
+                return Err(err);
+            }
+        }
+        if let Some(table) = self.tables.get_mut(name) {
+            table.unsaved.clear();
+            table.logged = 0;
+        }
+        Ok(())
+    }
+
+    // Rows of a table with no schema in the `model` folder get an empty one. The records
+    // of a log file are applied to the rows read from the table file.
+    fn read_rows(&mut self, path: &Path, is_log: bool) -> Result<()> {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| storage_error(format!("Invalid table file {}", path.display())))?;
+        let table = self
+            .tables
+            .entry(name.to_string())
+            .or_insert_with(|| Table::new(TableSchema::new(name.to_string(), vec![])));
+        let mut reader = BufReader::new(File::open(path)?);
+        if !is_log {
+            while !reader.fill_buf()?.is_empty() {
+                let row = Document::from_reader(&mut reader).map_err(storage_error)?;
+                table.put_row(row);
+            }
+            return Ok(());
+        }
+        while !reader.fill_buf()?.is_empty() {
+            let position = reader.stream_position()?;
+            // A record left half-written by a crash ends the log, and is cut off so
+            // that the next writes are appended after the whole ones.
+            let Ok(record) = Document::from_reader(&mut reader) else {
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)?
+                    .set_len(position)?;
+                break;
+            };
+            table.replay(record)?;
+        }
+        // The index files may be older or newer than the rows the log was replayed on,
+        // when compacting the table was cut short, so the indexes are made anew.
+        if table.logged > 0 {
+            table.rebuild_indexes();
+        }
+        Ok(())
+    }
+}
+
+// Tables and events of every package share one catalog, so a name declared by one
+// package can't be declared again by another one, or outside of packages.
+pub fn check_package(
+    kind: &str,
+    name: &str,
+    owner: &Option<String>,
+    declarer: &Option<String>,
+) -> Result<()> {
+    if owner == declarer {
+        return Ok(());
+    }
+    Err(storage_error(format!(
+        "{} '{}' is declared {}, so it can't be declared {}",
+        kind,
+        name,
+        match owner {
+            Some(package) => format!("by package {}", package),
+            None => "outside of packages".to_string(),
+        },
+        match declarer {
+            Some(package) => format!("by package {}", package),
+            None => "outside of it".to_string(),
+        }
+    )))
+}
+
+// Writes a file next to the one at `path` and renames it into place once it has reached
+// the disk, so that a crash leaves either the old file or the new one whole.
+fn replace_file(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<()> {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    let mut writer = BufWriter::new(File::create(&temporary_path)?);
+    write(&mut writer)?;
+    writer
+        .into_inner()
+        .map_err(|err| err.into_error())?
+        .sync_all()?;
+    fs::rename(temporary_path, path)
+}
+
+pub fn storage_error(message: impl std::fmt::Display) -> io::Error {
+    io::Error::other(format!("{}: {}", "Storage Error".bright_red(), message))
+}
+
+fn passes_filters(steps: &[Step], row: &Document) -> bool {
+    steps.iter().all(|step| match step {
+        Step::Filter(conditions) => conditions.iter().all(|condition| condition.matches(row)),
+        _ => true,
+    })
+}
@@ -0,0 +1,85 @@
+use bson::{doc, Bson};
+use std::cmp::Ordering;
+
+use super::datetime;
+
+pub fn as_f64(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(number) => Some(*number as f64),
+        Bson::Int64(number) => Some(*number as f64),
+        Bson::Double(number) => Some(*number),
+        _ => None,
+    }
+}
+
+// Links between rows follow the DBRef convention: `{"$ref": <table>, "$id": <key>}`.
+pub fn reference(table: &str, id: Bson) -> Bson {
+    Bson::Document(doc! { "$ref": table, "$id": id })
+}
+
+pub fn as_reference(value: &Bson) -> Option<(&str, &Bson)> {
+    match value {
+        Bson::Document(document) if document.len() == 2 => {
+            Some((document.get_str("$ref").ok()?, document.get("$id")?))
+        }
+        _ => None,
+    }
+}
+
+// Values of different numeric types are compared by their magnitude,
+// while values of unrelated types are considered incomparable.
+// Links are compared by the keys of the rows they point to.
+pub fn compare(left: &Bson, right: &Bson) -> Option<Ordering> {
+    let left = as_reference(left).map_or(left, |(_, id)| id);
+    let right = as_reference(right).map_or(right, |(_, id)| id);
+    if let (Some(left), Some(right)) = (as_f64(left), as_f64(right)) {
+        return left.partial_cmp(&right);
+    }
+    match (left, right) {
+        (Bson::String(left), Bson::String(right)) => Some(left.cmp(right)),
+        (Bson::Boolean(left), Bson::Boolean(right)) => Some(left.cmp(right)),
+        (Bson::DateTime(left), Bson::DateTime(right)) => Some(left.cmp(right)),
+        // Datetimes are compared with ISO-8601 strings as with the datetimes they stand for.
+        (Bson::DateTime(left), Bson::String(right)) => Some(left.cmp(&datetime::from_text(right)?)),
+        (Bson::String(left), Bson::DateTime(right)) => Some(datetime::from_text(left)?.cmp(right)),
+        (Bson::Null, Bson::Null) => Some(Ordering::Equal),
+        (left, right) if left == right => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+pub fn equals(left: &Bson, right: &Bson) -> bool {
+    compare(left, right) == Some(Ordering::Equal)
+}
+
+pub fn is_truthy(value: &Bson) -> bool {
+    match value {
+        Bson::Null => false,
+        Bson::Boolean(state) => *state,
+        Bson::String(string) => !string.is_empty(),
+        Bson::Array(array) => !array.is_empty(),
+        value => as_f64(value).is_none_or(|number| number != 0.0),
+    }
+}
+
+// Converts a value to a column type when no information is lost on the way,
+// e.g. `2.0` becomes the integer `2` while `2.5` can't become one.
+pub fn convert(value: &Bson, datatype: &str) -> Option<Bson> {
+    match (datatype, value) {
+        (_, Bson::Null) => Some(Bson::Null),
+        ("int", Bson::Int32(number)) => Some(Bson::Int64(*number as i64)),
+        ("int", Bson::Int64(_)) => Some(value.clone()),
+        ("int", Bson::Double(number)) if number.fract() == 0.0 => Some(Bson::Int64(*number as i64)),
+        ("int", Bson::String(string)) => string.trim().parse().ok().map(Bson::Int64),
+        ("float", Bson::String(string)) => string.trim().parse().ok().map(Bson::Double),
+        ("float", value) => as_f64(value).map(Bson::Double),
+        ("str", Bson::String(_)) => Some(value.clone()),
+        ("str", Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Boolean(_)) => {
+            Some(Bson::String(value.to_string()))
+        }
+        ("bool", Bson::Boolean(_)) => Some(value.clone()),
+        ("bool", Bson::String(string)) => string.parse().ok().map(Bson::Boolean),
+        ("datetime", value) => datetime::coerce(value),
+        _ => None,
+    }
+}
@@ -1,3 +1,4 @@
+pub mod client;
 pub mod db;
 pub mod scripting;
 pub mod server;
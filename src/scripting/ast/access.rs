@@ -0,0 +1,72 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::access::{Grant, User};
+use crate::scripting::executor::{runtime_error, Executor};
+
+use super::expression::ExpressionNode;
+
+pub enum AccessStatement {
+    // `user alice = "password": analysts, auditors`
+    User {
+        name: String,
+        password: Box<dyn ExpressionNode>,
+        roles: Vec<String>,
+    },
+    // `role analysts`
+    Role(String),
+    // `grant read, write on table sales to analysts`
+    Grant {
+        grants: Vec<Grant>,
+        role: String,
+    },
+    // `revoke write on table sales from analysts`
+    Revoke {
+        grants: Vec<Grant>,
+        role: String,
+    },
+}
+
+// Statements managing users and roles, which only admins may run.
+pub struct AccessNode {
+    statement: AccessStatement,
+}
+
+impl AccessNode {
+    pub fn new(statement: AccessStatement) -> Self {
+        AccessNode { statement }
+    }
+}
+
+impl ExpressionNode for AccessNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        executor.authorize_admin("manage users and roles")?;
+        let password = match &self.statement {
+            AccessStatement::User { password, .. } => match password.evaluate(executor)? {
+                Bson::String(password) => password,
+                value => {
+                    return Err(runtime_error(format!(
+                        "A password is expected to be a string, but {} is given",
+                        value
+                    )))
+                }
+            },
+            _ => String::new(),
+        };
+        executor
+            .lock_storage()?
+            .change_access(|access| match &self.statement {
+                AccessStatement::User { name, roles, .. } => {
+                    access.set_user(User::new(name, &password, roles.clone()))
+                }
+                AccessStatement::Role(name) => access.define_role(name),
+                AccessStatement::Grant { grants, role } => grants
+                    .iter()
+                    .try_for_each(|grant| access.grant(role, grant.clone())),
+                AccessStatement::Revoke { grants, role } => grants
+                    .iter()
+                    .try_for_each(|grant| access.revoke(role, grant)),
+            })?;
+        Ok(Bson::Null)
+    }
+}
@@ -1,11 +1,17 @@
+use bson::Bson;
+use std::cmp::Ordering;
+use std::io::Result;
+
+use crate::db::{datetime, values};
+use crate::scripting::executor::{runtime_error, Executor};
 use crate::scripting::tokens::TokenType;
 
 use super::expression::ExpressionNode;
 
 pub struct BinaryOperatorNode {
-    _operator: TokenType,
-    _left_operand: Box<dyn ExpressionNode>,
-    _right_operand: Box<dyn ExpressionNode>,
+    operator: TokenType,
+    left_operand: Box<dyn ExpressionNode>,
+    right_operand: Box<dyn ExpressionNode>,
 }
 
 impl BinaryOperatorNode {
@@ -15,11 +21,96 @@ impl BinaryOperatorNode {
         right_operand: Box<dyn ExpressionNode>,
     ) -> Self {
         BinaryOperatorNode {
-            _operator: operator,
-            _left_operand: left_operand,
-            _right_operand: right_operand,
+            operator,
+            left_operand,
+            right_operand,
+        }
+    }
+
+    fn calculate(&self, left: Bson, right: Bson) -> Result<Bson> {
+        let ordering = values::compare(&left, &right);
+        match (&self.operator, left, right) {
+            (TokenType::EqualSign, ..) => Ok(Bson::Boolean(ordering == Some(Ordering::Equal))),
+            (TokenType::NotEqualSign, ..) => Ok(Bson::Boolean(ordering != Some(Ordering::Equal))),
+            (TokenType::Greater, ..) => Ok(Bson::Boolean(ordering == Some(Ordering::Greater))),
+            (TokenType::Less, ..) => Ok(Bson::Boolean(ordering == Some(Ordering::Less))),
+            (TokenType::GreaterOrEqual, ..) => Ok(Bson::Boolean(matches!(
+                ordering,
+                Some(Ordering::Greater | Ordering::Equal)
+            ))),
+            (TokenType::LessOrEqual, ..) => Ok(Bson::Boolean(matches!(
+                ordering,
+                Some(Ordering::Less | Ordering::Equal)
+            ))),
+            // Durations are added to and subtracted from datetimes as milliseconds.
+            (TokenType::Addition, Bson::DateTime(datetime), Bson::Int64(duration))
+            | (TokenType::Addition, Bson::Int64(duration), Bson::DateTime(datetime)) => {
+                datetime::add(datetime, duration)
+                    .map(Bson::DateTime)
+                    .ok_or_else(|| runtime_error("Datetime overflow"))
+            }
+            (TokenType::Subtraction, Bson::DateTime(datetime), Bson::Int64(duration)) => duration
+                .checked_neg()
+                .and_then(|duration| datetime::add(datetime, duration))
+                .map(Bson::DateTime)
+                .ok_or_else(|| runtime_error("Datetime overflow")),
+            (TokenType::Subtraction, Bson::DateTime(left), Bson::DateTime(right)) => left
+                .timestamp_millis()
+                .checked_sub(right.timestamp_millis())
+                .map(Bson::Int64)
+                .ok_or_else(|| runtime_error("Integer overflow")),
+            (TokenType::Addition, Bson::String(left), right) => Ok(Bson::String(match right {
+                Bson::String(right) => left + &right,
+                right => format!("{}{}", left, right),
+            })),
+            (TokenType::Addition, Bson::Int64(left), Bson::Int64(right)) => left
+                .checked_add(right)
+                .map(Bson::Int64)
+                .ok_or_else(|| runtime_error("Integer overflow")),
+            (TokenType::Subtraction, Bson::Int64(left), Bson::Int64(right)) => left
+                .checked_sub(right)
+                .map(Bson::Int64)
+                .ok_or_else(|| runtime_error("Integer overflow")),
+            (TokenType::Multiplication, Bson::Int64(left), Bson::Int64(right)) => left
+                .checked_mul(right)
+                .map(Bson::Int64)
+                .ok_or_else(|| runtime_error("Integer overflow")),
+            (operator, left, right) => {
+                let (Some(left_number), Some(right_number)) =
+                    (values::as_f64(&left), values::as_f64(&right))
+                else {
+                    return Err(runtime_error(format!(
+                        "'{}' operator can't be applied to {} and {}",
+                        operator, left, right
+                    )));
+                };
+                match operator {
+                    TokenType::Addition => Ok(Bson::Double(left_number + right_number)),
+                    TokenType::Subtraction => Ok(Bson::Double(left_number - right_number)),
+                    TokenType::Multiplication => Ok(Bson::Double(left_number * right_number)),
+                    TokenType::Division if right_number == 0.0 => {
+                        Err(runtime_error("Division by zero"))
+                    }
+                    TokenType::Division => Ok(Bson::Double(left_number / right_number)),
+                    TokenType::Hat => Ok(Bson::Double(left_number.powf(right_number))),
+                    _ => Err(runtime_error(format!(
+                        "'{}' operator isn't supported yet",
+                        operator
+                    ))),
+                }
+            }
         }
     }
 }
 
-impl ExpressionNode for BinaryOperatorNode {}
+impl ExpressionNode for BinaryOperatorNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        if self.operator == TokenType::Assign {
+            let value = self.right_operand.evaluate(executor)?;
+            return self.left_operand.assign(value, executor);
+        }
+        let left = self.left_operand.evaluate(executor)?;
+        let right = self.right_operand.evaluate(executor)?;
+        self.calculate(left, right)
+    }
+}
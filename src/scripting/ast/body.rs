@@ -1,3 +1,9 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::cursor::Cursor;
+use crate::scripting::executor::{runtime_error, Executor};
+
 use super::expression::ExpressionNode;
 
 pub struct BodyNode {
@@ -14,7 +20,27 @@ impl BodyNode {
     }
 }
 
-impl ExpressionNode for BodyNode {}
+impl ExpressionNode for BodyNode {
+    // The value of a body is the value of its last expression.
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        let mut value = Bson::Null;
+        for node in &self.nodes {
+            value = node.evaluate(executor)?;
+        }
+        Ok(value)
+    }
+
+    // Only the last expression goes through the cursor.
+    fn open_cursor(&self, executor: &mut Executor) -> Result<Cursor> {
+        let Some((last, rest)) = self.nodes.split_last() else {
+            return Err(runtime_error("A cursor can't go through an empty script"));
+        };
+        for node in rest {
+            node.evaluate(executor)?;
+        }
+        last.open_cursor(executor)
+    }
+}
 
 impl Default for BodyNode {
     fn default() -> Self {
@@ -1,27 +1,30 @@
+use bson::Bson;
 use std::io;
 
+use crate::scripting::executor::Executor;
 use crate::scripting::tokens::TokenType;
 
 use super::expression::ExpressionNode;
 
 pub struct BooleanNode {
-    _state: bool,
+    state: bool,
 }
 
 impl BooleanNode {
     pub fn new(token_type: TokenType) -> Result<Self, io::Error> {
         match token_type {
-            TokenType::True => Ok(BooleanNode { _state: true }),
-            TokenType::False => Ok(BooleanNode { _state: false }),
-            _ => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Invalid value for a boolean node",
-            )),
+            TokenType::True => Ok(BooleanNode { state: true }),
+            TokenType::False => Ok(BooleanNode { state: false }),
+            _ => Err(io::Error::other("Invalid value for a boolean node")),
         }
     }
 }
 
-impl ExpressionNode for BooleanNode {}
+impl ExpressionNode for BooleanNode {
+    fn evaluate(&self, _executor: &mut Executor) -> io::Result<Bson> {
+        Ok(Bson::Boolean(self.state))
+    }
+}
 
 impl Default for BooleanNode {
     fn default() -> Self {
@@ -1,3 +1,9 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::scripting::executor::Executor;
+use crate::scripting::functions;
+
 use super::{expression::ExpressionNode, parameter::Parameters};
 
 pub enum CallType {
@@ -7,18 +13,32 @@ pub enum CallType {
 }
 
 pub struct CallNode {
-    _callee: Box<dyn ExpressionNode>,
-    _arguments: Parameters,
+    name: String,
+    arguments: Parameters,
     _call_type: CallType,
 }
 
-impl ExpressionNode for CallNode {}
+impl ExpressionNode for CallNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        let mut arguments: Vec<Bson> = vec![];
+        for argument in &self.arguments {
+            if let Some(value) = argument.value() {
+                arguments.push(value.evaluate(executor)?);
+            }
+        }
+        // Functions of the standard library come first, then the declared ones.
+        match functions::get(&self.name) {
+            Some(function) => functions::call(function, &arguments),
+            None => executor.call_function(&self.name, arguments),
+        }
+    }
+}
 
 impl CallNode {
-    pub fn new(callee: Box<dyn ExpressionNode>, arguments: Parameters) -> Self {
+    pub fn new(name: String, arguments: Parameters) -> Self {
         CallNode {
-            _callee: callee,
-            _arguments: arguments,
+            name,
+            arguments,
             _call_type: CallType::Function,
         }
     }
@@ -0,0 +1,27 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::scripting::executor::Executor;
+
+use super::expression::ExpressionNode;
+use super::query::QueryNode;
+
+// `delete &table.{...}` deletes the selected rows and evaluates to their count.
+pub struct DeletionNode {
+    query: QueryNode,
+}
+
+impl DeletionNode {
+    pub fn new(query: QueryNode) -> Self {
+        DeletionNode { query }
+    }
+}
+
+impl ExpressionNode for DeletionNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        let query = self.query.resolve(executor)?;
+        let rows = executor.writable_rows(&query)?;
+        let deleted_count = executor.write(|storage| storage.delete_rows(rows))?;
+        Ok(Bson::Int64(deleted_count as i64))
+    }
+}
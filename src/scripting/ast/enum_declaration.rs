@@ -0,0 +1,28 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::schema::EnumDefinition;
+use crate::scripting::executor::Executor;
+
+use super::expression::ExpressionNode;
+
+pub struct EnumDeclarationNode {
+    definition: EnumDefinition,
+}
+
+impl EnumDeclarationNode {
+    pub fn new(definition: EnumDefinition) -> Self {
+        EnumDeclarationNode { definition }
+    }
+}
+
+impl ExpressionNode for EnumDeclarationNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        executor.authorize_declaration(&self.definition.name)?;
+        executor
+            .lock_storage()?
+            .define_enum(self.definition.clone())?;
+        executor.declare(&self.definition.name);
+        Ok(Bson::Null)
+    }
+}
@@ -0,0 +1,31 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::events::Event;
+use crate::scripting::executor::Executor;
+
+use super::expression::ExpressionNode;
+
+// `event <name> (&<table>[.<column>], "<operation>") { ... }` declares a trigger.
+pub struct EventDeclarationNode {
+    event: Event,
+}
+
+impl EventDeclarationNode {
+    pub fn new(event: Event) -> Self {
+        EventDeclarationNode { event }
+    }
+}
+
+impl ExpressionNode for EventDeclarationNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        executor.authorize_declaration(&self.event.name)?;
+        let event = Event {
+            package: executor.package().map(str::to_string),
+            ..self.event.clone()
+        };
+        executor.lock_storage()?.define_event(event)?;
+        executor.declare(&self.event.name);
+        Ok(Bson::Null)
+    }
+}
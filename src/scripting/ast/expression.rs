@@ -1 +1,31 @@
-pub trait ExpressionNode {}
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::cursor::Cursor;
+use crate::scripting::executor::{runtime_error, Executor};
+
+pub trait ExpressionNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson>;
+
+    // Resolves the node as a child of a member expression, e.g. `name` in `account.name`.
+    fn access(&self, _parent: Bson, _executor: &mut Executor) -> Result<Bson> {
+        Err(runtime_error("The expression can't be used as a member"))
+    }
+
+    // Called when the node is the left operand of `=`.
+    fn assign(&self, _value: Bson, _executor: &mut Executor) -> Result<Bson> {
+        Err(runtime_error("The expression can't be assigned"))
+    }
+
+    // Called when the node is the result of a script run through a cursor. Queries
+    // override it to be run lazily; other results have to be arrays.
+    fn open_cursor(&self, executor: &mut Executor) -> Result<Cursor> {
+        match self.evaluate(executor)? {
+            Bson::Array(values) => Ok(Cursor::for_values(values)),
+            value => Err(runtime_error(format!(
+                "A cursor goes through an array, but the script results in {}",
+                value
+            ))),
+        }
+    }
+}
@@ -1,20 +1,52 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::schema::FunctionDefinition;
+use crate::scripting::executor::Executor;
+
 use super::expression::ExpressionNode;
 use super::parameter::Parameters;
 
 pub struct FunctionDeclarationNode {
-    _name: String,
-    _datatype: Option<String>,
-    _arguments: Parameters,
+    name: String,
+    datatype: Option<String>,
+    arguments: Parameters,
+    body: Option<String>,
 }
 
 impl FunctionDeclarationNode {
-    pub fn new(name: String, datatype: Option<String>, arguments: Parameters) -> Self {
+    pub fn new(
+        name: String,
+        datatype: Option<String>,
+        arguments: Parameters,
+        body: Option<String>,
+    ) -> Self {
         FunctionDeclarationNode {
-            _name: name,
-            _datatype: datatype,
-            _arguments: arguments,
+            name,
+            datatype,
+            arguments,
+            body,
         }
     }
 }
 
-impl ExpressionNode for FunctionDeclarationNode {}
+impl ExpressionNode for FunctionDeclarationNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        executor.authorize_declaration(&self.name)?;
+        let parameters = self
+            .arguments
+            .iter()
+            .map(|argument| (argument.name().to_string(), argument.datatype().cloned()))
+            .collect();
+        executor
+            .lock_storage()?
+            .define_function(FunctionDefinition {
+                name: self.name.clone(),
+                parameters,
+                returns: self.datatype.clone(),
+                body: self.body.clone(),
+            })?;
+        executor.declare(&self.name);
+        Ok(Bson::Null)
+    }
+}
@@ -1,13 +1,59 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::access::{Permission, Resource};
+use crate::db::values;
+use crate::scripting::executor::{runtime_error, Executor};
+
 use super::expression::ExpressionNode;
 
 pub struct IdentifierNode {
-    _name: String,
+    name: String,
 }
 
-impl ExpressionNode for IdentifierNode {}
+impl ExpressionNode for IdentifierNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        executor.get_variable(&self.name)
+    }
+
+    fn access(&self, parent: Bson, executor: &mut Executor) -> Result<Bson> {
+        self.access_field(parent, executor)
+    }
+
+    fn assign(&self, value: Bson, executor: &mut Executor) -> Result<Bson> {
+        executor.get_variable(&self.name)?;
+        executor.set_variable(&self.name, value);
+        Ok(Bson::Null)
+    }
+}
 
 impl IdentifierNode {
     pub fn new(name: String) -> Self {
-        IdentifierNode { _name: name }
+        IdentifierNode { name }
+    }
+
+    // Accessing a field of a selection picks it from every selected row,
+    // while accessing a field of a link walks to the row it points to.
+    fn access_field(&self, parent: Bson, executor: &mut Executor) -> Result<Bson> {
+        let parent = match values::as_reference(&parent) {
+            Some((table, _)) => {
+                executor.authorize(Permission::Read, Resource::Table(table.to_string()))?;
+                executor.lock_storage()?.dereference(&parent)?
+            }
+            None => parent,
+        };
+        match parent {
+            Bson::Document(mut document) => Ok(document.remove(&self.name).unwrap_or(Bson::Null)),
+            Bson::Array(items) => Ok(Bson::Array(
+                items
+                    .into_iter()
+                    .map(|item| self.access_field(item, executor))
+                    .collect::<Result<_>>()?,
+            )),
+            value => Err(runtime_error(format!(
+                "'{}' has no member '{}'",
+                value, self.name
+            ))),
+        }
     }
 }
@@ -0,0 +1,26 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::scripting::executor::Executor;
+
+use super::expression::ExpressionNode;
+
+// `import users:all, animals:species` loads packages and brings their items in.
+pub struct ImportNode {
+    items: Vec<(String, String)>,
+}
+
+impl ImportNode {
+    pub fn new(items: Vec<(String, String)>) -> Self {
+        ImportNode { items }
+    }
+}
+
+impl ExpressionNode for ImportNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        for (package, item) in &self.items {
+            executor.import(package, item)?;
+        }
+        Ok(Bson::Null)
+    }
+}
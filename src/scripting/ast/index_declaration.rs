@@ -0,0 +1,28 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::scripting::executor::Executor;
+
+use super::expression::ExpressionNode;
+
+// `index <table>.<column>` builds a secondary index used by the query planner.
+pub struct IndexDeclarationNode {
+    table: String,
+    column: String,
+}
+
+impl IndexDeclarationNode {
+    pub fn new(table: String, column: String) -> Self {
+        IndexDeclarationNode { table, column }
+    }
+}
+
+impl ExpressionNode for IndexDeclarationNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        executor.authorize_declaration(&format!("{}.{}", self.table, self.column))?;
+        executor
+            .lock_storage()?
+            .create_index(&self.table, &self.column)?;
+        Ok(Bson::Null)
+    }
+}
@@ -0,0 +1,28 @@
+use bson::{Bson, Document};
+use std::io::Result;
+
+use crate::scripting::executor::Executor;
+
+use super::expression::ExpressionNode;
+
+// `&table(column = value, ...)` inserts a row and evaluates to its key.
+pub struct InsertionNode {
+    table: String,
+    values: Vec<(String, Box<dyn ExpressionNode>)>,
+}
+
+impl InsertionNode {
+    pub fn new(table: String, values: Vec<(String, Box<dyn ExpressionNode>)>) -> Self {
+        InsertionNode { table, values }
+    }
+}
+
+impl ExpressionNode for InsertionNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        let mut row = Document::new();
+        for (column, value) in &self.values {
+            row.insert(column, value.evaluate(executor)?);
+        }
+        executor.insert_row(&self.table, row)
+    }
+}
@@ -0,0 +1,59 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::access::{Permission, Resource};
+use crate::scripting::executor::Executor;
+use crate::scripting::functions;
+
+use super::expression::ExpressionNode;
+use super::query::QueryNode;
+
+pub enum InspectTarget {
+    Query(QueryNode),
+    // `inspect all` lists everything held by the catalog.
+    All,
+    // A table, or a built-in function whose signature is shown.
+    Name(String),
+}
+
+// `inspect &table.{...}` explains how a query would be executed instead of running it,
+// while `inspect all` and `inspect <name>` describe what's declared.
+pub struct InspectNode {
+    target: InspectTarget,
+}
+
+impl InspectNode {
+    pub fn new(target: InspectTarget) -> Self {
+        InspectNode { target }
+    }
+}
+
+impl ExpressionNode for InspectNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        match &self.target {
+            InspectTarget::Query(query_node) => {
+                let query = query_node.resolve(executor)?;
+                executor.authorize_query(&query)?;
+                let plan = executor.lock_storage()?.plan(&query)?;
+                Ok(Bson::String(plan.to_string()))
+            }
+            InspectTarget::All => {
+                executor.authorize_admin("inspect everything")?;
+                let catalog = executor.lock_storage()?.catalog();
+                Ok(Bson::String(catalog.to_string()))
+            }
+            InspectTarget::Name(name) => {
+                let is_table = executor.lock_storage()?.get_table(name).is_ok();
+                if is_table {
+                    executor.authorize(Permission::Read, Resource::Table(name.clone()))?;
+                }
+                let storage = executor.lock_storage()?;
+                let schema = match (storage.get_table(name), functions::get(name)) {
+                    (Err(_), Some(function)) => return Ok(Bson::String(function.to_string())),
+                    (table, _) => &table?.schema,
+                };
+                Ok(Bson::String(schema.to_string()))
+            }
+        }
+    }
+}
@@ -1,17 +1,29 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::scripting::executor::Executor;
+
 use super::expression::ExpressionNode;
 
 pub struct MemberNode {
-    _parent: Box<dyn ExpressionNode>,
-    _child: Box<dyn ExpressionNode>,
+    parent: Box<dyn ExpressionNode>,
+    child: Box<dyn ExpressionNode>,
 }
 
-impl ExpressionNode for MemberNode {}
+impl ExpressionNode for MemberNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        let parent = self.parent.evaluate(executor)?;
+        self.child.access(parent, executor)
+    }
+
+    fn access(&self, parent: Bson, executor: &mut Executor) -> Result<Bson> {
+        let parent = self.parent.access(parent, executor)?;
+        self.child.access(parent, executor)
+    }
+}
 
 impl MemberNode {
     pub fn new(parent: Box<dyn ExpressionNode>, child: Box<dyn ExpressionNode>) -> Self {
-        MemberNode {
-            _parent: parent,
-            _child: child,
-        }
+        MemberNode { parent, child }
     }
 }
@@ -1,14 +1,26 @@
+pub mod access;
 pub mod binary_operator;
 pub mod body;
 pub mod boolean;
 pub mod call;
+pub mod deletion;
+pub mod enum_declaration;
+pub mod event_declaration;
 pub mod expression;
 pub mod function_declaration;
 pub mod identifier;
+pub mod import;
+pub mod index_declaration;
+pub mod insertion;
+pub mod inspect;
 pub mod member;
 pub mod null;
 pub mod number;
+pub mod package_declaration;
 pub mod parameter;
+pub mod placeholder;
+pub mod query;
 pub mod string;
+pub mod table_declaration;
 pub mod unary_operator;
 pub mod variable_declaration;
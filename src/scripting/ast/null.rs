@@ -1,5 +1,14 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::scripting::executor::Executor;
+
 use super::expression::ExpressionNode;
 
 pub struct NullNode;
 
-impl ExpressionNode for NullNode {}
+impl ExpressionNode for NullNode {
+    fn evaluate(&self, _executor: &mut Executor) -> Result<Bson> {
+        Ok(Bson::Null)
+    }
+}
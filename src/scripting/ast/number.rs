@@ -1,13 +1,25 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::scripting::executor::Executor;
+
 use super::expression::ExpressionNode;
 
 pub struct NumberNode {
-    _value: f64,
+    value: f64,
 }
 
 impl NumberNode {
     pub fn new(value: f64) -> Self {
-        NumberNode { _value: value }
+        NumberNode { value }
     }
 }
 
-impl ExpressionNode for NumberNode {}
+impl ExpressionNode for NumberNode {
+    fn evaluate(&self, _executor: &mut Executor) -> Result<Bson> {
+        if self.value.fract() == 0.0 && self.value.abs() < i64::MAX as f64 {
+            return Ok(Bson::Int64(self.value as i64));
+        }
+        Ok(Bson::Double(self.value))
+    }
+}
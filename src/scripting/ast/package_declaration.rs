@@ -0,0 +1,24 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::scripting::executor::Executor;
+
+use super::expression::ExpressionNode;
+
+// `package <name>` names the package the code of a file belongs to.
+pub struct PackageDeclarationNode {
+    name: String,
+}
+
+impl PackageDeclarationNode {
+    pub fn new(name: String) -> Self {
+        PackageDeclarationNode { name }
+    }
+}
+
+impl ExpressionNode for PackageDeclarationNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        executor.enter_package(&self.name)?;
+        Ok(Bson::Null)
+    }
+}
@@ -0,0 +1,24 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::scripting::executor::Executor;
+use crate::scripting::params::Placeholder;
+
+use super::expression::ExpressionNode;
+
+// `$name` or `?`, evaluated to the value bound to it.
+pub struct PlaceholderNode {
+    placeholder: Placeholder,
+}
+
+impl PlaceholderNode {
+    pub fn new(placeholder: Placeholder) -> Self {
+        PlaceholderNode { placeholder }
+    }
+}
+
+impl ExpressionNode for PlaceholderNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        executor.params().get(&self.placeholder)
+    }
+}
@@ -0,0 +1,131 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::aggregation::Grouping;
+use crate::db::cursor::Cursor;
+use crate::db::query::{Comparison, Condition, Query, SortKey, Step};
+use crate::scripting::executor::{runtime_error, Executor};
+
+use super::expression::ExpressionNode;
+
+// A `self.<path> <comparison> <value>` condition inside selector braces.
+pub struct ConditionNode {
+    path: Vec<String>,
+    comparison: Comparison,
+    value: Box<dyn ExpressionNode>,
+}
+
+impl ConditionNode {
+    pub fn new(path: Vec<String>, comparison: Comparison, value: Box<dyn ExpressionNode>) -> Self {
+        ConditionNode {
+            path,
+            comparison,
+            value,
+        }
+    }
+}
+
+pub enum SelectorStep {
+    Filter(Vec<ConditionNode>),
+    Field(String),
+    Group(Grouping),
+    Order(Vec<SortKey>),
+    // `.limit(...)` and `.offset(...)` take expressions, e.g. `.offset(page * 20)`.
+    Limit(Box<dyn ExpressionNode>),
+    Offset(Box<dyn ExpressionNode>),
+    ReferencedBy(String, String),
+}
+
+// A table selector, e.g. `&countries.{self.name=country_name}.id`.
+pub struct QueryNode {
+    table: String,
+    steps: Vec<SelectorStep>,
+}
+
+impl QueryNode {
+    pub fn new(table: String, steps: Vec<SelectorStep>) -> Self {
+        QueryNode { table, steps }
+    }
+
+    // Condition values are evaluated once, before the table is scanned.
+    pub fn resolve(&self, executor: &mut Executor) -> Result<Query> {
+        let mut steps = vec![];
+        for step in &self.steps {
+            steps.push(match step {
+                SelectorStep::Filter(conditions) => Step::Filter(
+                    conditions
+                        .iter()
+                        .map(|condition| {
+                            Ok(Condition::new(
+                                condition.path.clone(),
+                                condition.comparison,
+                                condition.value.evaluate(executor)?,
+                            ))
+                        })
+                        .collect::<Result<_>>()?,
+                ),
+                SelectorStep::Field(name) => Step::Field(name.clone()),
+                SelectorStep::Group(grouping) => Step::Group(grouping.clone()),
+                SelectorStep::Order(keys) => Step::Order(keys.clone()),
+                SelectorStep::Limit(limit) => {
+                    Step::Limit(count("limit", limit.as_ref(), executor)?)
+                }
+                SelectorStep::ReferencedBy(table, column) => Step::ReferencedBy {
+                    table: table.clone(),
+                    column: column.clone(),
+                },
+                SelectorStep::Offset(offset) => {
+                    Step::Offset(count("offset", offset.as_ref(), executor)?)
+                }
+            });
+        }
+        Ok(Query::new(self.table.clone(), steps))
+    }
+}
+
+fn count(step: &str, node: &dyn ExpressionNode, executor: &mut Executor) -> Result<usize> {
+    let value = node.evaluate(executor)?;
+    let count = match value {
+        Bson::Int32(count) => usize::try_from(count).ok(),
+        Bson::Int64(count) => usize::try_from(count).ok(),
+        _ => None,
+    };
+    count.ok_or_else(|| {
+        runtime_error(format!(
+            "'{}' takes a non-negative integer, but {} is given",
+            step, value
+        ))
+    })
+}
+
+impl ExpressionNode for QueryNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        let query = self.resolve(executor)?;
+        executor.authorize_query(&query)?;
+        let selection = executor.lock_storage()?.select(&query)?;
+        Ok(Bson::Array(selection))
+    }
+
+    fn open_cursor(&self, executor: &mut Executor) -> Result<Cursor> {
+        let query = self.resolve(executor)?;
+        executor.authorize_query(&query)?;
+        match Cursor::for_query(&query) {
+            Some(cursor) => Ok(cursor),
+            None => Ok(Cursor::for_values(executor.lock_storage()?.select(&query)?)),
+        }
+    }
+
+    // `&table.{...}.column = value` updates the column of every selected row.
+    fn assign(&self, value: Bson, executor: &mut Executor) -> Result<Bson> {
+        let mut query = self.resolve(executor)?;
+        let Some(Step::Field(column)) = query.steps.pop() else {
+            return Err(runtime_error(format!(
+                "A column of '{}' is expected to be assigned",
+                self.table
+            )));
+        };
+        let rows = executor.writable_rows(&query)?;
+        let updated_count = executor.write(|storage| storage.update_rows(&rows, &column, value))?;
+        Ok(Bson::Int64(updated_count as i64))
+    }
+}
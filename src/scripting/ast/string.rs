@@ -1,21 +1,55 @@
+use bson::Bson;
+use std::io::Result;
+
 use crate::scripting::ast::expression::ExpressionNode;
+use crate::scripting::executor::Executor;
 
 enum StringType {
     Basic,
 }
 
 pub struct StringNode {
-    _value: String,
-    _string_type: StringType,
+    value: String,
+    string_type: StringType,
 }
 
 impl StringNode {
     pub fn new(value: String) -> Self {
         StringNode {
-            _value: value,
-            _string_type: StringType::Basic,
+            value,
+            string_type: StringType::Basic,
+        }
+    }
+
+    // The lexer keeps quotes and escape sequences of char arrays untouched.
+    fn unquote(&self) -> String {
+        let inner = self
+            .value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(&self.value);
+        let mut unquoted = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(char) = chars.next() {
+            if char != '\\' {
+                unquoted.push(char);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => unquoted.push('\n'),
+                Some('t') => unquoted.push('\t'),
+                Some(escaped) => unquoted.push(escaped),
+                None => unquoted.push('\\'),
+            }
         }
+        unquoted
     }
 }
 
-impl ExpressionNode for StringNode {}
+impl ExpressionNode for StringNode {
+    fn evaluate(&self, _executor: &mut Executor) -> Result<Bson> {
+        match self.string_type {
+            StringType::Basic => Ok(Bson::String(self.unquote())),
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::schema::TableSchema;
+use crate::scripting::executor::Executor;
+
+use super::expression::ExpressionNode;
+
+// `table <name> { ... }` creates a table or migrates the existing one;
+// `table! <name> { ... }` forces migrations that lose values.
+pub struct TableDeclarationNode {
+    schema: TableSchema,
+    force: bool,
+}
+
+impl TableDeclarationNode {
+    pub fn new(schema: TableSchema, force: bool) -> Self {
+        TableDeclarationNode { schema, force }
+    }
+}
+
+impl ExpressionNode for TableDeclarationNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        executor.authorize_declaration(&self.schema.name)?;
+        let plan = executor.define_table(self.schema.clone(), self.force)?;
+        executor.declare(&self.schema.name);
+        Ok(plan)
+    }
+}
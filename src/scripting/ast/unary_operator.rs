@@ -1,20 +1,36 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::values;
+use crate::scripting::executor::{runtime_error, Executor};
 use crate::scripting::tokens::{TokenSide, TokenType};
 
 use super::expression::ExpressionNode;
 
 pub struct UnaryOperatorNode {
-    _operator: TokenType,
-    _operand: Box<dyn ExpressionNode>,
+    operator: TokenType,
+    operand: Box<dyn ExpressionNode>,
     _side: TokenSide,
 }
 
-impl ExpressionNode for UnaryOperatorNode {}
+impl ExpressionNode for UnaryOperatorNode {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        let operand = self.operand.evaluate(executor)?;
+        match self.operator {
+            TokenType::Negotion => Ok(Bson::Boolean(!values::is_truthy(&operand))),
+            _ => Err(runtime_error(format!(
+                "'{}' operator isn't supported yet",
+                self.operator
+            ))),
+        }
+    }
+}
 
 impl UnaryOperatorNode {
     pub fn new(operator: TokenType, operand: Box<dyn ExpressionNode>, side: TokenSide) -> Self {
         UnaryOperatorNode {
-            _operator: operator,
-            _operand: operand,
+            operator,
+            operand,
             _side: side,
         }
     }
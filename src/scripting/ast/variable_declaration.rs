@@ -1,9 +1,14 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::scripting::executor::Executor;
+
 use super::expression::ExpressionNode;
 
 pub struct VariableDeclaration {
-    _name: String,
+    name: String,
     _datatype: Option<String>,
-    _value: Option<Box<dyn ExpressionNode>>,
+    value: Option<Box<dyn ExpressionNode>>,
 }
 
 impl VariableDeclaration {
@@ -13,11 +18,20 @@ impl VariableDeclaration {
         value: Option<Box<dyn ExpressionNode>>,
     ) -> Self {
         VariableDeclaration {
-            _name: name,
+            name,
             _datatype: datatype,
-            _value: value,
+            value,
         }
     }
 }
 
-impl ExpressionNode for VariableDeclaration {}
+impl ExpressionNode for VariableDeclaration {
+    fn evaluate(&self, executor: &mut Executor) -> Result<Bson> {
+        let value = match &self.value {
+            Some(value) => value.evaluate(executor)?,
+            None => Bson::Null,
+        };
+        executor.set_variable(&self.name, value);
+        Ok(Bson::Null)
+    }
+}
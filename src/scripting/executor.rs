@@ -1,8 +1,548 @@
-pub struct Executor {}
+use bson::{Bson, Document};
+use colored::Colorize;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Result};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::MutexGuard;
+use std::time::Duration;
+
+use crate::db::access::{access_error, Permission, Resource};
+use crate::db::cursor::Cursor;
+use crate::db::events::Firing;
+use crate::db::migration::Migration;
+use crate::db::query::Query;
+use crate::db::schema::TableSchema;
+use crate::db::storage::{check_package, SharedStorage, Storage};
+
+use super::ast::body::BodyNode;
+use super::ast::expression::ExpressionNode;
+use super::lexer::Lexer;
+use super::packages::{Package, PackageLoader};
+use super::params::Params;
+use super::parser::Parser;
+
+// How deep events may trigger each other before the write is considered runaway.
+const EVENT_DEPTH_LIMIT: usize = 16;
+
+// How deep declared functions may call each other.
+const CALL_DEPTH_LIMIT: usize = 64;
+
+// How long to wait before checking again whether another session's write has ended.
+const TRANSACTION_WAIT: Duration = Duration::from_millis(1);
+
+static NEXT_SESSION: AtomicU64 = AtomicU64::new(1);
+
+pub struct Executor {
+    storage: Option<SharedStorage>,
+    // Identifies the executor to the storage, which serves one session at a time
+    // while a transaction is open.
+    session: u64,
+    // The user the code runs for, whose grants it's checked against.
+    // Code run without a user, e.g. from the shell or a package file, may do anything.
+    user: Option<String>,
+    variables: HashMap<String, Bson>,
+    // The values the placeholders of the running script are bound to.
+    params: Params,
+    event_depth: usize,
+    call_depth: usize,
+    // The package the running code belongs to, declared with `package <name>`.
+    package: Option<String>,
+    // Names of the tables, events, and functions declared by the running code.
+    declarations: BTreeSet<String>,
+    packages: PackageLoader,
+}
 
 impl Executor {
     pub fn new() -> Self {
-        Executor {}
+        Executor {
+            storage: None,
+            session: NEXT_SESSION.fetch_add(1, AtomicOrdering::Relaxed),
+            user: None,
+            variables: HashMap::new(),
+            params: Params::default(),
+            event_depth: 0,
+            call_depth: 0,
+            package: None,
+            declarations: BTreeSet::new(),
+            packages: PackageLoader::default(),
+        }
+    }
+
+    pub fn with_packages(mut self, packages_path: PathBuf) -> Self {
+        self.packages = PackageLoader::new(packages_path);
+        self
+    }
+
+    pub fn with_storage(storage: SharedStorage) -> Self {
+        Executor {
+            storage: Some(storage),
+            ..Self::new()
+        }
+    }
+
+    pub fn set_user(&mut self, user: Option<String>) {
+        self.user = user;
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    pub fn authorize(&self, permission: Permission, resource: Resource) -> Result<()> {
+        let Some(user) = &self.user else {
+            return Ok(());
+        };
+        if self
+            .lock_access()?
+            .access()
+            .allows(user, permission, &resource)
+        {
+            return Ok(());
+        }
+        Err(access_error(format!(
+            "'{}' has no {} permission on {}",
+            user, permission, resource
+        )))
+    }
+
+    pub fn authorize_query(&self, query: &Query) -> Result<()> {
+        if self.user.is_none() {
+            return Ok(());
+        }
+        let tables = self.lock_storage()?.tables_read_by(query);
+        for table in tables {
+            self.authorize(Permission::Read, Resource::Table(table))?;
+        }
+        Ok(())
+    }
+
+    // The rows a write through a query changes, once the user may write every table
+    // they belong to: links lead a query out of its table, so `delete &products.seller`
+    // deletes accounts.
+    pub fn writable_rows(&self, query: &Query) -> Result<Vec<(String, Bson)>> {
+        self.authorize_query(query)?;
+        self.authorize(Permission::Write, Resource::Table(query.table.clone()))?;
+        let rows = self.lock_storage()?.selected_rows(query)?;
+        let tables: BTreeSet<&String> = rows.iter().map(|(table, _)| table).collect();
+        for table in tables {
+            self.authorize(Permission::Write, Resource::Table(table.clone()))?;
+        }
+        Ok(rows)
+    }
+
+    // Only admins may declare things outside of packages; inside one, writing the package is enough.
+    pub fn authorize_declaration(&self, name: &str) -> Result<()> {
+        let Some(user) = &self.user else {
+            return Ok(());
+        };
+        let storage = self.lock_access()?;
+        let access = storage.access();
+        let is_allowed = access.is_admin(user)
+            || self.package.as_ref().is_some_and(|package| {
+                access.allows(user, Permission::Write, &Resource::Package(package.clone()))
+            });
+        if is_allowed {
+            return Ok(());
+        }
+        Err(access_error(match &self.package {
+            Some(package) => format!(
+                "'{}' has no write permission on package {} to declare '{}'",
+                user, package, name
+            ),
+            None => format!("Only admins may declare '{}' outside of packages", name),
+        }))
+    }
+
+    pub fn authorize_admin(&self, action: &str) -> Result<()> {
+        match &self.user {
+            Some(user) if !self.lock_access()?.access().is_admin(user) => Err(access_error(
+                format!("Only admins may {}, which '{}' isn't", action, user),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn execute(&mut self, body: &BodyNode) -> Result<Bson> {
+        body.evaluate(self)
+    }
+
+    // Runs a script whose result is handed out in batches.
+    pub fn open_cursor(&mut self, body: &BodyNode) -> Result<Cursor> {
+        body.open_cursor(self)
+    }
+
+    // Names that aren't variables may be enums declared in the attached datablaze.
+    pub fn get_variable(&self, name: &str) -> Result<Bson> {
+        if let Some(value) = self.variables.get(name) {
+            return Ok(value.clone());
+        }
+        if self.storage.is_some() {
+            if let Some(definition) = self.lock_storage()?.get_enum(name) {
+                return Ok(Bson::Document(definition.to_document()));
+            }
+        }
+        Err(runtime_error(format!("'{}' is not defined", name)))
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: Bson) {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    // Binds the placeholders of the scripts run next, returning what they were bound to.
+    pub fn bind(&mut self, params: Params) -> Params {
+        std::mem::replace(&mut self.params, params)
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    pub fn package(&self) -> Option<&str> {
+        self.package.as_deref()
+    }
+
+    pub fn declare(&mut self, name: &str) {
+        self.declarations.insert(name.to_string());
+    }
+
+    // A package file has to declare the package it's imported as.
+    pub fn enter_package(&mut self, name: &str) -> Result<()> {
+        if let Some(package) = &self.package {
+            if package != name {
+                return Err(runtime_error(format!(
+                    "Package '{}' is declared where '{}' is expected",
+                    name, package
+                )));
+            }
+        }
+        self.package = Some(name.to_string());
+        Ok(())
+    }
+
+    // `import <package>:<item>` brings a variable of a package into the running code,
+    // or all of them with `<package>:all`. Tables and events of a package are stored
+    // along with the others, marked as its own, so importing them only makes sure the
+    // package is loaded.
+    pub fn import(&mut self, package_name: &str, item: &str) -> Result<()> {
+        self.authorize(
+            Permission::Execute,
+            Resource::Package(package_name.to_string()),
+        )?;
+        let package = self.load_package(package_name)?;
+        if item == "all" {
+            self.variables.extend(package.variables);
+            return Ok(());
+        }
+        if let Some(value) = package.variables.get(item) {
+            self.set_variable(item, value.clone());
+            return Ok(());
+        }
+        if package.declarations.contains(item) {
+            return Ok(());
+        }
+        Err(runtime_error(format!(
+            "Package '{}' has no '{}'",
+            package_name, item
+        )))
+    }
+
+    // Runs a package with its own variables, so that it only shares what's imported from it.
+    // Package files lie on the server, so their code runs without a user.
+    fn load_package(&mut self, name: &str) -> Result<Package> {
+        if let Some(package) = self.packages.loaded.get(name) {
+            return Ok(package.clone());
+        }
+        if self.packages.loading.iter().any(|loading| loading == name) {
+            return Err(self.packages.cycle_error(name));
+        }
+        let body = self.packages.read(name)?;
+        let mut executor = Executor {
+            storage: self.storage.clone(),
+            session: self.session,
+            package: Some(name.to_string()),
+            packages: std::mem::take(&mut self.packages),
+            ..Self::new()
+        };
+        executor.packages.loading.push(name.to_string());
+        let result = body.evaluate(&mut executor);
+        executor.packages.loading.pop();
+        self.packages = std::mem::take(&mut executor.packages);
+        result?;
+        let package = Package {
+            variables: executor.variables,
+            declarations: executor.declarations,
+        };
+        self.packages
+            .loaded
+            .insert(name.to_string(), package.clone());
+        Ok(package)
+    }
+
+    // Runs a function declared in the datablaze with its parameters as its only variables.
+    pub fn call_function(&mut self, name: &str, arguments: Vec<Bson>) -> Result<Bson> {
+        let definition = self
+            .lock_storage()?
+            .get_function(name)
+            .cloned()
+            .ok_or_else(|| runtime_error(format!("Function '{}' isn't defined", name)))?;
+        self.authorize(Permission::Execute, Resource::Function(name.to_string()))?;
+        if arguments.len() != definition.parameters.len() {
+            return Err(runtime_error(format!(
+                "'{}' takes {} arguments, but {} are given",
+                name,
+                definition.parameters.len(),
+                arguments.len()
+            )));
+        }
+        let Some(body) = &definition.body else {
+            return Err(runtime_error(format!("Function '{}' has no body", name)));
+        };
+        if self.call_depth >= CALL_DEPTH_LIMIT {
+            return Err(runtime_error(format!(
+                "Function '{}' exceeded the limit of {} nested calls",
+                name, CALL_DEPTH_LIMIT
+            )));
+        }
+        let mut executor = Executor {
+            storage: self.storage.clone(),
+            session: self.session,
+            user: self.user.clone(),
+            package: self.package.clone(),
+            event_depth: self.event_depth,
+            call_depth: self.call_depth + 1,
+            ..Self::new()
+        };
+        for ((parameter, _), argument) in definition.parameters.iter().zip(arguments) {
+            executor.set_variable(parameter, argument);
+        }
+        executor.evaluate_code(body)
+    }
+
+    // Evaluates a piece of code stored apart from the script, e.g. a column default.
+    pub fn evaluate_code(&mut self, code: &str) -> Result<Bson> {
+        let tokens = Lexer::new(code.to_string()).analyze()?;
+        let body = Parser::new(tokens).parse()?;
+        body.evaluate(self)
+    }
+
+    // Declaring an existing table migrates its rows to the new schema and returns the plan
+    // of the migration. Columns missing from migrated rows are filled with their defaults.
+    // Migrations losing values are refused unless they're forced with `table!`.
+    // The storage isn't locked while defaults are evaluated, so the migration is refused
+    // too when the table or the catalog is written to meanwhile.
+    pub fn define_table(&mut self, mut schema: TableSchema, force: bool) -> Result<Bson> {
+        schema.package = self.package.clone();
+        let (migration, rows, read_at) = {
+            let mut storage = self.lock_storage()?;
+            let revision = storage.revision();
+            let Ok(table) = storage.get_table(&schema.name) else {
+                storage.define_table(schema)?;
+                return Ok(Bson::Null);
+            };
+            check_package(
+                "Table",
+                &schema.name,
+                &table.schema.package,
+                &schema.package,
+            )?;
+            let rows: Vec<Document> = table.rows.values().cloned().collect();
+            let read_at = (revision, table.writes());
+            (Migration::plan(&table.schema, &schema), rows, read_at)
+        };
+        if migration.is_empty() {
+            return Ok(Bson::Null);
+        }
+        let mut migrated_rows: Vec<Document> = vec![];
+        let mut losses: Vec<String> = vec![];
+        for row in rows {
+            let (mut row, row_losses) = migration.migrate_row(row);
+            losses.extend(row_losses);
+            for column in &schema.columns {
+                let Some(code) = column.default.as_ref() else {
+                    continue;
+                };
+                if !row.contains_key(&column.name) {
+                    let value = self.with_self(Bson::Document(row.clone()), |executor| {
+                        executor.evaluate_code(code)
+                    })?;
+                    row.insert(&column.name, value);
+                }
+            }
+            migrated_rows.push(row);
+        }
+        if let Some(loss) = losses.first().filter(|_| !force) {
+            return Err(runtime_error(format!(
+                "Migrating '{}' would {}{}; declare it with `table!` to force the migration",
+                schema.name,
+                loss,
+                match losses.len() {
+                    1 => String::new(),
+                    count => format!(" along with {} more values", count - 1),
+                }
+            )));
+        }
+        let plan = migration.to_string();
+        let mut storage = self.lock_storage()?;
+        let table = storage.get_table(&schema.name)?;
+        if (storage.revision(), table.writes()) != read_at {
+            return Err(runtime_error(format!(
+                "'{}' was written to while it was being migrated; declare it again",
+                schema.name
+            )));
+        }
+        storage.migrate_table(&migration, schema, migrated_rows)?;
+        Ok(Bson::String(plan))
+    }
+
+    // Fills the columns missing from a row with their defaults before inserting it.
+    // Required columns are never filled since they must be given explicitly.
+    // Defaults see the row being inserted, key included, as `self`.
+    pub fn insert_row(&mut self, table: &str, mut row: Document) -> Result<Bson> {
+        self.authorize(Permission::Write, Resource::Table(table.to_string()))?;
+        if !row.contains_key("id") {
+            let key = self.lock_storage()?.generate_key(table)?;
+            if key != Bson::Null {
+                row.insert("id", key);
+            }
+        }
+        let defaults: Vec<(String, String)> = self
+            .lock_storage()?
+            .get_table(table)?
+            .schema
+            .columns
+            .iter()
+            .filter(|column| !column.required && !row.contains_key(&column.name))
+            .filter_map(|column| Some((column.name.clone(), column.default.clone()?)))
+            .collect();
+        for (column, code) in defaults {
+            let value = self.with_self(Bson::Document(row.clone()), |executor| {
+                executor.evaluate_code(&code)
+            })?;
+            row.insert(column, value);
+        }
+        self.write(|storage| storage.insert(table, row))
+    }
+
+    // Runs code with `self` bound to a value, restoring the outer `self` afterwards.
+    fn with_self<T>(&mut self, value: Bson, run: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let outer_self = self.variables.insert("self".to_string(), value);
+        let result = run(self);
+        match outer_self {
+            Some(outer_self) => self.set_variable("self", outer_self),
+            None => {
+                self.variables.remove("self");
+            }
+        }
+        result
+    }
+
+    // Runs a write and the events it triggers. A write made outside of events is a
+    // transaction along with everything its events do, so an error undoes all of it.
+    pub fn write<T>(&mut self, operation: impl FnOnce(&mut Storage) -> Result<T>) -> Result<T> {
+        let is_outermost = !self.lock_storage()?.in_transaction();
+        if is_outermost {
+            self.lock_storage()?.begin(self.session, false);
+        }
+        let result = self.write_and_fire(operation);
+        if is_outermost {
+            let mut storage = self.lock_storage()?;
+            match result {
+                Ok(_) => storage.commit()?,
+                Err(_) => storage.rollback(),
+            }
+        }
+        result
+    }
+
+    fn write_and_fire<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Storage) -> Result<T>,
+    ) -> Result<T> {
+        let (value, fired) = {
+            let mut storage = self.lock_storage()?;
+            let value = operation(&mut storage)?;
+            (value, storage.take_fired())
+        };
+        for firing in fired {
+            self.run_event(firing)?;
+        }
+        Ok(value)
+    }
+
+    fn run_event(&mut self, firing: Firing) -> Result<()> {
+        if self.event_depth >= EVENT_DEPTH_LIMIT {
+            return Err(runtime_error(format!(
+                "Event '{}' exceeded the limit of {} nested events",
+                firing.event, EVENT_DEPTH_LIMIT
+            )));
+        }
+        self.event_depth += 1;
+        let result = self.with_self(Bson::Document(firing.row), |executor| {
+            executor.evaluate_code(&firing.body)
+        });
+        self.event_depth -= 1;
+        result.map(|_| ())
+    }
+
+    pub fn lock_storage(&self) -> Result<MutexGuard<'_, Storage>> {
+        loop {
+            let guard = self.lock_access()?;
+            match guard.transaction_owner() {
+                Some(owner) if owner != self.session && guard.is_begun_by_session() => {
+                    return Err(busy_error(
+                        "Another session has a transaction open; try again once it has ended",
+                    ));
+                }
+                Some(owner) if owner != self.session => {
+                    drop(guard);
+                    std::thread::sleep(TRANSACTION_WAIT);
+                }
+                _ => return Ok(guard),
+            }
+        }
+    }
+
+    // Locks the storage even while another session has a transaction open, to read
+    // users and roles, which can't be changed inside transactions.
+    fn lock_access(&self) -> Result<MutexGuard<'_, Storage>> {
+        self.storage
+            .as_ref()
+            .ok_or_else(|| runtime_error("No datablaze is attached"))?
+            .lock()
+            .map_err(|_| runtime_error("The datablaze storage is poisoned"))
+    }
+
+    // Opens a transaction lasting until it's committed or rolled back, so the writes
+    // of several scripts are kept or undone together.
+    pub fn begin_transaction(&mut self) -> Result<()> {
+        let mut storage = self.lock_storage()?;
+        if storage.in_transaction() {
+            return Err(runtime_error("A transaction is already open"));
+        }
+        storage.begin(self.session, true);
+        Ok(())
+    }
+
+    pub fn commit_transaction(&mut self) -> Result<()> {
+        let mut storage = self.lock_storage()?;
+        if !storage.in_transaction() {
+            return Err(runtime_error("No transaction is open"));
+        }
+        storage.commit()
+    }
+
+    pub fn rollback_transaction(&mut self) -> Result<()> {
+        let mut storage = self.lock_storage()?;
+        if !storage.in_transaction() {
+            return Err(runtime_error("No transaction is open"));
+        }
+        storage.rollback();
+        Ok(())
+    }
+
+    pub fn in_transaction(&self) -> Result<bool> {
+        Ok(self.storage.is_some() && self.lock_storage()?.in_transaction())
     }
 }
 
@@ -11,3 +551,14 @@ impl Default for Executor {
         Self::new()
     }
 }
+
+pub fn runtime_error(message: impl std::fmt::Display) -> io::Error {
+    io::Error::other(format!("{}: {}", "Runtime Error".bright_red(), message))
+}
+
+pub fn busy_error(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ResourceBusy,
+        format!("{}: {}", "Unavailable Error".bright_red(), message),
+    )
+}
@@ -0,0 +1,62 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::aggregation::{Accumulator, Aggregate};
+
+use super::{argument_error, NativeFunction};
+
+// Aggregates take the result of a query, e.g. `sum(&orders.{self.paid = true}.total)`.
+// Nulls are skipped, so missing values don't count.
+pub const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "count",
+        arity: 1..=1,
+        parameters: &["array"],
+        returns: "int",
+        call: |arguments| aggregate("count", Aggregate::Count, arguments),
+    },
+    NativeFunction {
+        name: "sum",
+        arity: 1..=1,
+        parameters: &["array"],
+        returns: "num",
+        call: |arguments| aggregate("sum", Aggregate::Sum, arguments),
+    },
+    NativeFunction {
+        name: "avg",
+        arity: 1..=1,
+        parameters: &["array"],
+        returns: "float",
+        call: |arguments| aggregate("avg", Aggregate::Avg, arguments),
+    },
+    // `min(a, b)` picks the least of its arguments, while `min(&table.column)`
+    // picks the least of the selected values.
+    NativeFunction {
+        name: "min",
+        arity: 1..=usize::MAX,
+        parameters: &["num|str|datetime|array", "num|str|datetime"],
+        returns: "any",
+        call: |arguments| aggregate("min", Aggregate::Min, arguments),
+    },
+    NativeFunction {
+        name: "max",
+        arity: 1..=usize::MAX,
+        parameters: &["num|str|datetime|array", "num|str|datetime"],
+        returns: "any",
+        call: |arguments| aggregate("max", Aggregate::Max, arguments),
+    },
+];
+
+fn aggregate(function: &str, aggregate: Aggregate, arguments: &[Bson]) -> Result<Bson> {
+    let values = match arguments {
+        [Bson::Array(items)] => items.as_slice(),
+        arguments => arguments,
+    };
+    let mut accumulator = Accumulator::new(aggregate);
+    for value in values {
+        if !accumulator.add(value) {
+            return Err(argument_error(function, value));
+        }
+    }
+    Ok(accumulator.finish())
+}
@@ -0,0 +1,51 @@
+use bson::Bson;
+
+use crate::db::values;
+
+use super::strings::text;
+use super::{argument_error, NativeFunction};
+
+// Conversions between the column types, e.g. `int("42")` or `str(3.5)`.
+pub const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "int",
+        arity: 1..=1,
+        parameters: &["num|str|bool"],
+        returns: "int",
+        call: |arguments| {
+            let converted = match &arguments[0] {
+                // Fractions are dropped as they are by most languages.
+                Bson::Double(number) if number.is_finite() && number.abs() < i64::MAX as f64 => {
+                    Some(Bson::Int64(number.trunc() as i64))
+                }
+                Bson::Boolean(state) => Some(Bson::Int64(*state as i64)),
+                value => values::convert(value, "int"),
+            };
+            converted.ok_or_else(|| argument_error("int", &arguments[0]))
+        },
+    },
+    NativeFunction {
+        name: "float",
+        arity: 1..=1,
+        parameters: &["num|str"],
+        returns: "float",
+        call: |arguments| {
+            values::convert(&arguments[0], "float")
+                .ok_or_else(|| argument_error("float", &arguments[0]))
+        },
+    },
+    NativeFunction {
+        name: "str",
+        arity: 1..=1,
+        parameters: &["any"],
+        returns: "str",
+        call: |arguments| Ok(Bson::String(text(&arguments[0]))),
+    },
+    NativeFunction {
+        name: "bool",
+        arity: 1..=1,
+        parameters: &["any"],
+        returns: "bool",
+        call: |arguments| Ok(Bson::Boolean(values::is_truthy(&arguments[0]))),
+    },
+];
@@ -0,0 +1,90 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::datetime::{
+    self, MILLISECONDS_IN_DAY, MILLISECONDS_IN_HOUR, MILLISECONDS_IN_MINUTE, MILLISECONDS_IN_SECOND,
+};
+
+use super::{argument_error, NativeFunction};
+
+pub const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "now",
+        arity: 0..=0,
+        parameters: &[],
+        returns: "datetime",
+        call: |_| Ok(Bson::DateTime(bson::DateTime::now())),
+    },
+    NativeFunction {
+        name: "datetime",
+        arity: 1..=1,
+        parameters: &["str|datetime"],
+        returns: "datetime",
+        call: |arguments| to_datetime("datetime", &arguments[0]).map(Bson::DateTime),
+    },
+    NativeFunction {
+        name: "format_datetime",
+        arity: 2..=2,
+        parameters: &["str|datetime", "str"],
+        returns: "str",
+        call: format_datetime,
+    },
+    NativeFunction {
+        name: "days",
+        arity: 1..=1,
+        parameters: &["num"],
+        returns: "int",
+        call: |arguments| duration("days", &arguments[0], MILLISECONDS_IN_DAY),
+    },
+    NativeFunction {
+        name: "hours",
+        arity: 1..=1,
+        parameters: &["num"],
+        returns: "int",
+        call: |arguments| duration("hours", &arguments[0], MILLISECONDS_IN_HOUR),
+    },
+    NativeFunction {
+        name: "minutes",
+        arity: 1..=1,
+        parameters: &["num"],
+        returns: "int",
+        call: |arguments| duration("minutes", &arguments[0], MILLISECONDS_IN_MINUTE),
+    },
+    NativeFunction {
+        name: "seconds",
+        arity: 1..=1,
+        parameters: &["num"],
+        returns: "int",
+        call: |arguments| duration("seconds", &arguments[0], MILLISECONDS_IN_SECOND),
+    },
+];
+
+fn to_datetime(function: &str, value: &Bson) -> Result<bson::DateTime> {
+    match datetime::coerce(value) {
+        Some(Bson::DateTime(datetime)) => Ok(datetime),
+        _ => Err(argument_error(function, value)),
+    }
+}
+
+// `format_datetime(created_at, "%d.%m.%Y")`
+fn format_datetime(arguments: &[Bson]) -> Result<Bson> {
+    let datetime = to_datetime("format_datetime", &arguments[0])?;
+    let Bson::String(pattern) = &arguments[1] else {
+        return Err(argument_error("format_datetime", &arguments[1]));
+    };
+    datetime::format(datetime, pattern)
+        .map(Bson::String)
+        .ok_or_else(|| argument_error("format_datetime", &arguments[1]))
+}
+
+fn duration(function: &str, amount: &Bson, unit: i64) -> Result<Bson> {
+    let milliseconds = match amount {
+        Bson::Int32(amount) => (*amount as i64).checked_mul(unit),
+        Bson::Int64(amount) => amount.checked_mul(unit),
+        Bson::Double(amount) => Some((amount * unit as f64).round() as i64),
+        _ => None,
+    };
+    milliseconds
+        .map(Bson::Int64)
+        .ok_or_else(|| argument_error(function, amount))
+}
@@ -0,0 +1,74 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::{geo, values};
+
+use super::{argument_error, NativeFunction};
+
+pub const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "point",
+        arity: 2..=2,
+        parameters: &["num", "num"],
+        returns: "geo",
+        call: |arguments| {
+            let (longitude, latitude) = coordinates("point", &arguments[0], &arguments[1])?;
+            Ok(geo::point(longitude, latitude))
+        },
+    },
+    NativeFunction {
+        name: "polygon",
+        arity: 3..=usize::MAX,
+        parameters: &["geo"],
+        returns: "geo",
+        call: polygon,
+    },
+    NativeFunction {
+        name: "box",
+        arity: 4..=4,
+        parameters: &["num", "num", "num", "num"],
+        returns: "geo",
+        call: |arguments| {
+            let min = coordinates("box", &arguments[0], &arguments[1])?;
+            let max = coordinates("box", &arguments[2], &arguments[3])?;
+            Ok(geo::bounding_box(min, max))
+        },
+    },
+    NativeFunction {
+        name: "distance",
+        arity: 2..=2,
+        parameters: &["geo", "geo"],
+        returns: "float",
+        call: |arguments| {
+            geo::distance(&arguments[0], &arguments[1])
+                .map(Bson::Double)
+                .ok_or_else(|| argument_error("distance", &arguments[0]))
+        },
+    },
+    NativeFunction {
+        name: "within",
+        arity: 2..=2,
+        parameters: &["any", "geo"],
+        returns: "bool",
+        call: |arguments| Ok(Bson::Boolean(geo::within(&arguments[0], &arguments[1]))),
+    },
+];
+
+fn coordinates(function: &str, longitude: &Bson, latitude: &Bson) -> Result<(f64, f64)> {
+    let longitude_value = values::as_f64(longitude)
+        .filter(|longitude| (-180.0..=180.0).contains(longitude))
+        .ok_or_else(|| argument_error(function, longitude))?;
+    let latitude_value = values::as_f64(latitude)
+        .filter(|latitude| (-90.0..=90.0).contains(latitude))
+        .ok_or_else(|| argument_error(function, latitude))?;
+    Ok((longitude_value, latitude_value))
+}
+
+// `polygon(point(0, 0), point(10, 0), point(10, 10))`
+fn polygon(arguments: &[Bson]) -> Result<Bson> {
+    let ring = arguments
+        .iter()
+        .map(|argument| geo::as_point(argument).ok_or_else(|| argument_error("polygon", argument)))
+        .collect::<Result<Vec<(f64, f64)>>>()?;
+    Ok(geo::polygon(&ring))
+}
@@ -0,0 +1,79 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::values;
+
+use super::{argument_error, NativeFunction};
+
+pub const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "abs",
+        arity: 1..=1,
+        parameters: &["num"],
+        returns: "num",
+        call: |arguments| match &arguments[0] {
+            Bson::Int32(number) => Ok(Bson::Int64((*number as i64).abs())),
+            Bson::Int64(number) => number
+                .checked_abs()
+                .map(Bson::Int64)
+                .ok_or_else(|| argument_error("abs", &arguments[0])),
+            value => Ok(Bson::Double(number(value).abs())),
+        },
+    },
+    NativeFunction {
+        name: "round",
+        arity: 1..=2,
+        parameters: &["num", "int"],
+        returns: "num",
+        call: round,
+    },
+    NativeFunction {
+        name: "pow",
+        arity: 2..=2,
+        parameters: &["num", "num"],
+        returns: "num",
+        call: pow,
+    },
+];
+
+fn number(value: &Bson) -> f64 {
+    values::as_f64(value).unwrap_or(f64::NAN)
+}
+
+fn integer(value: &Bson) -> Option<i64> {
+    match value {
+        Bson::Int32(number) => Some(*number as i64),
+        Bson::Int64(number) => Some(*number),
+        _ => None,
+    }
+}
+
+// `round(2.5)` gives the integer `3`, while `round(2.345, 2)` keeps two decimal places.
+fn round(arguments: &[Bson]) -> Result<Bson> {
+    let value = number(&arguments[0]);
+    match arguments.get(1).map(integer) {
+        None if value.is_finite() && value.abs() < i64::MAX as f64 => {
+            Ok(Bson::Int64(value.round() as i64))
+        }
+        None => Err(argument_error("round", &arguments[0])),
+        Some(Some(places)) => {
+            let scale = 10_f64.powi(places.clamp(-308, 308) as i32);
+            Ok(Bson::Double((value * scale).round() / scale))
+        }
+        Some(None) => Err(argument_error("round", &arguments[1])),
+    }
+}
+
+// Integers raised to non-negative integer powers stay integers.
+fn pow(arguments: &[Bson]) -> Result<Bson> {
+    match (integer(&arguments[0]), integer(&arguments[1])) {
+        (Some(base), Some(exponent)) if exponent >= 0 => u32::try_from(exponent)
+            .ok()
+            .and_then(|exponent| base.checked_pow(exponent))
+            .map(Bson::Int64)
+            .ok_or_else(|| argument_error("pow", &arguments[1])),
+        _ => Ok(Bson::Double(
+            number(&arguments[0]).powf(number(&arguments[1])),
+        )),
+    }
+}
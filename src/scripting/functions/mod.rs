@@ -0,0 +1,131 @@
+use bson::Bson;
+use std::fmt;
+use std::io::Result;
+use std::ops::RangeInclusive;
+
+use crate::db::{constraints, values};
+
+use super::executor::runtime_error;
+
+mod aggregates;
+mod conversions;
+mod datetime;
+mod geo;
+mod math;
+mod strings;
+
+// A function implemented in Rust and called from Blaze code by its name.
+pub struct NativeFunction {
+    pub name: &'static str,
+    // How many arguments the function takes.
+    pub arity: RangeInclusive<usize>,
+    // The types of the arguments, where the last one also stands for any extra arguments.
+    // Alternatives are separated by `|`, `num` is any number and `any` is anything.
+    pub parameters: &'static [&'static str],
+    pub returns: &'static str,
+    pub call: fn(&[Bson]) -> Result<Bson>,
+}
+
+impl NativeFunction {
+    fn parameter(&self, position: usize) -> &'static str {
+        self.parameters
+            .get(position)
+            .or(self.parameters.last())
+            .copied()
+            .unwrap_or("any")
+    }
+
+    // Checks the arguments against the signature before the function is called.
+    pub fn check(&self, arguments: &[Bson]) -> Result<()> {
+        if !self.arity.contains(&arguments.len()) {
+            return Err(runtime_error(format!(
+                "'{}' takes {} arguments, but {} are given",
+                self.name,
+                describe_arity(&self.arity),
+                arguments.len()
+            )));
+        }
+        for (position, argument) in arguments.iter().enumerate() {
+            let datatype = self.parameter(position);
+            if !accepts(datatype, argument) {
+                return Err(runtime_error(format!(
+                    "'{}' expects {} as argument {}, but {} is given",
+                    self.name,
+                    datatype,
+                    position + 1,
+                    argument
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// `round(num, int?) -> num`
+impl fmt::Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shown = (*self.arity.end()).min(self.parameters.len());
+        let parameters: Vec<String> = (0..shown)
+            .map(|position| {
+                let datatype = self.parameter(position);
+                match position {
+                    _ if position + 1 == shown && *self.arity.end() > shown => {
+                        format!("{}...", datatype)
+                    }
+                    _ if position >= *self.arity.start() => format!("{}?", datatype),
+                    _ => datatype.to_string(),
+                }
+            })
+            .collect();
+        write!(
+            f,
+            "{}({}) -> {}",
+            self.name,
+            parameters.join(", "),
+            self.returns
+        )
+    }
+}
+
+const FUNCTIONS: &[&[NativeFunction]] = &[
+    strings::FUNCTIONS,
+    math::FUNCTIONS,
+    aggregates::FUNCTIONS,
+    conversions::FUNCTIONS,
+    datetime::FUNCTIONS,
+    geo::FUNCTIONS,
+];
+
+pub fn get(name: &str) -> Option<&'static NativeFunction> {
+    FUNCTIONS
+        .iter()
+        .flat_map(|functions| functions.iter())
+        .find(|function| function.name == name)
+}
+
+pub fn call(function: &NativeFunction, arguments: &[Bson]) -> Result<Bson> {
+    function.check(arguments)?;
+    (function.call)(arguments)
+}
+
+fn accepts(datatype: &str, value: &Bson) -> bool {
+    datatype.split('|').any(|datatype| match datatype {
+        "any" => true,
+        "num" => values::as_f64(value).is_some(),
+        "array" => matches!(value, Bson::Array(_)),
+        datatype => constraints::matches_type(datatype, value),
+    })
+}
+
+fn describe_arity(arity: &RangeInclusive<usize>) -> String {
+    match (arity.start(), arity.end()) {
+        (start, end) if start == end => start.to_string(),
+        (start, &usize::MAX) => format!("at least {}", start),
+        (start, end) => format!("from {} to {}", start, end),
+    }
+}
+
+// Reports an argument of a type a function can't work with.
+fn argument_error(function: &str, argument: &Bson) -> std::io::Error {
+    runtime_error(format!("'{}' can't be applied to {}", function, argument))
+}
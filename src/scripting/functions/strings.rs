@@ -0,0 +1,105 @@
+use bson::Bson;
+use std::io::Result;
+
+use crate::db::values;
+use crate::scripting::executor::runtime_error;
+
+use super::NativeFunction;
+
+pub const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "format",
+        arity: 1..=usize::MAX,
+        parameters: &["str", "any"],
+        returns: "str",
+        call: format,
+    },
+    NativeFunction {
+        name: "len",
+        arity: 1..=1,
+        parameters: &["str|array"],
+        returns: "int",
+        call: |arguments| {
+            let length = match &arguments[0] {
+                Bson::Array(items) => items.len(),
+                value => text(value).chars().count(),
+            };
+            Ok(Bson::Int64(length as i64))
+        },
+    },
+    NativeFunction {
+        name: "lower",
+        arity: 1..=1,
+        parameters: &["str"],
+        returns: "str",
+        call: |arguments| Ok(Bson::String(text(&arguments[0]).to_lowercase())),
+    },
+    NativeFunction {
+        name: "upper",
+        arity: 1..=1,
+        parameters: &["str"],
+        returns: "str",
+        call: |arguments| Ok(Bson::String(text(&arguments[0]).to_uppercase())),
+    },
+    NativeFunction {
+        name: "split",
+        arity: 2..=2,
+        parameters: &["str", "str"],
+        returns: "array",
+        call: |arguments| {
+            let separator = text(&arguments[1]);
+            if separator.is_empty() {
+                return Err(runtime_error("'split' needs a non-empty separator"));
+            }
+            let parts = text(&arguments[0])
+                .split(&separator)
+                .map(|part| Bson::String(part.to_string()))
+                .collect();
+            Ok(Bson::Array(parts))
+        },
+    },
+    NativeFunction {
+        name: "contains",
+        arity: 2..=2,
+        parameters: &["str|array", "any"],
+        returns: "bool",
+        call: |arguments| {
+            let found = match (&arguments[0], &arguments[1]) {
+                (Bson::Array(items), item) => {
+                    items.iter().any(|option| values::equals(option, item))
+                }
+                (string, part) => text(string).contains(&text(part)),
+            };
+            Ok(Bson::Boolean(found))
+        },
+    },
+];
+
+// How a value reads inside a string, so strings go without their quotes.
+pub fn text(value: &Bson) -> String {
+    match value {
+        Bson::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+// `format("User{}", self.id)` puts the values in place of the `{}`s in order.
+fn format(arguments: &[Bson]) -> Result<Bson> {
+    let pattern = text(&arguments[0]);
+    let values = &arguments[1..];
+    let placeholders = pattern.matches("{}").count();
+    if placeholders != values.len() {
+        return Err(runtime_error(format!(
+            "'format' has {} placeholders, but {} values are given",
+            placeholders,
+            values.len()
+        )));
+    }
+    let mut parts = pattern.split("{}");
+    let mut formatted = parts.next().unwrap_or_default().to_string();
+    for (part, value) in parts.zip(values) {
+        formatted.push_str(&text(value));
+        formatted.push_str(part);
+    }
+    Ok(Bson::String(formatted))
+}
@@ -4,7 +4,7 @@ use std::io;
 use strum::IntoEnumIterator;
 
 use super::context::Context;
-use super::tokens::{Token, TokenType, WHITESPACE_TOKENS};
+use super::tokens::{Token, TokenType};
 
 pub struct Lexer {
     pub context: Context,
@@ -26,30 +26,9 @@ impl Lexer {
     }
 
     pub fn analyze(mut self) -> io::Result<Vec<Token>> {
-        loop {
-            match self.recognize_next_token() {
-                Ok(proceed_parsing) => {
-                    if !proceed_parsing {
-                        self.tokens.retain(|token| !token.is_type(TokenType::Space));
-                        return Ok(self.tokens);
-                    }
-                    let last_token = self.tokens.last().unwrap();
-                    if !WHITESPACE_TOKENS.contains(&last_token.token_type) {
-                        let start_position =
-                            1 + self.context.position - last_token.value.len() as u64;
-                        println!(
-                            "{}:{} = {}",
-                            start_position, last_token.value, last_token.token_type
-                        );
-                    }
-                }
-                Err(err) => {
-                    eprintln!("{}", err);
-                    break;
-                }
-            };
-        }
-        Ok(vec![])
+        while self.recognize_next_token()? {}
+        self.tokens.retain(|token| !token.is_type(TokenType::Space));
+        Ok(self.tokens)
     }
 
     fn recognize_next_token(&mut self) -> io::Result<bool> {
@@ -77,18 +56,15 @@ impl Lexer {
                 return Ok(true);
             }
         }
-        Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!(
-                "{}: '{}' {} <-= at {}:{}:{}",
-                "Lexical Error".bright_red(),
-                positioned_code,
-                "isn't recognized",
-                self.context.code_source,
-                self.context.line + 1,
-                self.context.position + 1
-            ),
-        ))
+        Err(io::Error::other(format!(
+            "{}: '{}' {} <-= at {}:{}:{}",
+            "Lexical Error".bright_red(),
+            positioned_code,
+            "isn't recognized",
+            self.context.code_source,
+            self.context.line + 1,
+            self.context.position + 1
+        )))
     }
 
     fn find_lexical_errors(&mut self) -> io::Result<()> {
@@ -105,8 +81,7 @@ impl Lexer {
             let current_token_is_alphanumeric = current_token.is_type(TokenType::Alphanumeric);
             let last_token_is_number = last_token.is_type(TokenType::Number);
             if last_token_is_number && current_token_is_alphanumeric {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
+                return Err(io::Error::other(
                     format!(
                         "{}\n\"{}{}\": numbers cannot end with alphanumeric <-= at {}:{}:{}",
                         "Lexical Error".bright_red(),
@@ -130,30 +105,26 @@ impl Lexer {
             let both_sides_unresolved_chars_regex = Regex::new(r"[\w\d]").unwrap();
             let left_side_unresolved_chars_regex = Regex::new(r"[\.]").unwrap();
 
-            let char_before_index: u64 = current_token.start - 1;
             let char_after_index: u64 = current_token.start + current_token.value.len() as u64;
 
-            if char_before_index as i32 > 0 {
+            if let Some(char_before_index) = current_token.start.checked_sub(1) {
                 let char_before = &self
                     .code
                     .chars()
-                    .nth((current_token.start - 1) as usize)
+                    .nth(char_before_index as usize)
                     .unwrap()
                     .to_string();
                 if both_sides_unresolved_chars_regex.is_match(char_before)
                     || left_side_unresolved_chars_regex.is_match(char_before)
                 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        format!(
-                            "{}: \"{}\" near a string with no space between <-= at {}:{}:{}",
-                            "Lexical Error".bright_red(),
-                            char_before,
-                            self.context.code_source,
-                            current_token.line + 1,
-                            char_before_index + 1
-                        ),
-                    ));
+                    return Err(io::Error::other(format!(
+                        "{}: \"{}\" near a string with no space between <-= at {}:{}:{}",
+                        "Lexical Error".bright_red(),
+                        char_before,
+                        self.context.code_source,
+                        current_token.line + 1,
+                        char_before_index + 1
+                    )));
                 }
             };
             if char_after_index < self.code.len() as u64 {
@@ -164,17 +135,14 @@ impl Lexer {
                     .unwrap()
                     .to_string();
                 if both_sides_unresolved_chars_regex.is_match(char_after) {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        format!(
-                            "{}: \"{}\" after a string with no space between <-= at {}:{}:{}",
-                            "Lexical Error".bright_red(),
-                            char_after,
-                            self.context.code_source,
-                            current_token.line + 1,
-                            char_after_index + 1
-                        ),
-                    ));
+                    return Err(io::Error::other(format!(
+                        "{}: \"{}\" after a string with no space between <-= at {}:{}:{}",
+                        "Lexical Error".bright_red(),
+                        char_after,
+                        self.context.code_source,
+                        current_token.line + 1,
+                        char_after_index + 1
+                    )));
                 }
             };
         }
@@ -1,6 +1,9 @@
 pub mod ast;
 pub mod context;
 pub mod executor;
+pub mod functions;
 pub mod lexer;
+pub mod packages;
+pub mod params;
 pub mod parser;
 pub mod tokens;
@@ -0,0 +1,73 @@
+use bson::Bson;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::Result;
+use std::path::PathBuf;
+
+use super::ast::body::BodyNode;
+use super::executor::runtime_error;
+use super::lexer::Lexer;
+use super::parser::Parser;
+
+// What a package makes importable: the values of its variables and the names
+// of its tables, events, and functions.
+#[derive(Clone, Default)]
+pub struct Package {
+    pub variables: HashMap<String, Bson>,
+    pub declarations: BTreeSet<String>,
+}
+
+// Finds packages as `<name>.blz` files in the packages directory and keeps the ones
+// loaded so far, so that every package is run once however many times it's imported.
+#[derive(Default)]
+pub struct PackageLoader {
+    path: Option<PathBuf>,
+    pub loaded: HashMap<String, Package>,
+    // The packages being loaded, each one importing the next.
+    pub loading: Vec<String>,
+}
+
+impl PackageLoader {
+    pub fn new(path: PathBuf) -> Self {
+        PackageLoader {
+            path: Some(path),
+            ..Self::default()
+        }
+    }
+
+    pub fn read(&self, name: &str) -> Result<BodyNode> {
+        let packages_path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| runtime_error("No packages directory is set"))?;
+        let path = packages_path.join(format!("{}.blz", name));
+        let code = fs::read_to_string(&path).map_err(|err| {
+            runtime_error(format!(
+                "Package '{}' can't be read from {}: {}",
+                name,
+                path.display(),
+                err
+            ))
+        })?;
+        let code_source = path.display().to_string();
+        let mut lexer = Lexer::new(code);
+        lexer.get_context().set_code_source(code_source.clone());
+        let mut parser = Parser::new(lexer.analyze()?);
+        parser.get_context().set_code_source(code_source);
+        parser.parse()
+    }
+
+    pub fn cycle_error(&self, name: &str) -> std::io::Error {
+        let position = self
+            .loading
+            .iter()
+            .position(|loading| loading == name)
+            .unwrap_or_default();
+        let mut cycle = self.loading[position..].to_vec();
+        cycle.push(name.to_string());
+        runtime_error(format!(
+            "Packages import each other in a cycle: {}",
+            cycle.join(" -> ")
+        ))
+    }
+}
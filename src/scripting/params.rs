@@ -0,0 +1,111 @@
+use bson::{Bson, Document};
+use std::fmt;
+use std::io::Result;
+
+use super::executor::runtime_error;
+
+// A placeholder of a script, `$name` or `?`. The question marks are numbered from 0
+// in the order they're written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Placeholder {
+    Named(String),
+    Positional(usize),
+}
+
+impl fmt::Display for Placeholder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Placeholder::Named(name) => write!(f, "${}", name),
+            Placeholder::Positional(position) => write!(f, "? number {}", position + 1),
+        }
+    }
+}
+
+// The values the placeholders of a script are bound to, sent apart from the script:
+// a document for `$name` placeholders or an array for `?` ones.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Params {
+    pub named: Document,
+    pub positional: Vec<Bson>,
+}
+
+impl Params {
+    pub fn is_empty(&self) -> bool {
+        self.named.is_empty() && self.positional.is_empty()
+    }
+
+    pub fn from_bson(value: Bson) -> Option<Self> {
+        match value {
+            Bson::Null => Some(Params::default()),
+            Bson::Document(named) => Some(named.into()),
+            Bson::Array(positional) => Some(positional.into()),
+            _ => None,
+        }
+    }
+
+    pub fn to_bson(&self) -> Bson {
+        if self.positional.is_empty() {
+            Bson::Document(self.named.clone())
+        } else {
+            Bson::Array(self.positional.clone())
+        }
+    }
+
+    pub fn get(&self, placeholder: &Placeholder) -> Result<Bson> {
+        let value = match placeholder {
+            Placeholder::Named(name) => self.named.get(name),
+            Placeholder::Positional(position) => self.positional.get(*position),
+        };
+        value
+            .cloned()
+            .ok_or_else(|| runtime_error(format!("No value is bound to {}", placeholder)))
+    }
+
+    // Every placeholder has to be bound, and every value has to be used, so that a
+    // misspelt name or a miscounted value doesn't go unnoticed.
+    pub fn check(&self, placeholders: &[Placeholder]) -> Result<()> {
+        for placeholder in placeholders {
+            self.get(placeholder)?;
+        }
+        if let Some(name) = self
+            .named
+            .keys()
+            .find(|name| !placeholders.contains(&Placeholder::Named(name.to_string())))
+        {
+            return Err(runtime_error(format!(
+                "The script has no placeholder ${}",
+                name
+            )));
+        }
+        let positions = placeholders
+            .iter()
+            .filter(|placeholder| matches!(placeholder, Placeholder::Positional(_)))
+            .count();
+        if self.positional.len() > positions {
+            return Err(runtime_error(format!(
+                "The script has {} ? placeholders, but {} values are given",
+                positions,
+                self.positional.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl From<Document> for Params {
+    fn from(named: Document) -> Self {
+        Params {
+            named,
+            positional: vec![],
+        }
+    }
+}
+
+impl From<Vec<Bson>> for Params {
+    fn from(positional: Vec<Bson>) -> Self {
+        Params {
+            named: Document::new(),
+            positional,
+        }
+    }
+}
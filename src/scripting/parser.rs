@@ -1,30 +1,52 @@
+use super::ast::access::{AccessNode, AccessStatement};
 use super::ast::binary_operator::BinaryOperatorNode;
 use super::ast::body::BodyNode;
 use super::ast::boolean::BooleanNode;
 use super::ast::call::CallNode;
+use super::ast::deletion::DeletionNode;
+use super::ast::enum_declaration::EnumDeclarationNode;
+use super::ast::event_declaration::EventDeclarationNode;
 use super::ast::expression::ExpressionNode;
 use super::ast::function_declaration::FunctionDeclarationNode;
 use super::ast::identifier::IdentifierNode;
+use super::ast::import::ImportNode;
+use super::ast::index_declaration::IndexDeclarationNode;
+use super::ast::insertion::InsertionNode;
+use super::ast::inspect::{InspectNode, InspectTarget};
 use super::ast::member::MemberNode;
 use super::ast::null::NullNode;
 use super::ast::number::NumberNode;
+use super::ast::package_declaration::PackageDeclarationNode;
 use super::ast::parameter::{Parameter, ParameterType, Parameters};
+use super::ast::placeholder::PlaceholderNode;
+use super::ast::query::{ConditionNode, QueryNode, SelectorStep};
 use super::ast::string::StringNode;
+use super::ast::table_declaration::TableDeclarationNode;
 use super::ast::unary_operator::UnaryOperatorNode;
 use super::ast::variable_declaration::VariableDeclaration;
 use super::context::Context;
+use super::params::Placeholder;
 use super::tokens::{
-    Token, TokenSide, TokenType, BINARY_OPERATOR_TOKENS, FORMULA_TOKENS, UNARY_OPERATOR_TOKENS,
-    VARIABLE_ASSIGNMENT_TOKENS,
+    Token, TokenSide, TokenType, BINARY_OPERATOR_TOKENS, CONDITION_OPERATOR_TOKENS, FORMULA_TOKENS,
+    UNARY_OPERATOR_TOKENS, VARIABLE_ASSIGNMENT_TOKENS,
 };
+use crate::db::access::{Grant, Permission, Resource};
+use crate::db::aggregation::{Aggregate, Aggregation, Grouping};
+use crate::db::constraints::Constraint;
+use crate::db::events::{Event, Operation};
+use crate::db::query::{Comparison, SortKey};
+use crate::db::schema::{Column, ColumnType, EnumDefinition, KeyStrategy, OnDelete, TableSchema};
 use colored::*;
 use rand::seq::SliceRandom;
 use std::io::{self, Result};
+use std::str::FromStr;
 
 pub struct Parser {
     tokens: Vec<Token>,
     context: Context,
     parser_position: u64,
+    // The `$name` and `?` placeholders met so far.
+    placeholders: Vec<Placeholder>,
 }
 
 impl Parser {
@@ -33,9 +55,14 @@ impl Parser {
             context: Context::default(),
             tokens,
             parser_position: 0,
+            placeholders: vec![],
         }
     }
 
+    pub fn placeholders(&self) -> &[Placeholder] {
+        &self.placeholders
+    }
+
     pub fn parse(&mut self) -> Result<BodyNode> {
         let mut root = BodyNode::new();
         let mut add_node = |node: Box<dyn ExpressionNode>| {
@@ -48,21 +75,13 @@ impl Parser {
                     let parsed_expression = parsed_expression?;
                     add_node(parsed_expression.unwrap());
                     if self.move_if_position_is_movable() {
-                        let semicolon_required = self.require_token(vec![TokenType::ExpressionEnd]);
-                        if semicolon_required.is_err() {
-                            let err = semicolon_required.unwrap_err();
-                            println!("{}", err);
-                            return Ok(BodyNode::new());
-                        };
+                        self.require_token(vec![TokenType::ExpressionEnd])?;
                         continue;
                     };
                     break;
                 }
                 Ok(None) => break,
-                Err(error) => {
-                    eprintln!("{}", error);
-                    return Ok(BodyNode::new());
-                }
+                Err(error) => return Err(error),
             }
         }
         Ok(root)
@@ -77,14 +96,11 @@ impl Parser {
             let current_token = self.tokens[self.parser_position as usize].clone();
             return Ok(current_token);
         }
-        Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!(
-                "{}: {}",
-                "FATAL".red(),
-                "Attempted to access a non-existent token"
-            ),
-        ))
+        Err(io::Error::other(format!(
+            "{}: {}",
+            "FATAL".red(),
+            "Attempted to access a non-existent token"
+        )))
     }
 
     fn move_position(&mut self) -> Token {
@@ -144,6 +160,23 @@ impl Parser {
         current_token
     }
 
+    // Words like `on` and `to` structure a few statements without being keywords.
+    fn require_word(&mut self, word: &str) -> Result<Token> {
+        let token = self.require_token(vec![TokenType::Alphanumeric])?;
+        if token.value == word {
+            return Ok(token);
+        }
+        Err(io::Error::other(format!(
+            "{}: '{}' is expected instead of '{}' <-= at {}:{}:{}",
+            "Syntax Error".bright_red(),
+            word,
+            token.value,
+            self.context.code_source,
+            token.line + 1,
+            token.start + 1
+        )))
+    }
+
     fn raise_expected_tokens_error(&mut self, expected_tokens: Vec<TokenType>) -> Result<()> {
         let mut shuffled_tokens = expected_tokens;
         shuffled_tokens.shuffle(&mut rand::thread_rng());
@@ -174,7 +207,7 @@ impl Parser {
                 self.context.code_source, self.context.line, self.context.position
             )
         );
-        Err(io::Error::new(io::ErrorKind::Other, error_message))
+        Err(io::Error::other(error_message))
     }
 
     fn parse_expression(&mut self) -> Result<Option<Box<dyn ExpressionNode>>> {
@@ -218,21 +251,253 @@ impl Parser {
                 if datatype.is_none() {
                     self.move_position_back();
                 }
+                let mut body = None;
+                if self.move_if_next_token_is(vec![TokenType::LBracket]) {
+                    body = Some(self.parse_block_source()?);
+                }
                 Ok(Some(
                     Box::new(
                         FunctionDeclarationNode::new(
                         name_token.value,
                         datatype,
-                        arguments
+                        arguments,
+                        body
                     )))
                 )
             }
+            TokenType::Index => {
+                let table_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                self.move_position();
+                self.require_token(vec![TokenType::Dot])?;
+                self.move_position();
+                let column_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                Ok(Some(Box::new(IndexDeclarationNode::new(
+                    table_token.value,
+                    column_token.value,
+                ))))
+            }
+            TokenType::Table => {
+                let force = self.get_current_token()?.is_type(TokenType::Negotion);
+                if force {
+                    self.move_position();
+                }
+                let name_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                let mut key = KeyStrategy::default();
+                if self.move_if_next_token_is(vec![TokenType::Colon]) {
+                    self.move_position();
+                    let key_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                    key = KeyStrategy::from_str(&key_token.value).map_err(|message| {
+                        io::Error::other(format!(
+                            "{}: {}; increment, uuid, uuid7, str, or int is expected <-= at {}:{}:{}",
+                            "Syntax Error".bright_red(),
+                            message,
+                            self.context.code_source,
+                            key_token.line + 1,
+                            key_token.start + 1
+                        ))
+                    })?;
+                }
+                self.move_position();
+                self.require_token(vec![TokenType::LBracket])?;
+                let columns = self.parse_columns()?;
+                Ok(Some(Box::new(TableDeclarationNode::new(
+                    TableSchema::new(name_token.value, columns).with_key(key),
+                    force,
+                ))))
+            }
+            TokenType::Delete => {
+                self.require_token(vec![TokenType::Link])?;
+                let query_node = self.parse_query()?;
+                Ok(Some(Box::new(DeletionNode::new(query_node))))
+            }
+            TokenType::Package => {
+                let name_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                Ok(Some(Box::new(PackageDeclarationNode::new(name_token.value))))
+            }
+            TokenType::Import => {
+                let mut items: Vec<(String, String)> = vec![];
+                loop {
+                    let package_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                    self.move_position();
+                    self.require_token(vec![TokenType::Colon])?;
+                    self.move_position();
+                    let item_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                    items.push((package_token.value, item_token.value));
+                    if !self.move_if_next_token_is(vec![TokenType::Comma]) {
+                        break;
+                    }
+                    self.move_position();
+                }
+                Ok(Some(Box::new(ImportNode::new(items))))
+            }
+            TokenType::Event => {
+                let name_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                self.move_position();
+                self.require_token(vec![TokenType::LPar])?;
+                self.move_position();
+                self.require_token(vec![TokenType::Link])?;
+                self.move_position();
+                let table_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                let mut column = None;
+                if self.move_if_next_token_is(vec![TokenType::Dot]) {
+                    self.move_position();
+                    column = Some(self.require_token(vec![TokenType::Alphanumeric])?.value);
+                }
+                self.move_position();
+                self.require_token(vec![TokenType::Comma])?;
+                self.move_position();
+                let operation_token = self.require_token(vec![TokenType::CharArray])?;
+                let operation_name = operation_token.value.trim_matches('"');
+                let operation = Operation::from_str(operation_name).map_err(|_| {
+                    io::Error::other(format!(
+                        "{}: '{}' isn't an operation; insert, change, or delete is expected <-= at {}:{}:{}",
+                        "Syntax Error".bright_red(),
+                        operation_name,
+                        self.context.code_source,
+                        operation_token.line + 1,
+                        operation_token.start + 1
+                    ))
+                })?;
+                self.move_position();
+                self.require_token(vec![TokenType::RPar])?;
+                self.move_position();
+                self.require_token(vec![TokenType::LBracket])?;
+                let body = self.parse_block_source()?;
+                Ok(Some(Box::new(EventDeclarationNode::new(Event {
+                    name: name_token.value,
+                    table: table_token.value,
+                    column,
+                    operation,
+                    body,
+                    package: None,
+                }))))
+            }
+            TokenType::User => {
+                let name_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                self.move_position();
+                self.require_token(vec![TokenType::Assign])?;
+                self.move_position();
+                let password_token = self.require_token(vec![TokenType::CharArray])?;
+                let mut roles: Vec<String> = vec![];
+                if self.move_if_next_token_is(vec![TokenType::Colon]) {
+                    loop {
+                        self.move_position();
+                        roles.push(self.require_token(vec![TokenType::Alphanumeric])?.value);
+                        if !self.move_if_next_token_is(vec![TokenType::Comma]) {
+                            break;
+                        }
+                    }
+                }
+                Ok(Some(Box::new(AccessNode::new(AccessStatement::User {
+                    name: name_token.value,
+                    password: Box::new(StringNode::new(password_token.value)),
+                    roles,
+                }))))
+            }
+            TokenType::Role => {
+                let name_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                Ok(Some(Box::new(AccessNode::new(AccessStatement::Role(
+                    name_token.value,
+                )))))
+            }
+            TokenType::Grant | TokenType::Revoke => {
+                let is_grant = current_token.is_type(TokenType::Grant);
+                let mut permissions: Vec<Permission> = vec![];
+                loop {
+                    let permission_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                    let permission =
+                        Permission::from_str(&permission_token.value).map_err(|_| {
+                            io::Error::other(format!(
+                                "{}: '{}' isn't a permission; read, write, or execute is expected <-= at {}:{}:{}",
+                                "Syntax Error".bright_red(),
+                                permission_token.value,
+                                self.context.code_source,
+                                permission_token.line + 1,
+                                permission_token.start + 1
+                            ))
+                        })?;
+                    permissions.push(permission);
+                    self.move_position();
+                    if !self.get_current_token()?.is_type(TokenType::Comma) {
+                        break;
+                    }
+                    self.move_position();
+                }
+                self.require_word("on")?;
+                self.move_position();
+                let kind_token = self.require_token(vec![
+                    TokenType::Table,
+                    TokenType::Package,
+                    TokenType::Function,
+                ])?;
+                self.move_position();
+                let name = self.require_token(vec![TokenType::Alphanumeric])?.value;
+                let resource = match kind_token.token_type {
+                    TokenType::Table => Resource::Table(name),
+                    TokenType::Package => Resource::Package(name),
+                    _ => Resource::Function(name),
+                };
+                self.move_position();
+                self.require_word(if is_grant { "to" } else { "from" })?;
+                self.move_position();
+                let role = self.require_token(vec![TokenType::Alphanumeric])?.value;
+                let grants = permissions
+                    .into_iter()
+                    .map(|permission| Grant {
+                        permission,
+                        resource: resource.clone(),
+                    })
+                    .collect();
+                Ok(Some(Box::new(AccessNode::new(match is_grant {
+                    true => AccessStatement::Grant { grants, role },
+                    false => AccessStatement::Revoke { grants, role },
+                }))))
+            }
+            TokenType::Inspect => {
+                let target_token =
+                    self.require_token(vec![TokenType::Link, TokenType::Alphanumeric])?;
+                let target = match target_token.value.as_str() {
+                    "&" => InspectTarget::Query(self.parse_query()?),
+                    "all" => InspectTarget::All,
+                    _ => InspectTarget::Name(target_token.value),
+                };
+                Ok(Some(Box::new(InspectNode::new(target))))
+            }
+            TokenType::Enum => {
+                let name_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                self.move_position();
+                let datatype = self.parse_datatype()?;
+                if datatype.is_some() {
+                    self.move_position();
+                }
+                self.require_token(vec![TokenType::LBracket])?;
+                let mut variants: Vec<String> = vec![];
+                loop {
+                    self.move_position();
+                    let variant_token =
+                        self.require_token(vec![TokenType::Alphanumeric, TokenType::RBracket])?;
+                    if variant_token.is_type(TokenType::RBracket) {
+                        break;
+                    }
+                    variants.push(variant_token.value);
+                    self.move_position();
+                    let delimiter_token =
+                        self.require_token(vec![TokenType::Comma, TokenType::RBracket])?;
+                    if delimiter_token.is_type(TokenType::RBracket) {
+                        break;
+                    }
+                }
+                Ok(Some(Box::new(EnumDeclarationNode::new(EnumDefinition {
+                    name: name_token.value,
+                    datatype,
+                    variants,
+                }))))
+            }
             TokenType::ExpressionEnd => {
                 Ok(self.parse_expression()?)
            }
             _ => {
-                Err(io::Error::new(
-                    io::ErrorKind::Other,
+                Err(io::Error::other(
                     format!(
                         "{}: {} hasn't been implemented yet or is not being considered in this context <-= at {}:{}:{}",
                         "Syntax Error".bright_red(),
@@ -286,16 +551,13 @@ impl Parser {
         let _check_if_incorrect_argument_sequence = |is_keyword_argument: bool, this: &mut Self| {
             if !is_keyword_argument && keyword_arguments_time {
                 let current_token = this.get_current_token()?;
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!(
-                        "{}: Positional argument follows keyword argument <-= at {}:{}:{}",
-                        "Syntax Error".bright_red(),
-                        this.context.code_source,
-                        current_token.line + 1,
-                        current_token.start + 1
-                    ),
-                ));
+                return Err(io::Error::other(format!(
+                    "{}: Positional argument follows keyword argument <-= at {}:{}:{}",
+                    "Syntax Error".bright_red(),
+                    this.context.code_source,
+                    current_token.line + 1,
+                    current_token.start + 1
+                )));
             };
             if is_keyword_argument {
                 keyword_arguments_time = true;
@@ -330,27 +592,21 @@ impl Parser {
                 .parse_datatype()
                 .expect("Error occured while datatype parsing");
             if datatype_string.is_none() && parameter_type == ParameterType::Function {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!(
-                        "{}: Argument type is expected <-= {}:{}:{}",
-                        "Syntax Error".bright_red(),
-                        self.context.code_source,
-                        self.context.line,
-                        self.context.position
-                    ),
-                ));
+                return Err(io::Error::other(format!(
+                    "{}: Argument type is expected <-= {}:{}:{}",
+                    "Syntax Error".bright_red(),
+                    self.context.code_source,
+                    self.context.line,
+                    self.context.position
+                )));
             } else if datatype_string.is_some() && parameter_type == ParameterType::Call {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!(
-                        "{}: Call argument doesn't require type notation <-= {}:{}:{}",
-                        "Syntax Error".bright_red(),
-                        self.context.code_source,
-                        self.context.line,
-                        self.context.position
-                    ),
-                ));
+                return Err(io::Error::other(format!(
+                    "{}: Call argument doesn't require type notation <-= {}:{}:{}",
+                    "Syntax Error".bright_red(),
+                    self.context.code_source,
+                    self.context.line,
+                    self.context.position
+                )));
             } else if datatype_string.is_some() {
                 self.move_position();
             };
@@ -367,23 +623,20 @@ impl Parser {
     fn parse_identifiers(&mut self) -> Result<Box<dyn ExpressionNode>> {
         let object_token = self.get_current_token()?;
         let mut object_node: Box<dyn ExpressionNode> =
-            Box::new(IdentifierNode::new(object_token.value));
+            Box::new(IdentifierNode::new(object_token.value.clone()));
         if self.move_if_next_token_is(vec![TokenType::LPar]) {
             let arguments = self.parse_parameters_in_parenthesis(ParameterType::Call)?;
-            object_node = Box::new(CallNode::new(object_node, arguments));
+            object_node = Box::new(CallNode::new(object_token.value, arguments));
         };
         if self.move_if_next_token_is(vec![TokenType::Dot]) {
             if !self.move_if_position_is_movable() {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!(
-                        "{}: Children expected <-= at {}:{}:{}",
-                        "Syntax Error".bright_red(),
-                        self.context.code_source,
-                        self.context.line,
-                        self.context.position + 1
-                    ),
-                ));
+                return Err(io::Error::other(format!(
+                    "{}: Children expected <-= at {}:{}:{}",
+                    "Syntax Error".bright_red(),
+                    self.context.code_source,
+                    self.context.line,
+                    self.context.position + 1
+                )));
             };
             let next_member = self.parse_identifiers()?;
             object_node = Box::new(MemberNode::new(object_node, next_member));
@@ -391,7 +644,461 @@ impl Parser {
         Ok(object_node)
     }
 
+    fn parse_columns(&mut self) -> Result<Vec<Column>> {
+        let mut columns: Vec<Column> = vec![];
+        loop {
+            self.move_position();
+            let name_token =
+                self.require_token(vec![TokenType::Alphanumeric, TokenType::RBracket])?;
+            if name_token.is_type(TokenType::RBracket) {
+                return Ok(columns);
+            }
+            self.move_position();
+            self.require_token(vec![TokenType::Colon])?;
+            self.move_position();
+            let is_reference = self.get_current_token()?.is_type(TokenType::Link);
+            if is_reference {
+                self.move_position();
+            }
+            let type_token = self.require_token(vec![TokenType::Alphanumeric])?;
+            let column_type = if is_reference {
+                ColumnType::Reference(type_token.value)
+            } else {
+                ColumnType::Scalar(type_token.value)
+            };
+            let mut column = Column::new(name_token.value, column_type);
+            while let Some(modifier_token) = self.parse_column_modifier()? {
+                match modifier_token.token_type {
+                    TokenType::LSquareBracket => {
+                        self.move_position();
+                        self.require_token(vec![TokenType::RSquareBracket])?;
+                        column.is_array = true;
+                    }
+                    TokenType::QuestionMark => column.nullable = true,
+                    TokenType::Negotion => column.required = true,
+                    TokenType::Alphanumeric if modifier_token.value == "from" => {
+                        self.move_position();
+                        let previous_name_token =
+                            self.require_token(vec![TokenType::Alphanumeric])?;
+                        column.renamed_from = Some(previous_name_token.value);
+                    }
+                    TokenType::Alphanumeric if is_reference => {
+                        column.on_delete = OnDelete::from_str(&modifier_token.value).map_err(|_| {
+                            io::Error::other(format!(
+                                "{}: '{}' isn't a deletion policy; restrict, cascade, or nullify is expected <-= at {}:{}:{}",
+                                "Syntax Error".bright_red(),
+                                modifier_token.value,
+                                self.context.code_source,
+                                self.context.line,
+                                self.context.position
+                            ))
+                        })?;
+                    }
+                    _ => column.constraints.push(self.parse_constraint()?),
+                }
+            }
+            if self.move_if_next_token_is(vec![TokenType::Assign]) {
+                self.move_position();
+                let default_start = self.parser_position;
+                self.require_formula()?;
+                column.default = Some(self.get_source(default_start, self.parser_position));
+            }
+            columns.push(column);
+            self.move_position();
+            let delimiter_token = self.require_token(vec![
+                TokenType::Comma,
+                TokenType::ExpressionEnd,
+                TokenType::RBracket,
+            ])?;
+            if delimiter_token.is_type(TokenType::RBracket) {
+                return Ok(columns);
+            }
+        }
+    }
+
+    // Moves to the next token if it modifies the column being parsed,
+    // e.g. `[]`, `?`, `!`, `<=30`, or a deletion policy.
+    fn parse_column_modifier(&mut self) -> Result<Option<Token>> {
+        let modifier_tokens = vec![
+            TokenType::LSquareBracket,
+            TokenType::QuestionMark,
+            TokenType::Negotion,
+            TokenType::Alphanumeric,
+            TokenType::Number,
+            TokenType::Greater,
+            TokenType::Less,
+            TokenType::GreaterOrEqual,
+            TokenType::LessOrEqual,
+        ];
+        if self.move_if_next_token_is(modifier_tokens) {
+            return Ok(Some(self.get_current_token()?));
+        }
+        Ok(None)
+    }
+
+    // A bound with no comparison sign requires the exact length or value, e.g. `str 2`.
+    fn parse_constraint(&mut self) -> Result<Constraint> {
+        let sign_token = self.get_current_token()?;
+        let comparison = match sign_token.token_type {
+            TokenType::Greater => Comparison::Greater,
+            TokenType::Less => Comparison::Less,
+            TokenType::GreaterOrEqual => Comparison::GreaterOrEqual,
+            TokenType::LessOrEqual => Comparison::LessOrEqual,
+            _ => Comparison::Equal,
+        };
+        if comparison != Comparison::Equal {
+            self.move_position();
+        }
+        let is_negative = self
+            .require_token(vec![TokenType::Number, TokenType::Subtraction])?
+            .is_type(TokenType::Subtraction);
+        if is_negative {
+            self.move_position();
+        }
+        let bound_token = self.require_token(vec![TokenType::Number])?;
+        let bound = bound_token
+            .value
+            .parse::<f64>()
+            .ok()
+            .filter(|bound| bound.is_finite())
+            .ok_or_else(|| {
+                io::Error::other(format!(
+                    "{}: '{}' is too large to be a bound <-= at {}:{}:{}",
+                    "Syntax Error".bright_red(),
+                    bound_token.value,
+                    self.context.code_source,
+                    bound_token.line + 1,
+                    bound_token.start + 1
+                ))
+            })?;
+        Ok(Constraint::new(
+            comparison,
+            if is_negative { -bound } else { bound },
+        ))
+    }
+
+    // Restores the code of the tokens between two positions, both inclusive.
+    // Tokens separated by whitespace in the code are separated by a single space.
+    fn get_source(&self, start: u64, stop: u64) -> String {
+        let mut source = String::new();
+        let mut previous_stop: Option<u64> = None;
+        for token in &self.tokens[start as usize..=stop as usize] {
+            if previous_stop.is_some_and(|previous_stop| previous_stop < token.start) {
+                source.push(' ');
+            }
+            source.push_str(&token.value);
+            previous_stop = Some(token.stop);
+        }
+        source
+    }
+
+    fn parse_link(&mut self) -> Result<Box<dyn ExpressionNode>> {
+        self.move_position();
+        let table_token = self.require_token(vec![TokenType::Alphanumeric])?;
+        if !self.move_if_next_token_is(vec![TokenType::LPar]) {
+            self.move_position_back();
+            return Ok(Box::new(self.parse_query()?));
+        }
+        let mut values: Vec<(String, Box<dyn ExpressionNode>)> = vec![];
+        if self.move_if_next_token_is(vec![TokenType::RPar]) {
+            return Ok(Box::new(InsertionNode::new(table_token.value, values)));
+        }
+        loop {
+            self.move_position();
+            let column_token = self.require_token(vec![TokenType::Alphanumeric])?;
+            self.move_position();
+            self.require_token(vec![TokenType::Assign])?;
+            self.move_position();
+            values.push((column_token.value, self.require_formula()?));
+            self.move_position();
+            let delimiter_token = self.require_token(vec![TokenType::Comma, TokenType::RPar])?;
+            if delimiter_token.is_type(TokenType::RPar) {
+                return Ok(Box::new(InsertionNode::new(table_token.value, values)));
+            }
+        }
+    }
+
+    // Checks the statements of a `{ ... }` block whose opening bracket is the current
+    // token and returns their source code, which is evaluated later, e.g. by an event.
+    fn parse_block_source(&mut self) -> Result<String> {
+        let start = self.parser_position + 1;
+        loop {
+            self.move_position();
+            let is_closed = self
+                .get_current_token()
+                .is_ok_and(|token| token.is_type(TokenType::RBracket));
+            if is_closed {
+                break;
+            }
+            if self.parse_expression()?.is_none() {
+                self.require_token(vec![TokenType::RBracket])?;
+            }
+            self.move_position();
+            let delimiter_token =
+                self.require_token(vec![TokenType::ExpressionEnd, TokenType::RBracket])?;
+            if delimiter_token.is_type(TokenType::RBracket) {
+                break;
+            }
+        }
+        if self.parser_position == start {
+            return Ok(String::new());
+        }
+        Ok(self.get_source(start, self.parser_position - 1))
+    }
+
+    fn parse_query(&mut self) -> Result<QueryNode> {
+        self.move_position();
+        let table_token = self.require_token(vec![TokenType::Alphanumeric])?;
+        let mut steps: Vec<SelectorStep> = vec![];
+        while self.move_if_next_token_is(vec![TokenType::Dot]) {
+            self.move_position();
+            let step_token =
+                self.require_token(vec![TokenType::LBracket, TokenType::Alphanumeric])?;
+            steps.push(match step_token.token_type {
+                TokenType::LBracket => SelectorStep::Filter(self.parse_conditions()?),
+                _ if self.move_if_next_token_is(vec![TokenType::LPar]) => {
+                    match step_token.value.as_str() {
+                        "group" => SelectorStep::Group(self.parse_grouping()?),
+                        "order" => SelectorStep::Order(self.parse_sort_keys()?),
+                        "limit" => SelectorStep::Limit(self.parse_step_argument()?),
+                        "offset" => SelectorStep::Offset(self.parse_step_argument()?),
+                        "referenced_by" => {
+                            self.move_position();
+                            let table = self.require_token(vec![TokenType::Alphanumeric])?.value;
+                            self.move_position();
+                            self.require_token(vec![TokenType::Dot])?;
+                            self.move_position();
+                            let column = self.require_token(vec![TokenType::Alphanumeric])?.value;
+                            self.move_position();
+                            self.require_token(vec![TokenType::RPar])?;
+                            SelectorStep::ReferencedBy(table, column)
+                        }
+                        name => {
+                            return Err(io::Error::other(format!(
+                                "{}: '{}' isn't a selector step; group, order, limit, offset, or referenced_by is expected <-= at {}:{}:{}",
+                                "Syntax Error".bright_red(),
+                                name,
+                                self.context.code_source,
+                                step_token.line + 1,
+                                step_token.start + 1
+                            )))
+                        }
+                    }
+                }
+                _ => SelectorStep::Field(step_token.value),
+            });
+        }
+        Ok(QueryNode::new(table_token.value, steps))
+    }
+
+    // `group(seller, year){total: sum(amount), deals: count()}`, where `group()`
+    // aggregates the whole selection as one group.
+    fn parse_grouping(&mut self) -> Result<Grouping> {
+        let mut keys: Vec<String> = vec![];
+        if !self.move_if_next_token_is(vec![TokenType::RPar]) {
+            loop {
+                self.move_position();
+                keys.push(self.require_token(vec![TokenType::Alphanumeric])?.value);
+                self.move_position();
+                let delimiter_token =
+                    self.require_token(vec![TokenType::Comma, TokenType::RPar])?;
+                if delimiter_token.is_type(TokenType::RPar) {
+                    break;
+                }
+            }
+        }
+        self.move_position();
+        self.require_token(vec![TokenType::LBracket])?;
+        let mut aggregations: Vec<Aggregation> = vec![];
+        loop {
+            self.move_position();
+            let name = self.require_token(vec![TokenType::Alphanumeric])?.value;
+            self.move_position();
+            self.require_token(vec![TokenType::Colon])?;
+            self.move_position();
+            let function_token = self.require_token(vec![TokenType::Alphanumeric])?;
+            let aggregate = Aggregate::from_str(&function_token.value).map_err(|_| {
+                io::Error::other(format!(
+                    "{}: '{}' isn't an aggregate; count, sum, avg, min, or max is expected <-= at {}:{}:{}",
+                    "Syntax Error".bright_red(),
+                    function_token.value,
+                    self.context.code_source,
+                    function_token.line + 1,
+                    function_token.start + 1
+                ))
+            })?;
+            self.move_position();
+            self.require_token(vec![TokenType::LPar])?;
+            // `count()` counts the rows themselves.
+            let is_row_count =
+                aggregate == Aggregate::Count && self.move_if_next_token_is(vec![TokenType::RPar]);
+            let column = if is_row_count {
+                None
+            } else {
+                self.move_position();
+                let column_token = self.require_token(vec![TokenType::Alphanumeric])?;
+                self.move_position();
+                self.require_token(vec![TokenType::RPar])?;
+                Some(column_token.value)
+            };
+            aggregations.push(Aggregation {
+                name,
+                aggregate,
+                column,
+            });
+            self.move_position();
+            let delimiter_token =
+                self.require_token(vec![TokenType::Comma, TokenType::RBracket])?;
+            if delimiter_token.is_type(TokenType::RBracket) {
+                return Ok(Grouping::new(keys, aggregations));
+            }
+        }
+    }
+
+    // `order(created_at desc, name)`, where keys are ascending unless followed by `desc`.
+    fn parse_sort_keys(&mut self) -> Result<Vec<SortKey>> {
+        let mut keys: Vec<SortKey> = vec![];
+        loop {
+            self.move_position();
+            let column = self.require_token(vec![TokenType::Alphanumeric])?.value;
+            self.move_position();
+            let mut delimiter_token = self.require_token(vec![
+                TokenType::Comma,
+                TokenType::RPar,
+                TokenType::Alphanumeric,
+            ])?;
+            let descending = delimiter_token.value == "desc";
+            if delimiter_token.is_type(TokenType::Alphanumeric) {
+                if !descending && delimiter_token.value != "asc" {
+                    return Err(io::Error::other(format!(
+                        "{}: '{}' isn't a direction; asc or desc is expected <-= at {}:{}:{}",
+                        "Syntax Error".bright_red(),
+                        delimiter_token.value,
+                        self.context.code_source,
+                        delimiter_token.line + 1,
+                        delimiter_token.start + 1
+                    )));
+                }
+                self.move_position();
+                delimiter_token = self.require_token(vec![TokenType::Comma, TokenType::RPar])?;
+            }
+            keys.push(SortKey { column, descending });
+            if delimiter_token.is_type(TokenType::RPar) {
+                return Ok(keys);
+            }
+        }
+    }
+
+    // The parenthesized expression of `limit(...)` or `offset(...)`.
+    fn parse_step_argument(&mut self) -> Result<Box<dyn ExpressionNode>> {
+        self.move_position();
+        let argument = self.require_formula()?;
+        self.move_position();
+        self.require_token(vec![TokenType::RPar])?;
+        Ok(argument)
+    }
+
+    fn parse_conditions(&mut self) -> Result<Vec<ConditionNode>> {
+        let mut conditions: Vec<ConditionNode> = vec![];
+        loop {
+            self.move_position();
+            let self_token = self.require_token(vec![TokenType::Alphanumeric])?;
+            if self_token.value != "self" {
+                return Err(io::Error::other(format!(
+                    "{}: Conditions must start with 'self' <-= at {}:{}:{}",
+                    "Syntax Error".bright_red(),
+                    self.context.code_source,
+                    self.context.line,
+                    self.context.position
+                )));
+            }
+            let mut path: Vec<String> = vec![];
+            while self.move_if_next_token_is(vec![TokenType::Dot]) {
+                self.move_position();
+                path.push(self.require_token(vec![TokenType::Alphanumeric])?.value);
+            }
+            self.move_position();
+            if path.is_empty() {
+                self.raise_expected_tokens_error(vec![TokenType::Dot])?;
+            }
+            let mut operator_types = CONDITION_OPERATOR_TOKENS.to_vec();
+            operator_types.push(TokenType::Alphanumeric);
+            let operator_token = self.require_token(operator_types)?;
+            if operator_token.is_type(TokenType::Alphanumeric) && operator_token.value != "within" {
+                return Err(io::Error::other(format!(
+                    "{}: '{}' isn't a comparison <-= at {}:{}:{}",
+                    "Syntax Error".bright_red(),
+                    operator_token.value,
+                    self.context.code_source,
+                    operator_token.line + 1,
+                    operator_token.start + 1
+                )));
+            }
+            let comparison = match operator_token.token_type {
+                TokenType::Alphanumeric => Comparison::Within,
+                TokenType::NotEqualSign => Comparison::NotEqual,
+                TokenType::Greater => Comparison::Greater,
+                TokenType::Less => Comparison::Less,
+                TokenType::GreaterOrEqual => Comparison::GreaterOrEqual,
+                TokenType::LessOrEqual => Comparison::LessOrEqual,
+                _ => Comparison::Equal,
+            };
+            self.move_position();
+            let value_node = self.require_formula()?;
+            conditions.push(ConditionNode::new(path, comparison, value_node));
+            self.move_position();
+            let delimiter_token =
+                self.require_token(vec![TokenType::Comma, TokenType::RBracket])?;
+            if delimiter_token.is_type(TokenType::RBracket) {
+                return Ok(conditions);
+            }
+        }
+    }
+
     fn parse_formula(&mut self) -> Result<Option<Box<dyn ExpressionNode>>> {
+        self.parse_operation(0)
+    }
+
+    // Precedence climbing: an operator binding tighter than the one before it takes the
+    // operand between them, so `1 + 2 * 3` is `1 + (2 * 3)`, and operators binding
+    // alike group to the left, so `10 - 2 - 3` is `(10 - 2) - 3`.
+    fn parse_operation(&mut self, min_precedence: u8) -> Result<Option<Box<dyn ExpressionNode>>> {
+        let Some(mut left_operand) = self.parse_operand()? else {
+            return Ok(None);
+        };
+        loop {
+            let operators = BINARY_OPERATOR_TOKENS
+                .into_iter()
+                .filter(|operator| {
+                    operator
+                        .precedence()
+                        .is_some_and(|precedence| precedence >= min_precedence)
+                })
+                .collect();
+            if !self.move_if_next_token_is(operators) {
+                return Ok(Some(left_operand));
+            }
+            let operator = self.move_position().token_type;
+            let precedence = operator.precedence().unwrap_or_default();
+            let right_precedence = if operator.is_right_associative() {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let Some(right_operand) = self.parse_operation(right_precedence)? else {
+                self.raise_expected_tokens_error(FORMULA_TOKENS.to_vec())?;
+                return Ok(None);
+            };
+            left_operand = Box::new(BinaryOperatorNode::new(
+                operator,
+                left_operand,
+                right_operand,
+            ));
+        }
+    }
+
+    // An operand of a formula with its unary operators, e.g. `!done`.
+    fn parse_operand(&mut self) -> Result<Option<Box<dyn ExpressionNode>>> {
         let mut unary_operator_tokens: Vec<Token> = vec![];
         let mut prohibited_unary_operator_types: Vec<TokenType> = vec![];
 
@@ -401,17 +1108,14 @@ impl Parser {
                     .into_iter()
                     .any(|x| token_to_check.is_type(x))
                 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        format!(
-                            "{}: '{}' operator is already used <-= at {}:{}:{}",
-                            "Syntax Error".bright_red(),
-                            token_to_check.token_type,
-                            this.context.code_source,
-                            token_to_check.line,
-                            token_to_check.start
-                        ),
-                    ));
+                    return Err(io::Error::other(format!(
+                        "{}: '{}' operator is already used <-= at {}:{}:{}",
+                        "Syntax Error".bright_red(),
+                        token_to_check.token_type,
+                        this.context.code_source,
+                        token_to_check.line,
+                        token_to_check.start
+                    )));
                 }
                 Ok(())
             };
@@ -447,12 +1151,28 @@ impl Parser {
         let formula_token = self.get_current_token()?;
         let mut left_operand: Box<dyn ExpressionNode> = match formula_token.token_type {
             TokenType::Alphanumeric => self.parse_identifiers()?,
+            TokenType::Link => self.parse_link()?,
             TokenType::CharArray => Box::new(StringNode::new(formula_token.value)),
             TokenType::Number => Box::new(NumberNode::new(formula_token.value.parse().unwrap())),
             TokenType::Null => Box::new(NullNode),
             TokenType::True | TokenType::False => {
                 Box::new(BooleanNode::new(formula_token.token_type)?)
             }
+            TokenType::Placeholder | TokenType::QuestionMark => {
+                let placeholder = match formula_token.value.strip_prefix('$') {
+                    Some(name) => Placeholder::Named(name.to_string()),
+                    None => Placeholder::Positional(
+                        self.placeholders
+                            .iter()
+                            .filter(|placeholder| matches!(placeholder, Placeholder::Positional(_)))
+                            .count(),
+                    ),
+                };
+                if !self.placeholders.contains(&placeholder) {
+                    self.placeholders.push(placeholder.clone());
+                }
+                Box::new(PlaceholderNode::new(placeholder))
+            }
             _ => {
                 self.raise_expected_tokens_error(FORMULA_TOKENS.to_vec())?;
                 Box::new(NullNode {})
@@ -465,17 +1185,6 @@ impl Parser {
                 TokenSide::Left,
             ));
         }
-        if self.move_if_next_token_is(BINARY_OPERATOR_TOKENS.to_vec()) {
-            let operator = self.get_current_token()?;
-            self.move_position();
-            let right_operand = self.parse_formula()?.unwrap();
-            let binary_operator_node = Box::new(BinaryOperatorNode::new(
-                operator.token_type,
-                left_operand,
-                right_operand,
-            ));
-            return Ok(Some(binary_operator_node));
-        };
         Ok(Some(left_operand))
     }
 
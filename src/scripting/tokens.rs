@@ -25,9 +25,18 @@ pub enum TokenSide {
 #[derive(Debug, EnumIter, Display, Clone, PartialEq)]
 pub enum TokenType {
     Import,
+    Package,
     Manage,
     Attach,
     Inspect,
+    Index,
+    Table,
+    Delete,
+    Event,
+    User,
+    Role,
+    Grant,
+    Revoke,
     // Conditions
     If,
     Else,
@@ -36,8 +45,6 @@ pub enum TokenType {
     Break,
     Return,
     // Unary Operators
-    Negotion,
-    Link,
     Increment,
     Decrement,
     // Binary Operators
@@ -47,11 +54,14 @@ pub enum TokenType {
     Division,
     EqualSign,
     NotEqualSign,
-    Greater,
-    Less,
     GreaterOrEqual,
     LessOrEqual,
+    Greater,
+    Less,
     Hat,
+    // Unary Operators that structure binary ones
+    Negotion,
+    Link,
     // Assignment
     Assign,
     Mut,
@@ -63,6 +73,8 @@ pub enum TokenType {
     RPar,
     LBracket,
     RBracket,
+    LSquareBracket,
+    RSquareBracket,
     // Types
     CharArray,
     Number,
@@ -70,6 +82,8 @@ pub enum TokenType {
     Dot,
     Comma,
     Colon,
+    QuestionMark,
+    Placeholder,
     True,
     False,
     Null,
@@ -82,6 +96,29 @@ pub enum TokenType {
 }
 
 impl TokenType {
+    // How tightly a binary operator binds its operands: `^` tightest, then `*` and `/`,
+    // `+` and `-`, the comparisons, and `=` last.
+    pub fn precedence(&self) -> Option<u8> {
+        match self {
+            TokenType::Assign => Some(1),
+            TokenType::EqualSign
+            | TokenType::NotEqualSign
+            | TokenType::Greater
+            | TokenType::Less
+            | TokenType::GreaterOrEqual
+            | TokenType::LessOrEqual => Some(2),
+            TokenType::Addition | TokenType::Subtraction => Some(3),
+            TokenType::Multiplication | TokenType::Division => Some(4),
+            TokenType::Hat => Some(5),
+            _ => None,
+        }
+    }
+
+    // `2 ^ 3 ^ 2` is `2 ^ 9`, and `a = b = 1` is `a = (b = 1)`.
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self, TokenType::Assign | TokenType::Hat)
+    }
+
     pub fn regex_str(&self) -> &str {
         match self {
             TokenType::If => r"if\b",
@@ -94,9 +131,18 @@ impl TokenType {
             TokenType::Else => r"else\b",
             TokenType::While => r"while\b",
             TokenType::Import => r"import\b",
+            TokenType::Package => r"package\b",
             TokenType::Manage => r"manage\b",
             TokenType::Attach => r"attach\b",
             TokenType::Inspect => r"inspect\b",
+            TokenType::Index => r"index\b",
+            TokenType::Table => r"table\b",
+            TokenType::Delete => r"delete\b",
+            TokenType::Event => r"event\b",
+            TokenType::User => r"user\b",
+            TokenType::Role => r"role\b",
+            TokenType::Grant => r"grant\b",
+            TokenType::Revoke => r"revoke\b",
             TokenType::Function => r"function\b",
             TokenType::Continue => r"continue\b",
             TokenType::Break => r"break\b",
@@ -121,13 +167,17 @@ impl TokenType {
             TokenType::RPar => r"\)",
             TokenType::LBracket => r"\{",
             TokenType::RBracket => r"\}",
-            TokenType::CharArray => r#"".*?[^\\]"|"""#,
+            TokenType::LSquareBracket => r"\[",
+            TokenType::RSquareBracket => r"\]",
+            TokenType::CharArray => r#""(?:[^"\\]|\\.)*""#,
             TokenType::Alphanumeric => r"[a-zA-Z_]\w*",
             TokenType::Number => r"\d+(\.\d+)?",
             TokenType::Space => r#"\s"#,
             TokenType::Dot => r"\.",
             TokenType::Comma => r",",
             TokenType::Colon => r":",
+            TokenType::QuestionMark => r"\?",
+            TokenType::Placeholder => r"\$[a-zA-Z_]\w*",
             TokenType::ExpressionEnd => r";",
             TokenType::NewLine => r"\n",
             TokenType::Indent => r"\t",
@@ -158,15 +208,25 @@ pub const BINARY_OPERATOR_TOKENS: [TokenType; 12] = [
     TokenType::Assign,
 ];
 
-pub const UNARY_OPERATOR_TOKENS: [TokenType; 4] = [
+pub const UNARY_OPERATOR_TOKENS: [TokenType; 3] = [
     TokenType::Increment,
     TokenType::Decrement,
     TokenType::Negotion,
-    TokenType::Link,
+];
+
+// The operators allowed in selector conditions, e.g. `{self.age >= 18}`.
+pub const CONDITION_OPERATOR_TOKENS: [TokenType; 7] = [
+    TokenType::Assign,
+    TokenType::EqualSign,
+    TokenType::NotEqualSign,
+    TokenType::Greater,
+    TokenType::Less,
+    TokenType::GreaterOrEqual,
+    TokenType::LessOrEqual,
 ];
 
 // The tokens formulas can start with.
-pub const FORMULA_TOKENS: [TokenType; 10] = [
+pub const FORMULA_TOKENS: [TokenType; 12] = [
     TokenType::CharArray,
     TokenType::Number,
     TokenType::Alphanumeric,
@@ -177,6 +237,8 @@ pub const FORMULA_TOKENS: [TokenType; 10] = [
     TokenType::Decrement,
     TokenType::Negotion,
     TokenType::Link,
+    TokenType::Placeholder,
+    TokenType::QuestionMark,
 ];
 
 pub const VARIABLE_ASSIGNMENT_TOKENS: [TokenType; 2] = [TokenType::Mut, TokenType::Fin];
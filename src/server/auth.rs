@@ -0,0 +1,243 @@
+use bson::{doc, Bson, Document};
+use colored::Colorize;
+use rand::distributions::Alphanumeric;
+use rand::{Rng, RngCore};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+
+use crate::db::access::{hmac, Keys, User, HASH_ITERATIONS};
+use crate::db::storage::{storage_error, SharedStorage, Storage};
+
+use super::protocol::{self, protocol_error, ErrorReport, Request, Response};
+
+// Logging in is a SCRAM-SHA-256 handshake, so the password never crosses the wire:
+// the client sends the user and a nonce of its own, the server answers with the nonce
+// extended by one of its own, the user's salt and the iterations, and the client
+// answers with a proof computed from them and the password. The server checks the
+// proof against the stored key and signs the challenge with the server key, so the
+// client knows the server knew the password too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Challenge {
+    pub user: String,
+    pub nonce: String,
+    pub salt: String,
+    pub iterations: u32,
+}
+
+impl Challenge {
+    // What both sides sign.
+    fn message(&self) -> String {
+        format!(
+            "user={},nonce={},salt={},iterations={}",
+            self.user, self.nonce, self.salt, self.iterations
+        )
+    }
+
+    // The client key hidden by the client signature: `ClientKey XOR HMAC(StoredKey, message)`.
+    pub fn proof(&self, keys: &Keys) -> String {
+        let signature = hmac(&keys.stored_key, self.message().as_bytes());
+        hex::encode(xor(&keys.client_key, &signature))
+    }
+
+    pub fn signature(&self, server_key: &[u8]) -> String {
+        hex::encode(hmac(server_key, self.message().as_bytes()))
+    }
+
+    // The client key is recovered from the proof, and its hash has to be the stored key.
+    fn is_proven_by(&self, user: &User, proof: &str) -> bool {
+        let (Ok(proof), Ok(stored_key)) = (hex::decode(proof), hex::decode(&user.stored_key))
+        else {
+            return false;
+        };
+        let signature = hmac(&stored_key, self.message().as_bytes());
+        let client_key = xor(&proof, &signature);
+        user.is_stored_key(&Sha256::digest(client_key))
+    }
+
+    pub fn to_document(&self) -> Document {
+        doc! {
+            "nonce": &self.nonce,
+            "salt": &self.salt,
+            "iterations": self.iterations as i64,
+        }
+    }
+
+    pub fn from_document(user: &str, document: &Document) -> io::Result<Self> {
+        let field = |name: &str| {
+            document
+                .get_str(name)
+                .map(str::to_string)
+                .map_err(|_| protocol_error(format!("The challenge has no '{}' string", name)))
+        };
+        let iterations = match document.get("iterations") {
+            Some(Bson::Int32(iterations)) => u32::try_from(*iterations).ok(),
+            Some(Bson::Int64(iterations)) => u32::try_from(*iterations).ok(),
+            _ => None,
+        }
+        .ok_or_else(|| protocol_error("The challenge has no 'iterations' count"))?;
+        Ok(Challenge {
+            user: user.to_string(),
+            nonce: field("nonce")?,
+            salt: field("salt")?,
+            iterations,
+        })
+    }
+}
+
+fn xor(left: &[u8], right: &[u8]) -> Vec<u8> {
+    left.iter()
+        .zip(right)
+        .map(|(left, right)| left ^ right)
+        .collect()
+}
+
+// A password for the first admin when the server isn't given one.
+pub fn new_password() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(24)
+        .map(char::from)
+        .collect()
+}
+
+pub fn new_nonce() -> String {
+    let mut nonce = [0; 16];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    hex::encode(nonce)
+}
+
+// Users and logins aren't part of transactions, so they're looked up without waiting for them.
+fn lock(storage: &SharedStorage) -> io::Result<std::sync::MutexGuard<'_, Storage>> {
+    storage
+        .lock()
+        .map_err(|_| storage_error("The datablaze storage is poisoned"))
+}
+
+fn refuse_locked_out(storage: &mut Storage, user: &str) -> io::Result<()> {
+    match storage.logins().lockout(user) {
+        Some(lockout) => Err(authentication_error(format!(
+            "Too many failed attempts to log in as '{}'; try again in {} seconds",
+            user,
+            lockout.as_secs().max(1)
+        ))),
+        None => Ok(()),
+    }
+}
+
+// Users that don't exist get a challenge all the same, which they can't answer.
+pub fn challenge(storage: &SharedStorage, user: &str, client_nonce: &str) -> io::Result<Challenge> {
+    if client_nonce.is_empty() {
+        return Err(protocol_error("The challenge takes a nonce of the client"));
+    }
+    let mut storage = lock(storage)?;
+    refuse_locked_out(&mut storage, user)?;
+    Ok(challenge_with(
+        &mut storage,
+        user,
+        format!("{}{}", client_nonce, new_nonce()),
+    ))
+}
+
+fn challenge_with(storage: &mut Storage, user: &str, nonce: String) -> Challenge {
+    let (salt, iterations) = match storage.access().users.get(user) {
+        Some(found) => (found.salt.clone(), found.iterations),
+        None => (storage.logins().made_up_salt(user), HASH_ITERATIONS),
+    };
+    Challenge {
+        user: user.to_string(),
+        nonce,
+        salt,
+        iterations,
+    }
+}
+
+// A challenge handed out over HTTP is answered by a later request, so the server holds it.
+pub fn hold(storage: &SharedStorage, challenge: &Challenge) -> io::Result<()> {
+    lock(storage)?
+        .logins()
+        .hold(&challenge.nonce, &challenge.user);
+    Ok(())
+}
+
+pub fn take(storage: &SharedStorage, nonce: &str) -> io::Result<Challenge> {
+    let mut storage = lock(storage)?;
+    let user = storage
+        .logins()
+        .take(nonce)
+        .ok_or_else(|| authentication_error("The challenge is unknown or has expired"))?;
+    Ok(challenge_with(&mut storage, &user, nonce.to_string()))
+}
+
+// Checks the proof answering the challenge and counts the failures towards a lockout.
+// Returns the user and the server's signature.
+pub fn verify(
+    storage: &SharedStorage,
+    challenge: &Challenge,
+    proof: &str,
+) -> io::Result<(User, String)> {
+    let user = {
+        let mut storage = lock(storage)?;
+        refuse_locked_out(&mut storage, &challenge.user)?;
+        storage.access().users.get(&challenge.user).cloned()
+    };
+    let proven = user.filter(|user| challenge.is_proven_by(user, proof));
+    let mut storage = lock(storage)?;
+    let Some(user) = proven else {
+        storage.logins().fail(&challenge.user);
+        return Err(authentication_error("The user or the password is wrong"));
+    };
+    storage.logins().succeed(&user.name);
+    let signature = hex::decode(&user.server_key)
+        .map(|server_key| challenge.signature(&server_key))
+        .map_err(storage_error)?;
+    Ok((user, signature))
+}
+
+// The client's side of the handshake. Returns the server's answer to the proof,
+// after checking the server's signature when it succeeded.
+pub fn login(stream: &mut (impl Read + Write), user: &str, password: &str) -> io::Result<Response> {
+    let client_nonce = new_nonce();
+    let request = Request::Challenge {
+        user: user.to_string(),
+        nonce: client_nonce.clone(),
+    };
+    let challenge = match exchange(stream, &request)? {
+        Response::Success(Bson::Document(document)) => Challenge::from_document(user, &document)?,
+        Response::Success(_) => return Err(protocol_error("The challenge isn't a document")),
+        failure => return Ok(failure),
+    };
+    if !challenge.nonce.starts_with(&client_nonce) || challenge.nonce == client_nonce {
+        return Err(protocol_error(
+            "The challenge doesn't extend the client's nonce",
+        ));
+    }
+    let keys = Keys::derive(password, &challenge.salt, challenge.iterations);
+    let request = Request::Authenticate {
+        proof: challenge.proof(&keys),
+    };
+    let response = exchange(stream, &request)?;
+    if let Response::Success(Bson::Document(result)) = &response {
+        if result.get_str("signature").ok() != Some(&challenge.signature(&keys.server_key)) {
+            return Ok(Response::Failure(ErrorReport::new(
+                "Authentication",
+                "The server couldn't prove it knows the password",
+            )));
+        }
+    }
+    Ok(response)
+}
+
+fn exchange(stream: &mut (impl Read + Write), request: &Request) -> io::Result<Response> {
+    protocol::write_frame(stream, &request.to_document())?;
+    match protocol::read_frame(stream)? {
+        Some(frame) => Response::from_document(&frame),
+        None => Err(protocol_error("The server closed the connection")),
+    }
+}
+
+pub fn authentication_error(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{}: {}", "Authentication Error".bright_red(), message),
+    )
+}
@@ -1,17 +1,25 @@
+use colored::Colorize;
 use dotenvy::dotenv;
 use regex::Regex;
-use std::{env, ffi::OsStr, path::Path};
+use std::{env, ffi::OsStr, io, path::Path};
 
 pub struct Config {
     pub host: String,
     pub port: String,
+    // The port of the HTTP front end, which is only served when it's given.
+    pub http_port: Option<String>,
     pub manager_file: String,
-    pub password: String,
+    // The password of the admin a new datablaze gets, made up when it isn't given.
+    pub password: Option<String>,
+    pub max_connections: usize,
+    // Seconds a connection may stay idle, or stall a read or a write, before it's closed.
+    pub timeout: u64,
 }
 
 impl Config {
     fn value(user_key: String) -> Option<String> {
-        dotenv().expect(".env file not found");
+        // Without a .env file, only the process environment is looked at.
+        let _ = dotenv();
 
         let user_key = Regex::new(r"env\.(\w+)")
             .unwrap()
@@ -34,20 +42,25 @@ impl Config {
         true
     }
 
-    pub fn parse_arguments(args: Vec<String>) -> Option<Self> {
+    pub fn parse_arguments(args: Vec<String>) -> io::Result<Self> {
         let default = Self::default();
         let mut host = default.host;
         let mut port = default.port;
+        let mut http_port = default.http_port;
         let mut manager_file = default.manager_file;
         let mut password = default.password;
+        let mut max_connections = None;
+        let mut timeout = default.timeout;
 
         for arg in 0..args.len() {
             let str = &args[arg];
-            if str.chars().nth(0)? != '-' {
+            if !str.starts_with('-') {
                 continue;
             }
 
-            let arg = args.get(arg + 1)?;
+            let arg = args
+                .get(arg + 1)
+                .ok_or_else(|| config_error(format!("'{}' is given no value", str)))?;
             let value = if let Some(parse_value) = Config::value(arg.to_string()) {
                 parse_value
             } else {
@@ -57,25 +70,66 @@ impl Config {
             match str.as_str() {
                 "-host" => host.clone_from(&value),
                 "-port" => port.clone_from(&value),
+                "-http_port" => http_port = Some(value),
                 "-blz_file" => manager_file.clone_from(&value),
-                "-password" => password.clone_from(&value),
+                "-password" => password = Some(value),
+                "-max_connections" => max_connections = Some(number(str, &value)?),
+                "-timeout" => timeout = number(str, &value)?,
                 _ => (),
             }
         }
-        Some(Config {
+        let max_connections = match max_connections {
+            Some(max_connections) => max_connections,
+            None => Config::managed_value(&manager_file, "max_connections")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default.max_connections),
+        };
+        Ok(Config {
             host,
             port,
+            http_port,
             manager_file,
             password,
+            max_connections,
+            timeout,
         })
     }
 
+    // A setting of the `manage (...)` block in the manager file, for settings
+    // not given as arguments.
+    fn managed_value(manager_file: &str, name: &str) -> Option<String> {
+        let content = std::fs::read_to_string(manager_file).ok()?;
+        let block = Regex::new(r"(?s)manage\s*\((.*?)\)").unwrap();
+        let settings = block.captures(&content)?.get(1)?.as_str();
+        let setting = Regex::new(&format!(r#"\b{}\s*=\s*"?([^",\s]+)"#, name)).unwrap();
+        Some(setting.captures(settings)?.get(1)?.as_str().to_string())
+    }
+
     fn default() -> Self {
         Config {
             host: "localhost".to_string(),
             port: "3306".to_string(),
+            http_port: None,
             manager_file: "./db/datablaze/manage.blz".to_string(),
-            password: "password".to_string(),
+            password: None,
+            max_connections: 8,
+            timeout: 60,
         }
     }
 }
+
+fn number<T: std::str::FromStr>(argument: &str, value: &str) -> io::Result<T> {
+    value.parse().map_err(|_| {
+        config_error(format!(
+            "'{}' takes a whole number, but '{}' is given",
+            argument, value
+        ))
+    })
+}
+
+pub fn config_error(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{}: {}", "Config Error".bright_red(), message),
+    )
+}
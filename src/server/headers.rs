@@ -1,5 +1,8 @@
 use std::collections::HashMap;
 
+// Header lines are `Name: value`. Only the first colon separates the two, since values
+// like `Host: localhost:3300` hold colons of their own. A line that isn't a header
+// makes the whole header malformed.
 pub fn parse_header(response: String) -> Option<HashMap<String, String>> {
     let mut header: HashMap<String, String> = HashMap::new();
 
@@ -8,17 +11,36 @@ pub fn parse_header(response: String) -> Option<HashMap<String, String>> {
             break;
         }
 
-        let mut parse: Vec<&str> = line.split(':').collect();
-        if parse[1].starts_with(' ') {
-            parse[1] = &parse[1][1..];
+        let (name, value) = line.split_once(':')?;
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return None;
         }
 
-        header.insert(parse[0].to_string(), parse[1].to_string());
+        header.insert(name.to_string(), value.trim().to_string());
     }
 
     Some(header)
 }
 
+// Header names are case-insensitive.
+pub fn get<'a>(header: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+    header
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+// `None` when the header has no `Content-Length`, an error when it isn't a length.
+pub fn content_length(header: &HashMap<String, String>) -> Result<Option<usize>, String> {
+    get(header, "Content-Length")
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| format!("'{}' isn't a content length", value))
+        })
+        .transpose()
+}
+
 pub fn remove_empty_line(response: String) -> Option<String> {
     let mut empty_line_found = false;
     let mut data = String::new();
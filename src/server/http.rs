@@ -0,0 +1,453 @@
+use bson::Bson;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::db::access::ADMIN_USER;
+use crate::db::storage::SharedStorage;
+use crate::scripting::params::Params;
+use crate::server::{
+    auth, headers,
+    pool::{self, ConnectionPool},
+    protocol::{ErrorReport, Request, Response, MAX_FRAME_SIZE, PROTOCOL_VERSION},
+    session::{self, Session},
+};
+
+// How many bytes the request line and the header lines may take together.
+pub const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+// A request as far as it's needed here: the body is read whole, whether it's sent
+// with a `Content-Length` or in chunks.
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub version: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    // HTTP/1.1 connections stay open unless they're closed, HTTP/1.0 ones the other way round.
+    pub fn keeps_alive(&self) -> bool {
+        match headers::get(&self.headers, "Connection") {
+            Some(connection) if connection.eq_ignore_ascii_case("close") => false,
+            Some(connection) if connection.eq_ignore_ascii_case("keep-alive") => true,
+            _ => self.version == "HTTP/1.1",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, body: Value) -> Self {
+        HttpResponse { status, body }
+    }
+
+    // Errors have the shape of failed responses of the wire protocol.
+    pub fn failure(report: ErrorReport) -> Self {
+        HttpResponse::from_response(Response::Failure(report))
+    }
+
+    pub fn error(status: u16, message: impl ToString) -> Self {
+        HttpResponse::failure(ErrorReport::new("HTTP", message)).with_status(status)
+    }
+
+    pub fn from_response(response: Response) -> Self {
+        let status = match &response {
+            Response::Success(_) => 200,
+            Response::Failure(report) => status_of(&report.kind),
+        };
+        HttpResponse::new(
+            status,
+            Bson::Document(response.to_document()).into_relaxed_extjson(),
+        )
+    }
+
+    fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn write(&self, writer: &mut impl Write, keep_alive: bool) -> io::Result<()> {
+        let body = self.body.to_string();
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: {}\r\n\r\n{}",
+            self.status,
+            reason(self.status),
+            body.len(),
+            if keep_alive { "keep-alive" } else { "close" },
+            body
+        )?;
+        writer.flush()
+    }
+}
+
+fn status_of(kind: &str) -> u16 {
+    match kind {
+        "Lexical" | "Syntax" | "Protocol" => 400,
+        "Authentication" => 401,
+        "Access" => 403,
+        "Constraint" => 409,
+        "Runtime" => 422,
+        "Unavailable" => 503,
+        _ => 500,
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        422 => "Unprocessable Content",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Internal Server Error",
+    }
+}
+
+// Returns `None` when the connection is closed before a new request. A request that
+// can't be read is answered with the returned response, after which the connection
+// can't be followed any further.
+pub fn read_request(reader: &mut impl BufRead) -> Result<Option<HttpRequest>, HttpResponse> {
+    let Some(head) = read_head(reader)? else {
+        return Ok(None);
+    };
+    let request_line = head.lines().next().unwrap_or_default();
+    let [method, target, version] = request_line.split_whitespace().collect::<Vec<_>>()[..] else {
+        return Err(HttpResponse::error(
+            400,
+            format!("'{}' isn't a request line", request_line),
+        ));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(HttpResponse::error(
+            505,
+            format!("{} isn't supported", version),
+        ));
+    }
+    let headers = headers::parse_header(head.clone())
+        .ok_or_else(|| HttpResponse::error(400, "The request has a malformed header line"))?;
+    let is_chunked = headers::get(&headers, "Transfer-Encoding")
+        .is_some_and(|encoding| encoding.to_ascii_lowercase().contains("chunked"));
+    let body = if is_chunked {
+        read_chunked_body(reader)?
+    } else {
+        let length = headers::content_length(&headers)
+            .map_err(|message| HttpResponse::error(400, message))?
+            .unwrap_or(0);
+        read_body(reader, length)?
+    };
+    Ok(Some(HttpRequest {
+        method: method.to_string(),
+        path: target.split('?').next().unwrap_or_default().to_string(),
+        version: version.to_string(),
+        headers,
+        body,
+    }))
+}
+
+// Lines up to the empty one ending the head. Empty lines before a request are skipped.
+fn read_head(reader: &mut impl BufRead) -> Result<Option<String>, HttpResponse> {
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        let read = match reader
+            .by_ref()
+            .take((MAX_HEAD_SIZE + 1 - head.len()) as u64)
+            .read_line(&mut line)
+        {
+            Ok(read) => read,
+            // An idle connection is closed quietly.
+            Err(error) if pool::is_timeout(&error) && head.is_empty() && line.is_empty() => {
+                return Ok(None)
+            }
+            Err(error) if pool::is_timeout(&error) => {
+                return Err(HttpResponse::error(408, "The request head took too long"))
+            }
+            Err(_) => return Err(HttpResponse::error(400, "The request head can't be read")),
+        };
+        if read == 0 {
+            if head.is_empty() {
+                return Ok(None);
+            }
+            return Err(HttpResponse::error(400, "The request head is cut short"));
+        }
+        head.push_str(&line);
+        if head.len() > MAX_HEAD_SIZE {
+            return Err(HttpResponse::error(
+                431,
+                format!(
+                    "The request head exceeds the limit of {} bytes",
+                    MAX_HEAD_SIZE
+                ),
+            ));
+        }
+        if line.trim_end().is_empty() {
+            if head.trim().is_empty() {
+                head.clear();
+                continue;
+            }
+            return Ok(Some(head));
+        }
+    }
+}
+
+fn read_body(reader: &mut impl Read, length: usize) -> Result<Vec<u8>, HttpResponse> {
+    if length > MAX_FRAME_SIZE {
+        return Err(body_too_large());
+    }
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| HttpResponse::error(400, "The request body is cut short"))?;
+    Ok(body)
+}
+
+// Chunks are a hexadecimal size line followed by as many bytes, up to a chunk of size 0.
+fn read_chunked_body(reader: &mut impl BufRead) -> Result<Vec<u8>, HttpResponse> {
+    let malformed = || HttpResponse::error(400, "The chunked request body is malformed");
+    let mut body = vec![];
+    loop {
+        let mut size_line = String::new();
+        reader
+            .by_ref()
+            .take(1024)
+            .read_line(&mut size_line)
+            .map_err(|_| malformed())?;
+        let size = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| malformed())?;
+        if size == 0 {
+            // Trailer lines, which aren't used, end with an empty line.
+            loop {
+                let mut line = String::new();
+                let read = reader
+                    .by_ref()
+                    .take(1024)
+                    .read_line(&mut line)
+                    .map_err(|_| malformed())?;
+                if read == 0 || line.trim_end().is_empty() {
+                    return Ok(body);
+                }
+            }
+        }
+        if body.len() + size > MAX_FRAME_SIZE {
+            return Err(body_too_large());
+        }
+        body.extend(read_body(reader, size)?);
+        let mut line_end = String::new();
+        reader.read_line(&mut line_end).map_err(|_| malformed())?;
+        if !line_end.trim_end().is_empty() {
+            return Err(malformed());
+        }
+    }
+}
+
+fn body_too_large() -> HttpResponse {
+    HttpResponse::error(
+        413,
+        format!(
+            "The request body exceeds the limit of {} bytes",
+            MAX_FRAME_SIZE
+        ),
+    )
+}
+
+pub fn serve(
+    listener: TcpListener,
+    password: String,
+    storage: SharedStorage,
+    pool: ConnectionPool,
+) -> io::Result<()> {
+    session::bootstrap_admin(&storage, &password)?;
+    pool.watch(&listener)?;
+    for stream in listener.incoming() {
+        if pool.is_closed() {
+            break;
+        }
+        let Some(stream) = pool::accepted(stream) else {
+            continue;
+        };
+        let storage = storage.clone();
+        let response = HttpResponse::failure(pool.full_report());
+        let connection_pool = pool.clone();
+
+        let _ = pool.accept(
+            stream,
+            move |stream| {
+                let _ = handle_connection(stream, storage, connection_pool);
+            },
+            |mut stream| {
+                let _ = response.write(&mut stream, false);
+            },
+        );
+    }
+
+    Ok(())
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    storage: SharedStorage,
+    pool: ConnectionPool,
+) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    loop {
+        let request = match read_request(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(response) => return response.write(&mut writer, false),
+        };
+        let response = route(&request, &storage);
+        let is_shutdown = request.path == "/shutdown" && response.status == 200;
+        let keep_alive = request.keeps_alive() && !is_shutdown && !pool.is_closed();
+        response.write(&mut writer, keep_alive)?;
+        if is_shutdown {
+            pool.close();
+        }
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+// `GET /health` needs no login. The other routes answer a challenge of the `auth`
+// handshake: `POST /challenge` takes the `User` header, where the user is `admin`
+// unless it's given, and the client's `Nonce`, and a later request sends the `Nonce`
+// of the challenge and the `Proof` computed from it. Each challenge is answered once.
+// `POST /shutdown` stops the server once the open connections have answered.
+pub fn route(request: &HttpRequest, storage: &SharedStorage) -> HttpResponse {
+    let allowed = match request.path.as_str() {
+        "/health" | "/metadata" => "GET",
+        "/challenge" | "/query" | "/shutdown" => "POST",
+        path => return HttpResponse::error(404, format!("There's no route '{}'", path)),
+    };
+    if request.method != allowed {
+        return HttpResponse::error(
+            405,
+            format!("'{}' only takes {} requests", request.path, allowed),
+        );
+    }
+    if request.path == "/health" {
+        return HttpResponse::new(200, json!({ "status": "ok" }));
+    }
+    let nonce = headers::get(&request.headers, "Nonce").unwrap_or_default();
+    if request.path == "/challenge" {
+        let user = headers::get(&request.headers, "User").unwrap_or(ADMIN_USER);
+        return match auth::challenge(storage, user, nonce)
+            .and_then(|challenge| auth::hold(storage, &challenge).map(|_| challenge))
+        {
+            Ok(challenge) => HttpResponse::new(
+                200,
+                json!({
+                    "nonce": challenge.nonce,
+                    "salt": challenge.salt,
+                    "iterations": challenge.iterations,
+                }),
+            ),
+            Err(error) => HttpResponse::failure(ErrorReport::from_error(&error)),
+        };
+    }
+    let mut session = Session::new(storage.clone());
+    match auth::take(storage, nonce) {
+        Ok(challenge) => session.expect(challenge),
+        Err(error) => return HttpResponse::failure(ErrorReport::from_error(&error)),
+    }
+    let proof = headers::get(&request.headers, "Proof").unwrap_or_default();
+    if let Response::Failure(report) = session.handle(Request::Authenticate {
+        proof: proof.to_string(),
+    }) {
+        return HttpResponse::failure(report);
+    }
+    match request.path.as_str() {
+        "/metadata" => metadata(storage),
+        "/shutdown" => match session.handle(Request::Shutdown) {
+            Response::Success(_) => HttpResponse::new(200, json!({ "status": "shutting down" })),
+            failure => HttpResponse::from_response(failure),
+        },
+        _ => match query_request(request) {
+            Ok(query) => HttpResponse::from_response(session.handle(query)),
+            Err(response) => response,
+        },
+    }
+}
+
+// The script is the body itself, or the `code` of a JSON body, whose `params` are
+// given in extended JSON, e.g. `{"since": {"$date": "2024-01-01T00:00:00Z"}}` for
+// `$since`, or `[{"$date": "2024-01-01T00:00:00Z"}]` for `?`.
+fn query_request(request: &HttpRequest) -> Result<Request, HttpResponse> {
+    let content_type = headers::get(&request.headers, "Content-Type").unwrap_or("text/plain");
+    if content_type.starts_with("application/json") {
+        let mut body: Value = serde_json::from_slice(&request.body)
+            .map_err(|error| HttpResponse::error(400, format!("The body isn't JSON: {}", error)))?;
+        let code = body["code"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| HttpResponse::error(400, "The body has no 'code' string"))?;
+        let params = Bson::try_from(body["params"].take())
+            .ok()
+            .and_then(Params::from_bson)
+            .ok_or_else(|| {
+                HttpResponse::error(400, "The 'params' aren't a JSON object or array")
+            })?;
+        return Ok(Request::Execute { code, params });
+    }
+    if !content_type.starts_with("text/") {
+        return Err(HttpResponse::error(
+            415,
+            format!("Scripts can't be sent as {}", content_type),
+        ));
+    }
+    let code = String::from_utf8(request.body.clone())
+        .map_err(|_| HttpResponse::error(400, "The script isn't valid UTF-8"))?;
+    Ok(Request::Execute {
+        code,
+        params: Params::default(),
+    })
+}
+
+fn metadata(storage: &SharedStorage) -> HttpResponse {
+    let Ok(storage) = storage.lock() else {
+        return HttpResponse::error(500, "The datablaze storage is poisoned");
+    };
+    let catalog = storage.catalog();
+    let tables: serde_json::Map<String, Value> = catalog
+        .tables
+        .iter()
+        .map(|table| {
+            let columns: Vec<String> = table.columns.iter().map(ToString::to_string).collect();
+            (
+                table.name.clone(),
+                json!({ "key": table.key.to_string(), "columns": columns }),
+            )
+        })
+        .collect();
+    HttpResponse::new(
+        200,
+        json!({
+            "protocol": PROTOCOL_VERSION,
+            "revision": catalog.revision,
+            "tables": tables,
+            "enums": catalog.enums.keys().collect::<Vec<_>>(),
+            "functions": catalog.functions.values().map(ToString::to_string).collect::<Vec<_>>(),
+        }),
+    )
+}
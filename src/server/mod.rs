@@ -1,3 +1,8 @@
+pub mod auth;
 pub mod config;
 pub mod headers;
+pub mod http;
+pub mod pool;
+pub mod protocol;
 pub mod server_bz;
+pub mod session;
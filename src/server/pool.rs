@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::protocol::ErrorReport;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// A fixed number of workers serving connections, shared by the front ends of a server.
+// There are never more connections open than workers; any more are rejected right away
+// rather than left waiting. A connection silent for longer than the timeout is closed, as is one
+// whose client doesn't take what's written to it in time.
+#[derive(Clone)]
+pub struct ConnectionPool {
+    sender: SyncSender<Job>,
+    pub size: usize,
+    pub timeout: Duration,
+    connections: Arc<Mutex<Connections>>,
+}
+
+// What closing the pool has to reach: the listeners of the front ends and the
+// connections being served or waiting for a worker.
+#[derive(Default)]
+struct Connections {
+    is_closed: bool,
+    listeners: Vec<SocketAddr>,
+    next_id: u64,
+    open: HashMap<u64, TcpStream>,
+}
+
+impl ConnectionPool {
+    pub fn new(size: usize, timeout: Duration) -> Self {
+        let size = size.max(1);
+        let (sender, receiver) = mpsc::sync_channel::<Job>(size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..size {
+            let receiver = receiver.clone();
+            thread::spawn(move || work(receiver));
+        }
+        ConnectionPool {
+            sender,
+            size,
+            timeout,
+            connections: Arc::default(),
+        }
+    }
+
+    fn connections(&self) -> MutexGuard<'_, Connections> {
+        self.connections
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // A front end registers its listener, so that closing the pool can wake it up.
+    pub fn watch(&self, listener: &TcpListener) -> io::Result<()> {
+        let address = listener.local_addr()?;
+        self.connections().listeners.push(address);
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.connections().is_closed
+    }
+
+    // Stops the front ends from accepting connections and ends the open ones once
+    // they've answered the request they're working on: their reading side is shut,
+    // so they see the client as gone.
+    pub fn close(&self) {
+        let listeners = {
+            let mut connections = self.connections();
+            if connections.is_closed {
+                return;
+            }
+            connections.is_closed = true;
+            for stream in connections.open.values() {
+                let _ = stream.shutdown(Shutdown::Read);
+            }
+            connections.listeners.clone()
+        };
+        // A front end waiting for a connection is woken up by one.
+        for mut address in listeners {
+            if address.ip().is_unspecified() {
+                address.set_ip(match address {
+                    SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                    SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+                });
+            }
+            let _ = TcpStream::connect_timeout(&address, self.timeout);
+        }
+    }
+
+    // Waits for the open connections to end, for the timeout at most.
+    // Returns false when some of them are still open.
+    pub fn wait(&self) -> bool {
+        let deadline = Instant::now() + self.timeout;
+        while !self.connections().open.is_empty() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    // Hands the connection to `handle` on a worker, or to `reject` right away when
+    // the pool is full.
+    pub fn accept(
+        &self,
+        stream: TcpStream,
+        handle: impl FnOnce(TcpStream) + Send + 'static,
+        reject: impl FnOnce(TcpStream),
+    ) -> io::Result<()> {
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let rejected = stream.try_clone()?;
+        let id = {
+            let mut connections = self.connections();
+            if connections.open.len() >= self.size {
+                drop(connections);
+                reject(rejected);
+                return Ok(());
+            }
+            connections.next_id += 1;
+            let id = connections.next_id;
+            connections.open.insert(id, stream.try_clone()?);
+            id
+        };
+        let connections = self.connections.clone();
+        // A panicking connection doesn't take its worker down with it.
+        let job = move || {
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| handle(stream)));
+            let mut connections = connections
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            connections.open.remove(&id);
+        };
+        if self.sender.try_send(Box::new(job)).is_err() {
+            self.connections().open.remove(&id);
+            reject(rejected);
+        }
+        Ok(())
+    }
+
+    pub fn full_report(&self) -> ErrorReport {
+        ErrorReport::new(
+            "Unavailable",
+            format!(
+                "All {} workers of the server are busy; try again later",
+                self.size
+            ),
+        )
+    }
+}
+
+fn work(receiver: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        let Ok(job) = job else {
+            return;
+        };
+        job();
+    }
+}
+
+// A connection that can't be accepted, e.g. when the process is out of file
+// descriptors, is skipped without stopping the front end. The pause keeps a failing
+// `accept` from spinning.
+pub fn accepted(stream: io::Result<TcpStream>) -> Option<TcpStream> {
+    match stream {
+        Ok(stream) => Some(stream),
+        Err(error) => {
+            eprintln!("A connection couldn't be accepted: {}", error);
+            thread::sleep(ACCEPT_PAUSE);
+            None
+        }
+    }
+}
+
+const ACCEPT_PAUSE: Duration = Duration::from_millis(10);
+
+pub fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
@@ -0,0 +1,340 @@
+use bson::{doc, Bson, Document};
+use colored::Colorize;
+use regex::Regex;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::db::access::ADMIN_USER;
+use crate::db::constraints::ConstraintViolation;
+use crate::scripting::params::Params;
+
+// Messages are BSON documents. A document starts with its own length as a little-endian
+// 32-bit integer, so documents follow each other on a stream with no other framing.
+pub const PROTOCOL_VERSION: i32 = 1;
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+// How many values a cursor hands out at a time, unless the request says otherwise.
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+// Returns `None` when the stream ends before a new frame.
+pub fn read_frame(reader: &mut impl Read) -> io::Result<Option<Document>> {
+    let mut length_bytes = [0; 4];
+    if reader.read(&mut length_bytes[..1])? == 0 {
+        return Ok(None);
+    }
+    reader.read_exact(&mut length_bytes[1..])?;
+    let length = i32::from_le_bytes(length_bytes);
+    if length < 5 || length as usize > MAX_FRAME_SIZE {
+        return Err(protocol_error(format!(
+            "A frame of {} bytes can't be read; frames take from 5 to {} bytes",
+            length, MAX_FRAME_SIZE
+        )));
+    }
+    let mut frame = length_bytes.to_vec();
+    frame.resize(length as usize, 0);
+    reader.read_exact(&mut frame[4..])?;
+    Document::from_reader(frame.as_slice())
+        .map(Some)
+        .map_err(protocol_error)
+}
+
+pub fn write_frame(writer: &mut impl Write, document: &Document) -> io::Result<()> {
+    let mut frame: Vec<u8> = vec![];
+    document.to_writer(&mut frame).map_err(protocol_error)?;
+    if frame.len() > MAX_FRAME_SIZE {
+        return Err(protocol_error(format!(
+            "A frame of {} bytes exceeds the limit of {} bytes",
+            frame.len(),
+            MAX_FRAME_SIZE
+        )));
+    }
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+pub fn protocol_error(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", "Protocol Error".bright_red(), message),
+    )
+}
+
+// A request names its operation, e.g. `{"op": "execute", "code": "&accounts.name"}`.
+// A connection is a session starting with the handshake of `auth`:
+// `{"op": "challenge", "version": 1, "user": "alice", "nonce": "..."}`, where the user
+// is `admin` unless it's given, then `{"op": "authenticate", "proof": "..."}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    Challenge {
+        user: String,
+        nonce: String,
+    },
+    Authenticate {
+        proof: String,
+    },
+    // The parameters are bound to the placeholders of the script, e.g.
+    // `{"code": "&notes(text = $text)", "params": {"text": "..."}}`, or
+    // `{"code": "&notes(text = ?)", "params": ["..."]}`, so values are never spliced into code.
+    Execute {
+        code: String,
+        params: Params,
+    },
+    // Opens a cursor handing out the result of a script `batch` values at a time,
+    // answered with `{"cursor": 1, "rows": [...], "done": false}`. The next batches
+    // are fetched with the cursor's id; a cursor is closed once it's done or cancelled.
+    Query {
+        code: String,
+        params: Params,
+        batch: usize,
+    },
+    Fetch {
+        cursor: i64,
+        batch: usize,
+    },
+    Cancel {
+        cursor: i64,
+    },
+    Begin,
+    Commit,
+    Rollback,
+    // Stops the server once the open connections have answered their requests.
+    Shutdown,
+}
+
+impl Request {
+    pub fn from_document(document: &Document) -> io::Result<Self> {
+        if let Some(version) = document.get("version") {
+            if version.as_i32() != Some(PROTOCOL_VERSION) {
+                return Err(protocol_error(format!(
+                    "Version {} of the protocol isn't supported; the server speaks version {}",
+                    version, PROTOCOL_VERSION
+                )));
+            }
+        }
+        let field = |name: &str| {
+            document
+                .get_str(name)
+                .map(str::to_string)
+                .map_err(|_| protocol_error(format!("The request has no '{}' string", name)))
+        };
+        let params = || {
+            document
+                .get("params")
+                .cloned()
+                .map_or(Some(Params::default()), Params::from_bson)
+                .ok_or_else(|| {
+                    protocol_error("The 'params' of a request are a document or an array")
+                })
+        };
+        let cursor = || {
+            document
+                .get("cursor")
+                .and_then(Bson::as_i64)
+                .ok_or_else(|| protocol_error("The request has no 'cursor' id"))
+        };
+        let batch = || match document.get("batch") {
+            None => Ok(DEFAULT_BATCH_SIZE),
+            Some(Bson::Int32(size)) if *size > 0 => Ok(*size as usize),
+            Some(Bson::Int64(size)) if *size > 0 => Ok(*size as usize),
+            Some(size) => Err(protocol_error(format!(
+                "A batch holds at least one value, but {} is given",
+                size
+            ))),
+        };
+        match field("op")?.as_str() {
+            "challenge" => Ok(Request::Challenge {
+                user: field("user").unwrap_or_else(|_| ADMIN_USER.to_string()),
+                nonce: field("nonce")?,
+            }),
+            "authenticate" => Ok(Request::Authenticate {
+                proof: field("proof")?,
+            }),
+            "execute" => Ok(Request::Execute {
+                code: field("code")?,
+                params: params()?,
+            }),
+            "query" => Ok(Request::Query {
+                code: field("code")?,
+                params: params()?,
+                batch: batch()?,
+            }),
+            "fetch" => Ok(Request::Fetch {
+                cursor: cursor()?,
+                batch: batch()?,
+            }),
+            "cancel" => Ok(Request::Cancel { cursor: cursor()? }),
+            "begin" => Ok(Request::Begin),
+            "commit" => Ok(Request::Commit),
+            "rollback" => Ok(Request::Rollback),
+            "shutdown" => Ok(Request::Shutdown),
+            operation => Err(protocol_error(format!(
+                "'{}' isn't an operation of the protocol",
+                operation
+            ))),
+        }
+    }
+
+    pub fn to_document(&self) -> Document {
+        match self {
+            Request::Challenge { user, nonce } => doc! {
+                "op": "challenge",
+                "version": PROTOCOL_VERSION,
+                "user": user,
+                "nonce": nonce,
+            },
+            Request::Authenticate { proof } => doc! { "op": "authenticate", "proof": proof },
+            Request::Execute { code, params } => {
+                doc! { "op": "execute", "code": code, "params": params.to_bson() }
+            }
+            Request::Query {
+                code,
+                params,
+                batch,
+            } => doc! {
+                "op": "query",
+                "code": code,
+                "params": params.to_bson(),
+                "batch": *batch as i64,
+            },
+            Request::Fetch { cursor, batch } => {
+                doc! { "op": "fetch", "cursor": cursor, "batch": *batch as i64 }
+            }
+            Request::Cancel { cursor } => doc! { "op": "cancel", "cursor": cursor },
+            Request::Begin => doc! { "op": "begin" },
+            Request::Commit => doc! { "op": "commit" },
+            Request::Rollback => doc! { "op": "rollback" },
+            Request::Shutdown => doc! { "op": "shutdown" },
+        }
+    }
+}
+
+// `{"ok": true, "result": ...}` or `{"ok": false, "error": {"kind": ..., "message": ...}}`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    Success(Bson),
+    Failure(ErrorReport),
+}
+
+impl Response {
+    pub fn from_result(result: io::Result<Bson>) -> Self {
+        match result {
+            Ok(value) => Response::Success(value),
+            Err(error) => Response::Failure(ErrorReport::from_error(&error)),
+        }
+    }
+
+    pub fn from_document(document: &Document) -> io::Result<Self> {
+        match document.get_bool("ok") {
+            Ok(true) => Ok(Response::Success(
+                document.get("result").cloned().unwrap_or(Bson::Null),
+            )),
+            Ok(false) => {
+                let error = document
+                    .get_document("error")
+                    .map_err(|_| protocol_error("The failed response has no 'error' document"))?;
+                Ok(Response::Failure(ErrorReport::from_document(error)))
+            }
+            Err(_) => Err(protocol_error("The response has no 'ok' flag")),
+        }
+    }
+
+    pub fn to_document(&self) -> Document {
+        match self {
+            Response::Success(result) => doc! { "ok": true, "result": result.clone() },
+            Response::Failure(report) => doc! { "ok": false, "error": report.to_document() },
+        }
+    }
+}
+
+// An error as clients see it: its kind, e.g. "Syntax" or "Constraint", the message
+// without terminal colors, and the broken rule for constraint violations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorReport {
+    pub kind: String,
+    pub message: String,
+    pub violation: Option<ConstraintViolation>,
+}
+
+impl ErrorReport {
+    pub fn new(kind: &str, message: impl ToString) -> Self {
+        ErrorReport {
+            kind: kind.to_string(),
+            message: message.to_string(),
+            violation: None,
+        }
+    }
+
+    // Errors are reported as "<Kind> Error: <message>", which is taken apart here,
+    // unless the error is a report received from a server.
+    pub fn from_error(error: &io::Error) -> Self {
+        if let Some(report) = error
+            .get_ref()
+            .and_then(|error| error.downcast_ref::<Self>())
+        {
+            return report.clone();
+        }
+        let colors = Regex::new(r"\x1b\[[0-9;]*m").unwrap();
+        let text = colors.replace_all(&error.to_string(), "").to_string();
+        let (kind, message) = match text.split_once(" Error: ") {
+            Some((kind, message)) if !kind.contains(char::is_whitespace) => {
+                (kind.to_string(), message.to_string())
+            }
+            _ => ("Internal".to_string(), text),
+        };
+        ErrorReport {
+            kind,
+            message,
+            violation: ConstraintViolation::from_error(error).cloned(),
+        }
+    }
+
+    pub fn from_document(document: &Document) -> Self {
+        let field = |name: &str| document.get_str(name).unwrap_or_default().to_string();
+        let violation = document
+            .get_str("rule")
+            .ok()
+            .map(|rule| ConstraintViolation::new(&field("table"), &field("column"), rule));
+        ErrorReport {
+            kind: field("kind"),
+            message: field("message"),
+            violation,
+        }
+    }
+
+    // How clients see the kinds of errors among I/O errors.
+    pub fn io_kind(&self) -> io::ErrorKind {
+        match self.kind.as_str() {
+            "Lexical" | "Syntax" => io::ErrorKind::InvalidInput,
+            "Constraint" | "Protocol" => io::ErrorKind::InvalidData,
+            "Authentication" | "Access" => io::ErrorKind::PermissionDenied,
+            "Unavailable" => io::ErrorKind::ResourceBusy,
+            _ => io::ErrorKind::Other,
+        }
+    }
+
+    pub fn to_document(&self) -> Document {
+        let mut document = doc! { "kind": &self.kind, "message": &self.message };
+        if let Some(violation) = &self.violation {
+            document.insert("table", &violation.table);
+            document.insert("column", &violation.column);
+            document.insert("rule", &violation.rule);
+        }
+        document
+    }
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} Error: {}", self.kind, self.message)
+    }
+}
+
+impl Error for ErrorReport {}
+
+// Clients get the reports of servers as I/O errors; `ErrorReport::from_error` gives
+// them back.
+impl From<ErrorReport> for io::Error {
+    fn from(report: ErrorReport) -> Self {
+        io::Error::new(report.io_kind(), report)
+    }
+}
@@ -1,12 +1,24 @@
 use crate::{
-    server::{config::Config, headers},
-    shell::handling::analyze_syntatically,
+    db::{
+        access::ADMIN_USER,
+        storage::{SharedStorage, Storage},
+    },
+    server::{
+        auth,
+        config::Config,
+        http,
+        pool::{self, ConnectionPool},
+        protocol::{self, Request, Response},
+        session::{self, Session},
+    },
 };
-use std::io::{self, Read};
+use std::io;
 use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::time::Duration;
 
 pub fn server_run(args: Vec<String>) -> io::Result<()> {
-    let config = Config::parse_arguments(args).unwrap();
+    let config = Config::parse_arguments(args)?;
 
     if !Config::blz_exists(&config.manager_file) {
         return Err(io::Error::new(
@@ -15,31 +27,143 @@ pub fn server_run(args: Vec<String>) -> io::Result<()> {
         ));
     }
 
+    // The manager file lies in the datablaze, whose catalog describes everything declared,
+    // so nothing has to be parsed again before serving requests.
+    let datablaze_path = Path::new(&config.manager_file)
+        .parent()
+        .unwrap_or(Path::new("."));
+    let storage = Storage::open(datablaze_path)?;
+    let catalog = storage.catalog();
+    println!(
+        "Catalog revision {} with {} tables has been loaded",
+        catalog.revision,
+        catalog.tables.len()
+    );
+    let storage = storage.shared();
+    // A made-up password is shown only once, when the admin is created with it.
+    let is_made_up = config.password.is_none();
+    let password = config.password.unwrap_or_else(auth::new_password);
+    if session::bootstrap_admin(&storage, &password)? {
+        if is_made_up {
+            println!(
+                "User '{}' has been created with the password {}, which isn't shown again",
+                ADMIN_USER, password
+            );
+        } else {
+            println!(
+                "User '{}' has been created with the password given to the server",
+                ADMIN_USER
+            );
+        }
+    }
+    let pool = ConnectionPool::new(config.max_connections, Duration::from_secs(config.timeout));
+
+    // SIGINT and SIGTERM shut the server down like the `shutdown` request does.
+    // A second signal doesn't wait for the connections.
+    let signalled_pool = pool.clone();
+    ctrlc::set_handler(move || {
+        if signalled_pool.is_closed() {
+            std::process::exit(1);
+        }
+        signalled_pool.close();
+    })
+    .map_err(io::Error::other)?;
+
+    if let Some(http_port) = &config.http_port {
+        let http_listener = TcpListener::bind(format!("{}:{}", config.host, http_port))?;
+        let password = password.clone();
+        let storage = storage.clone();
+        let pool = pool.clone();
+        std::thread::spawn(move || http::serve(http_listener, password, storage, pool));
+    }
+
     let host = format!("{}:{}", config.host, config.port);
     let listener = TcpListener::bind(host)?;
+    serve(listener, password, storage.clone(), pool.clone())?;
+    drain(&pool, &storage)?;
+    println!("The server has been shut down");
+    Ok(())
+}
 
+// Serves connections until the pool is closed.
+pub fn serve(
+    listener: TcpListener,
+    password: String,
+    storage: SharedStorage,
+    pool: ConnectionPool,
+) -> io::Result<()> {
+    session::bootstrap_admin(&storage, &password)?;
+    pool.watch(&listener)?;
     for stream in listener.incoming() {
-        let stream = stream?;
-        let password = std::mem::take(&mut config.password.clone());
+        if pool.is_closed() {
+            break;
+        }
+        let Some(stream) = pool::accepted(stream) else {
+            continue;
+        };
+        let storage = storage.clone();
+        let report = pool.full_report();
+        let connection_pool = pool.clone();
 
-        std::thread::spawn(move || handle_connection(stream, password));
+        // A connection that can't be set up is dropped without stopping the others.
+        let _ = pool.accept(
+            stream,
+            move |stream| {
+                let _ = handle_connection(stream, storage, connection_pool);
+            },
+            |mut stream| {
+                let response = Response::Failure(report);
+                let _ = protocol::write_frame(&mut stream, &response.to_document());
+            },
+        );
     }
 
     Ok(())
 }
 
-fn handle_connection(mut stream: TcpStream, password: String) -> io::Result<()> {
-    let mut buffer = [0; 1024];
-    let mut request = String::new();
-    let bytes_read = stream.read(&mut buffer)?;
-    request.push_str(&String::from_utf8_lossy(&buffer[..bytes_read]));
+// Once the pool is closed, waits for the connections to end, which rolls back the
+// transactions they leave open, and writes everything to disk.
+pub fn drain(pool: &ConnectionPool, storage: &SharedStorage) -> io::Result<()> {
+    pool.close();
+    if !pool.wait() {
+        eprintln!("Some connections are still open; their transactions are rolled back");
+    }
+    storage
+        .lock()
+        .map_err(|_| io::Error::other("The datablaze storage is poisoned"))?
+        .flush()
+}
 
-    let header = headers::parse_header(request.clone()).unwrap();
-    if let Some(value) = header.get("Password") {
-        if password == *value {
-            analyze_syntatically(headers::remove_empty_line(request).unwrap())?
+// A connection is a session of requests, each answered by a response,
+// lasting until the client closes it or the server shuts down.
+fn handle_connection(
+    mut stream: TcpStream,
+    storage: SharedStorage,
+    pool: ConnectionPool,
+) -> io::Result<()> {
+    let mut session = Session::new(storage);
+    loop {
+        let frame = match protocol::read_frame(&mut stream) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(()),
+            Err(error) if pool::is_timeout(&error) => return Ok(()),
+            // The stream can't be followed after a broken frame, so the connection is closed.
+            Err(error) => {
+                let response = Response::from_result(Err(error));
+                return protocol::write_frame(&mut stream, &response.to_document());
+            }
         };
-    };
-
-    Ok(())
+        let (response, is_shutdown) = match Request::from_document(&frame) {
+            Ok(request) => {
+                let is_shutdown = request == Request::Shutdown;
+                (session.handle(request), is_shutdown)
+            }
+            Err(error) => (Response::from_result(Err(error)), false),
+        };
+        let is_shutdown = is_shutdown && matches!(response, Response::Success(_));
+        protocol::write_frame(&mut stream, &response.to_document())?;
+        if is_shutdown {
+            pool.close();
+        }
+    }
 }
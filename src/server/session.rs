@@ -0,0 +1,280 @@
+use bson::{doc, Bson};
+use std::collections::HashMap;
+use std::io;
+use std::rc::Rc;
+
+use crate::db::access::{User, ADMIN_ROLE, ADMIN_USER};
+use crate::db::cursor::Cursor;
+use crate::db::storage::{storage_error, SharedStorage};
+use crate::scripting::ast::body::BodyNode;
+use crate::scripting::executor::{runtime_error, Executor};
+use crate::scripting::params::{Params, Placeholder};
+use crate::scripting::{lexer::Lexer, parser::Parser};
+
+use super::auth::{self, Challenge};
+use super::protocol::{ErrorReport, Request, Response, PROTOCOL_VERSION};
+
+// A datablaze without users gets an admin with the server's password, so that someone
+// can log in to declare the others. Returns whether the admin has been created.
+pub fn bootstrap_admin(storage: &SharedStorage, password: &str) -> io::Result<bool> {
+    let mut storage = storage
+        .lock()
+        .map_err(|_| storage_error("The datablaze storage is poisoned"))?;
+    if !storage.access().users.is_empty() {
+        return Ok(false);
+    }
+    storage.change_access(|access| {
+        access.set_user(User::new(
+            ADMIN_USER,
+            password,
+            vec![ADMIN_ROLE.to_string()],
+        ))
+    })?;
+    Ok(true)
+}
+
+// What a connection keeps between its requests: the challenge it has to answer, the
+// user it's authenticated as, the executor holding its variables and its open
+// transaction, the scripts it has parsed, and its open cursors.
+pub struct Session {
+    storage: SharedStorage,
+    challenge: Option<Challenge>,
+    executor: Executor,
+    statements: StatementCache,
+    cursors: HashMap<i64, Cursor>,
+    last_cursor: i64,
+}
+
+// How many cursors a session can keep open at a time.
+pub const MAX_OPEN_CURSORS: usize = 16;
+
+impl Session {
+    pub fn new(storage: SharedStorage) -> Self {
+        // Only a path is read, which a poisoned storage still holds.
+        let packages_path = storage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .packages_path();
+        Session {
+            executor: Executor::with_storage(storage.clone()).with_packages(packages_path),
+            storage,
+            challenge: None,
+            statements: StatementCache::default(),
+            cursors: HashMap::new(),
+            last_cursor: 0,
+        }
+    }
+
+    // A challenge handed out earlier, e.g. by another HTTP request, to be answered next.
+    pub fn expect(&mut self, challenge: Challenge) {
+        self.challenge = Some(challenge);
+    }
+
+    pub fn handle(&mut self, request: Request) -> Response {
+        let result = match request {
+            Request::Challenge { user, nonce } => self.challenge(&user, &nonce),
+            Request::Authenticate { proof } => self.authenticate(&proof),
+            _ if self.executor.user().is_none() => {
+                return Response::Failure(ErrorReport::new(
+                    "Authentication",
+                    "The session has to be authenticated first",
+                ))
+            }
+            Request::Execute { code, params } => self.execute(code, params),
+            Request::Query {
+                code,
+                params,
+                batch,
+            } => self.query(code, params, batch),
+            Request::Fetch { cursor, batch } => self.fetch(cursor, batch),
+            Request::Cancel { cursor } => self.cancel(cursor),
+            Request::Begin => self.executor.begin_transaction().map(|_| Bson::Null),
+            Request::Commit => self.executor.commit_transaction().map(|_| Bson::Null),
+            Request::Rollback => self.executor.rollback_transaction().map(|_| Bson::Null),
+            // The connection's front end stops the server.
+            Request::Shutdown => self
+                .executor
+                .authorize_admin("shut the server down")
+                .map(|_| Bson::Null),
+        };
+        Response::from_result(result)
+    }
+
+    // Starting the handshake again logs the session out.
+    fn challenge(&mut self, user: &str, nonce: &str) -> io::Result<Bson> {
+        self.executor.set_user(None);
+        let challenge = auth::challenge(&self.storage, user, nonce)?;
+        let document = challenge.to_document();
+        self.challenge = Some(challenge);
+        Ok(Bson::Document(document))
+    }
+
+    // A challenge is answered once, whether the proof holds or not.
+    fn authenticate(&mut self, proof: &str) -> io::Result<Bson> {
+        let challenge = self
+            .challenge
+            .take()
+            .ok_or_else(|| auth::authentication_error("No challenge has been asked for"))?;
+        let (user, signature) = auth::verify(&self.storage, &challenge, proof)?;
+        self.executor.set_user(Some(user.name));
+        Ok(Bson::Document(doc! {
+            "protocol": PROTOCOL_VERSION,
+            "roles": user.roles,
+            "signature": signature,
+        }))
+    }
+
+    // A script failing inside an open transaction rolls the whole transaction back,
+    // so none of its writes are left half-done. The parameters are bound only while
+    // their script runs.
+    fn execute(&mut self, code: String, params: Params) -> io::Result<Bson> {
+        let result = self.statements.prepare(code).and_then(|statement| {
+            params.check(&statement.placeholders)?;
+            let unbound = self.executor.bind(params);
+            let result = self.executor.execute(&statement.body);
+            self.executor.bind(unbound);
+            result
+        });
+        if result.is_err() && matches!(self.executor.in_transaction(), Ok(true)) {
+            self.executor.rollback_transaction()?;
+        }
+        result
+    }
+
+    // Runs a script like `execute`, but hands out its result through a cursor, whose
+    // first batch comes back at once.
+    fn query(&mut self, code: String, params: Params, batch: usize) -> io::Result<Bson> {
+        if self.cursors.len() >= MAX_OPEN_CURSORS {
+            return Err(runtime_error(format!(
+                "A session can have {} cursors open at a time",
+                MAX_OPEN_CURSORS
+            )));
+        }
+        let result = self.statements.prepare(code).and_then(|statement| {
+            params.check(&statement.placeholders)?;
+            let unbound = self.executor.bind(params);
+            let result = self.executor.open_cursor(&statement.body);
+            self.executor.bind(unbound);
+            result
+        });
+        if result.is_err() && matches!(self.executor.in_transaction(), Ok(true)) {
+            self.executor.rollback_transaction()?;
+        }
+        let cursor = result?;
+        self.last_cursor += 1;
+        self.cursors.insert(self.last_cursor, cursor);
+        self.fetch(self.last_cursor, batch)
+    }
+
+    // A cursor that's done, or fails, is closed. One that can't be read while another
+    // session's transaction is open stays open, to be fetched from again later.
+    fn fetch(&mut self, id: i64, batch: usize) -> io::Result<Bson> {
+        let cursor = self
+            .cursors
+            .get_mut(&id)
+            .ok_or_else(|| runtime_error(format!("Cursor {} isn't open", id)))?;
+        let result = self
+            .executor
+            .lock_storage()
+            .and_then(|storage| cursor.fetch(&storage, batch));
+        let done = match &result {
+            Ok(_) => cursor.is_done(),
+            Err(err) => err.kind() != io::ErrorKind::ResourceBusy,
+        };
+        if done {
+            self.cursors.remove(&id);
+        }
+        Ok(Bson::Document(doc! {
+            "cursor": id,
+            "rows": result?,
+            "done": done,
+        }))
+    }
+
+    fn cancel(&mut self, id: i64) -> io::Result<Bson> {
+        self.cursors
+            .remove(&id)
+            .map(|_| Bson::Null)
+            .ok_or_else(|| runtime_error(format!("Cursor {} isn't open", id)))
+    }
+
+    pub fn open_cursors(&self) -> usize {
+        self.cursors.len()
+    }
+
+    pub fn statements(&self) -> &StatementCache {
+        &self.statements
+    }
+}
+
+// How many parsed scripts a session keeps.
+pub const STATEMENT_CACHE_SIZE: usize = 128;
+
+// A script parsed once, to be run again with other parameters.
+pub struct Statement {
+    pub body: BodyNode,
+    pub placeholders: Vec<Placeholder>,
+}
+
+// The scripts a session has run, by their code, so that running one again skips
+// lexing and parsing. The least recently used one makes room for a new one.
+#[derive(Default)]
+pub struct StatementCache {
+    statements: HashMap<String, (Rc<Statement>, u64)>,
+    uses: u64,
+    hits: u64,
+}
+
+impl StatementCache {
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
+    // How many times a script has been found parsed already.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn prepare(&mut self, code: String) -> io::Result<Rc<Statement>> {
+        self.uses += 1;
+        if let Some((statement, last_used)) = self.statements.get_mut(&code) {
+            *last_used = self.uses;
+            self.hits += 1;
+            return Ok(statement.clone());
+        }
+        let mut lexer = Lexer::new(code.clone());
+        lexer.get_context().set_code_source("Request".to_string());
+        let mut parser = Parser::new(lexer.analyze()?);
+        parser.get_context().set_code_source("Request".to_string());
+        let statement = Rc::new(Statement {
+            body: parser.parse()?,
+            placeholders: parser.placeholders().to_vec(),
+        });
+        if self.statements.len() >= STATEMENT_CACHE_SIZE {
+            let least_recent = self
+                .statements
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(code, _)| code.clone());
+            if let Some(code) = least_recent {
+                self.statements.remove(&code);
+            }
+        }
+        self.statements.insert(code, (statement.clone(), self.uses));
+        Ok(statement)
+    }
+}
+
+// A transaction left open by a closed connection is rolled back, so other sessions
+// don't wait for it.
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Ok(true) = self.executor.in_transaction() {
+            let _ = self.executor.rollback_transaction();
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::db::create_db;
-use crate::scripting::tokens::Token;
+use crate::scripting::tokens::{Token, WHITESPACE_TOKENS};
 use crate::scripting::{lexer, parser};
 use crate::server::server_bz;
 use std::io::{self, Result};
@@ -15,7 +15,9 @@ pub fn handle_command_arguments() -> Result<()> {
         "run" => server_bz::server_run(args)?,
         "lexer" => {
             let text = input_text()?;
-            analyze_lexically(text)?;
+            if let Err(error) = analyze_lexically(text) {
+                eprintln!("{}", error);
+            }
         }
         "parser" => {
             let text = input_text()?;
@@ -55,16 +57,34 @@ fn analyze_lexically(code_to_parse: String) -> Result<Vec<Token>> {
     code_lexer
         .get_context()
         .set_code_source("Shell".to_string());
-    Ok(code_lexer.analyze().unwrap())
+    let tokens = code_lexer.analyze()?;
+    for token in &tokens {
+        if !WHITESPACE_TOKENS.contains(&token.token_type) {
+            println!("{}:{} = {}", token.start + 1, token.value, token.token_type);
+        }
+    }
+    Ok(tokens)
 }
 
 pub fn analyze_syntatically(code: String) -> Result<()> {
-    let tokens = analyze_lexically(code)?;
+    let tokens = match analyze_lexically(code) {
+        Ok(tokens) => tokens,
+        Err(error) => {
+            eprintln!("{}", error);
+            return Ok(());
+        }
+    };
     let mut code_parser = parser::Parser::new(tokens);
     code_parser
         .get_context()
         .set_code_source("Shell".to_string());
-    let nodes = code_parser.parse()?.nodes;
+    let nodes = match code_parser.parse() {
+        Ok(body) => body.nodes,
+        Err(error) => {
+            eprintln!("{}", error);
+            return Ok(());
+        }
+    };
     if !nodes.is_empty() {
         println!(
             "Parsing successfully completed! Nodes Count: {}",
@@ -1,8 +1,29 @@
+// `test_lexer` converts its token types explicitly.
+#![allow(clippy::useless_conversion)]
+
+use blaze::client::connection::Connection;
+use blaze::client::pool::ClientPool;
+use blaze::db::access::Keys;
+use blaze::db::constraints::ConstraintViolation;
 use blaze::db::create_db;
+use blaze::db::storage::{SharedStorage, Storage};
+use blaze::scripting::executor::Executor;
 use blaze::scripting::lexer::Lexer;
+use blaze::scripting::params::Params;
 use blaze::scripting::parser::Parser;
 use blaze::scripting::tokens::TokenType;
+use blaze::server::auth;
+use blaze::server::config::Config;
 use blaze::server::headers;
+use blaze::server::http;
+use blaze::server::pool::ConnectionPool;
+use blaze::server::protocol::{self, ErrorReport, Request, Response};
+use blaze::server::server_bz;
+use blaze::server::session::{self, Session};
+use bson::{bson, doc, Bson};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread::JoinHandle;
 
 #[test]
 fn test_lexer() {
@@ -25,6 +46,15 @@ fn test_lexer() {
     assert_eq!(actual_token_types, expected_tokens);
 }
 
+#[test]
+fn test_string_at_start() {
+    // A string may open the code, and the character before it is checked even when
+    // it's the first one.
+    assert!(matches!(parser("\"text\"".to_string()), Ok(true)));
+    assert!(!matches!(parser("x\"text\"".to_string()), Ok(true)));
+    assert!(!matches!(parser(".\"text\"".to_string()), Ok(true)));
+}
+
 fn parser(code: String) -> std::io::Result<bool> {
     let mut code_lexer = Lexer::new(code);
     code_lexer.get_context().code_source = "Tests".to_string();
@@ -67,4 +97,1930 @@ fn test_header_parser() {
     if let Some(value) = hashmap.get("Password") {
         assert!(value == "1221");
     }
+    assert_eq!(hashmap.get("Host").unwrap(), "localhost:3300");
+    assert_eq!(headers::get(&hashmap, "user-agent"), Some("curl/8.7.1"));
+    assert!(headers::parse_header("POST / HTTP/1.1\nmalformed\n".to_string()).is_none());
+    let hashmap = headers::parse_header("POST / HTTP/1.1\nContent-Length: 12\n".to_string());
+    assert_eq!(headers::content_length(&hashmap.unwrap()), Ok(Some(12)));
+}
+
+fn execute(code: &str, executor: &mut Executor) -> std::io::Result<Bson> {
+    let tokens = Lexer::new(code.to_string()).analyze()?;
+    let body = Parser::new(tokens).parse()?;
+    executor.execute(&body)
+}
+
+fn connection_pool() -> ConnectionPool {
+    ConnectionPool::new(8, std::time::Duration::from_secs(5))
+}
+
+// Serves the storage on a free local port, with "secret" as the admin's password.
+fn spawn_server(
+    storage: SharedStorage,
+    pool: ConnectionPool,
+    serve: fn(TcpListener, String, SharedStorage, ConnectionPool) -> std::io::Result<()>,
+) -> (SocketAddr, JoinHandle<std::io::Result<()>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || serve(listener, "secret".to_string(), storage, pool));
+    (address, server)
+}
+
+fn start_server(name: &str) -> SocketAddr {
+    let storage = Storage::open(&temporary_datablaze(name)).unwrap().shared();
+    spawn_server(storage, connection_pool(), server_bz::serve).0
+}
+
+fn send(stream: &mut TcpStream, request: Request) -> Response {
+    protocol::write_frame(stream, &request.to_document()).unwrap();
+    Response::from_document(&protocol::read_frame(stream).unwrap().unwrap()).unwrap()
+}
+
+fn send_code(stream: &mut TcpStream, code: &str) -> Response {
+    let request = Request::Execute {
+        code: code.to_string(),
+        params: Params::default(),
+    };
+    send(stream, request)
+}
+
+fn temporary_datablaze(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("blaze_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    create_db::create_db_structure(path.to_str().unwrap()).unwrap();
+    path.join("datablaze")
+}
+
+#[test]
+fn test_query_selector() {
+    let mut storage = Storage::open(&temporary_datablaze("query_selector")).unwrap();
+    storage.create_table("countries").unwrap();
+    storage.create_table("accounts").unwrap();
+    for name in ["Norway", "Japan"] {
+        storage.insert("countries", doc! { "name": name }).unwrap();
+    }
+    for (name, country) in [("Ole", 1), ("Yuki", 2), ("Ingrid", 1)] {
+        storage
+            .insert("accounts", doc! { "name": name, "country": country })
+            .unwrap();
+    }
+    let mut executor = Executor::with_storage(storage.shared());
+
+    let names = execute(
+        r#"fin country_id = &countries.{self.name="Norway"}.id;
+        &accounts.{self.country=country_id}.name"#,
+        &mut executor,
+    )
+    .unwrap();
+    assert_eq!(names, bson!(["Ole", "Ingrid"]));
+
+    let names = execute(
+        r#"&accounts.{self.country != 1, self.id >= 2}.name"#,
+        &mut executor,
+    )
+    .unwrap();
+    assert_eq!(names, bson!(["Yuki"]));
+}
+
+#[test]
+fn test_operator_precedence() {
+    let mut executor = Executor::new();
+    for (code, expected) in [
+        ("10 - 2 - 3", bson!(5_i64)),
+        ("2 * 3 + 1", bson!(7_i64)),
+        ("1 + 2 * 3", bson!(7_i64)),
+        ("12 / 2 / 3", bson!(2.0)),
+        ("20 - 4 * 2 - 6 / 3", bson!(10.0)),
+        ("2 ^ 3 ^ 2", bson!(512.0)),
+        ("1 + 2 * 3 == 7", bson!(true)),
+        ("10 - 2 - 3 < 2 * 3", bson!(true)),
+        ("mut total = 1; total = 2 + 3 * 4 - 1; total", bson!(13_i64)),
+    ] {
+        assert_eq!(execute(code, &mut executor).unwrap(), expected, "{}", code);
+    }
+
+    // An operator needs a right operand.
+    for code in ["1 +", "2 * ;"] {
+        let error = execute(code, &mut executor).unwrap_err();
+        assert!(error.to_string().contains("Syntax Error"), "{}", code);
+    }
+}
+
+#[test]
+fn test_query_planner() {
+    let datablaze_path = temporary_datablaze("query_planner");
+    let mut storage = Storage::open(&datablaze_path).unwrap();
+    storage.create_table("accounts").unwrap();
+    for (name, country, age) in [("Ole", 1, 31), ("Yuki", 2, 24), ("Ingrid", 1, 19)] {
+        storage
+            .insert(
+                "accounts",
+                doc! { "name": name, "country": country, "age": age },
+            )
+            .unwrap();
+    }
+    let mut executor = Executor::with_storage(storage.shared());
+    let query = "&accounts.{self.age > 20, self.country = 1}";
+
+    let plan = execute(&format!("inspect {}", query), &mut executor).unwrap();
+    assert_eq!(plan, bson!("FullScan accounts"));
+
+    execute("index accounts.age; index accounts.country", &mut executor).unwrap();
+    let plan = execute(&format!("inspect {}", query), &mut executor).unwrap();
+    assert_eq!(plan, bson!("IndexScan accounts.country = 1"));
+    let names = execute(&format!("{}.name", query), &mut executor).unwrap();
+    assert_eq!(names, bson!(["Ole"]));
+
+    let mut executor = Executor::with_storage(Storage::open(&datablaze_path).unwrap().shared());
+    let plan = execute("inspect &accounts.{self.age <= 24}", &mut executor).unwrap();
+    assert_eq!(plan, bson!("IndexScan accounts.age <= 24"));
+    let names = execute("&accounts.{self.age <= 24}.name", &mut executor).unwrap();
+    assert_eq!(names, bson!(["Ingrid", "Yuki"]));
+}
+
+#[test]
+fn test_table_references() {
+    let storage = Storage::open(&temporary_datablaze("table_references")).unwrap();
+    let mut executor = Executor::with_storage(storage.shared());
+    execute(
+        r#"table countries { name: str };
+        table accounts { name: str, country: &countries? nullify };
+        table products { title: str = "product", seller: &accounts cascade };
+        table carts { products: &products[] };
+        fin norway = &countries(name = "Norway");
+        fin ole = &accounts(name = "Ole", country = norway);
+        fin chair = &products(seller = ole);
+        &carts(products = &products.{self.id = chair}.id)"#,
+        &mut executor,
+    )
+    .unwrap();
+
+    let dangling = execute("&products(title = \"lamp\", seller = 42)", &mut executor);
+    assert!(dangling.is_err());
+
+    let walked = execute(
+        "fin product = &products.{self.title = \"product\"}; product.seller.country.name",
+        &mut executor,
+    )
+    .unwrap();
+    assert_eq!(walked, bson!(["Norway"]));
+    let selected = execute("&carts.products.seller.name", &mut executor).unwrap();
+    assert_eq!(selected, bson!(["Ole"]));
+
+    let restricted = execute("delete &products.{self.id = 1}", &mut executor);
+    assert!(restricted.is_err());
+    let deleted = execute("delete &carts; delete &accounts", &mut executor).unwrap();
+    assert_eq!(deleted, bson!(2_i64));
+    let countries = execute("&countries.name", &mut executor).unwrap();
+    assert_eq!(countries, bson!(["Norway"]));
+}
+
+#[test]
+fn test_column_constraints() {
+    let storage = Storage::open(&temporary_datablaze("column_constraints")).unwrap();
+    let mut executor = Executor::with_storage(storage.shared());
+    execute(
+        r#"table accounts {
+            name: str <=10 = "User",
+            alpha2: str 2,
+            age: int >0 <100,
+            bio: str?,
+            password: str!;
+        }"#,
+        &mut executor,
+    )
+    .unwrap();
+    execute(
+        r#"&accounts(alpha2 = "NO", age = 30, password = "secret")"#,
+        &mut executor,
+    )
+    .unwrap();
+
+    let violation_of = |code: &str, executor: &mut Executor| {
+        let error = execute(code, executor).unwrap_err();
+        let violation = ConstraintViolation::from_error(&error).unwrap().clone();
+        (violation.table, violation.column, violation.rule)
+    };
+    let expected =
+        |column: &str, rule: &str| ("accounts".to_string(), column.to_string(), rule.to_string());
+    assert_eq!(
+        violation_of(
+            r#"&accounts(alpha2 = "NOR", age = 30, password = "")"#,
+            &mut executor
+        ),
+        expected("alpha2", "= 2")
+    );
+    assert_eq!(
+        violation_of(
+            r#"&accounts(alpha2 = "NO", age = 100, password = "")"#,
+            &mut executor
+        ),
+        expected("age", "< 100")
+    );
+    assert_eq!(
+        violation_of(r#"&accounts(alpha2 = "NO", age = 5)"#, &mut executor),
+        expected("password", "required")
+    );
+    assert_eq!(
+        violation_of(
+            r#"&accounts(alpha2 = "NO", age = "5", password = "")"#,
+            &mut executor
+        ),
+        expected("age", "type int")
+    );
+    assert_eq!(
+        violation_of(
+            r#"&accounts.{self.id = 1}.name = "Longer than ten""#,
+            &mut executor
+        ),
+        expected("name", "<= 10")
+    );
+    assert_eq!(
+        violation_of(r#"&accounts.{self.id = 1}.age = null"#, &mut executor),
+        expected("age", "not null")
+    );
+
+    let updated = execute(r#"&accounts.{self.id = 1}.age = 31"#, &mut executor).unwrap();
+    assert_eq!(updated, bson!(1_i64));
+    let account = execute("&accounts.{self.id = 1}", &mut executor).unwrap();
+    assert_eq!(
+        account,
+        bson!([{ "alpha2": "NO", "age": 31_i64, "password": "secret", "name": "User", "id": 1_i64 }])
+    );
+
+    // The items of an array column are bounded one by one.
+    execute(
+        "table results { scores: int[] >0, tags: str[] <=3 }",
+        &mut executor,
+    )
+    .unwrap();
+    let insert = "&results(scores = ?, tags = ?)";
+    for (scores, tags, rule) in [
+        (bson!([-5, -7]), bson!(["a"]), Some(("scores", "> 0"))),
+        (bson!([3, 0]), bson!(["a"]), Some(("scores", "> 0"))),
+        (bson!([3]), bson!(["abc", "abcd"]), Some(("tags", "<= 3"))),
+        (bson!([3, 1]), bson!(["abc", "ab", "a", "b"]), None),
+    ] {
+        executor.bind(vec![scores, tags].into());
+        let result = execute(insert, &mut executor);
+        match rule {
+            Some((column, rule)) => {
+                let violation = ConstraintViolation::from_error(&result.unwrap_err())
+                    .unwrap()
+                    .clone();
+                assert_eq!(
+                    (violation.column.as_str(), violation.rule.as_str()),
+                    (column, rule)
+                );
+            }
+            None => assert!(result.is_ok()),
+        }
+    }
+
+    // A bound too large for a number is refused as the code is parsed.
+    let code = format!("table huge {{ name: str <= 1{} }}", "0".repeat(400));
+    let error = execute(&code, &mut executor).unwrap_err();
+    assert_eq!(ErrorReport::from_error(&error).kind, "Syntax");
+}
+
+#[test]
+fn test_key_strategies() {
+    let datablaze = temporary_datablaze("key_strategies");
+    let storage = Storage::open(&datablaze).unwrap();
+    let mut executor = Executor::with_storage(storage.shared());
+    execute(
+        r#"table countries: str { name: str; };
+        table accounts: uuid7 { handle: str = self.id; };
+        table visits { page: str; }"#,
+        &mut executor,
+    )
+    .unwrap();
+
+    let country = execute(r#"&countries(id = "NO", name = "Norway")"#, &mut executor).unwrap();
+    assert_eq!(country, Bson::String("NO".to_string()));
+    let error = execute(r#"&countries(name = "Sweden")"#, &mut executor).unwrap_err();
+    let violation = ConstraintViolation::from_error(&error).unwrap();
+    assert_eq!(
+        (violation.column.as_str(), violation.rule.as_str()),
+        ("id", "required")
+    );
+
+    let first_account = execute(r#"&accounts()"#, &mut executor).unwrap();
+    let second_account = execute(r#"&accounts()"#, &mut executor).unwrap();
+    let first_id = first_account.as_str().unwrap().to_string();
+    assert_eq!(first_id.len(), 36);
+    assert!(first_id.as_str() < second_account.as_str().unwrap());
+    assert_eq!(
+        execute(
+            &format!(r#"&accounts.{{self.id = "{}"}}.handle"#, first_id),
+            &mut executor
+        )
+        .unwrap(),
+        bson!([first_id.clone()])
+    );
+
+    execute(
+        r#"&visits(page = "/"); &visits(page = "/about")"#,
+        &mut executor,
+    )
+    .unwrap();
+    execute(r#"delete &visits.{self.id = 2}"#, &mut executor).unwrap();
+    drop(executor);
+    let mut executor = Executor::with_storage(Storage::open(&datablaze).unwrap().shared());
+    assert_eq!(
+        execute(r#"&visits(page = "/contacts")"#, &mut executor).unwrap(),
+        Bson::Int64(3)
+    );
+}
+
+#[test]
+fn test_table_logs() {
+    let datablaze = temporary_datablaze("table_logs");
+    let storage = Storage::open(&datablaze).unwrap();
+    let mut executor = Executor::with_storage(storage.shared());
+    execute("table notes { n: int }; index notes.n", &mut executor).unwrap();
+    let file_size = |name: &str| {
+        std::fs::metadata(datablaze.join("data").join(name)).map_or(0, |file| file.len())
+    };
+
+    // Writes are appended to the log of the table rather than rewriting its files.
+    execute(
+        "&notes(n = 3); &notes(n = 1); &notes(n = 2); &notes.{self.n = 1}.n = 5",
+        &mut executor,
+    )
+    .unwrap();
+    execute("delete &notes.{self.n = 2}", &mut executor).unwrap();
+    assert_eq!(file_size("notes.bson"), 0);
+    assert!(file_size("notes.log") > 0);
+    drop(executor);
+    let mut executor = Executor::with_storage(Storage::open(&datablaze).unwrap().shared());
+    assert_eq!(
+        execute("&notes.{self.n > 0}.n", &mut executor).unwrap(),
+        bson!([3_i64, 5_i64])
+    );
+    // A rolled back transaction restores the rows it wrote one by one.
+    executor.begin_transaction().unwrap();
+    execute(
+        "&notes(n = 8); &notes.{self.n = 3}.n = 9; delete &notes.{self.n = 5}",
+        &mut executor,
+    )
+    .unwrap();
+    executor.rollback_transaction().unwrap();
+    assert_eq!(
+        execute("&notes.{self.n > 0}.n", &mut executor).unwrap(),
+        bson!([3_i64, 5_i64])
+    );
+    assert_eq!(
+        execute("&notes(n = 4)", &mut executor).unwrap(),
+        bson!(4_i64)
+    );
+
+    // A log grown longer than the table is folded into the table file.
+    drop(executor);
+    let mut storage = Storage::open(&datablaze).unwrap();
+    for n in 0..1000 {
+        storage
+            .update("notes", &bson!(1_i64), doc! { "n": n as i64 })
+            .unwrap();
+    }
+    assert!(file_size("notes.bson") > 0);
+    assert!(file_size("notes.log") < file_size("notes.bson") * 10);
+    let files = std::fs::read_dir(datablaze.join("data")).unwrap();
+    assert!(files
+        .map(|file| file.unwrap().path())
+        .all(|path| path.extension().is_none_or(|extension| extension != "tmp")));
+    drop(storage);
+    let mut executor = Executor::with_storage(Storage::open(&datablaze).unwrap().shared());
+    assert_eq!(
+        execute("&notes.{self.n >= 0}.n", &mut executor).unwrap(),
+        bson!([4_i64, 5_i64, 999_i64])
+    );
+
+    // A record half-written when the process stopped is cut off the log.
+    execute("&notes(n = 6)", &mut executor).unwrap();
+    drop(executor);
+    let log_path = datablaze.join("data").join("notes.log");
+    let mut log = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&log_path)
+        .unwrap();
+    std::io::Write::write_all(&mut log, &[64, 0, 0, 0, 3, b'p']).unwrap();
+    drop(log);
+    let log_size = file_size("notes.log");
+    let mut executor = Executor::with_storage(Storage::open(&datablaze).unwrap().shared());
+    assert_eq!(file_size("notes.log"), log_size - 6);
+    execute("&notes(n = 7)", &mut executor).unwrap();
+    drop(executor);
+    let mut executor = Executor::with_storage(Storage::open(&datablaze).unwrap().shared());
+    assert_eq!(
+        execute("&notes.{self.n >= 0}.n", &mut executor).unwrap(),
+        bson!([4_i64, 5_i64, 6_i64, 7_i64, 999_i64])
+    );
+}
+
+#[test]
+fn test_events() {
+    let storage = Storage::open(&temporary_datablaze("events")).unwrap();
+    let mut executor = Executor::with_storage(storage.shared());
+    execute(
+        r#"table carts { items: int, touched: int = 0; };
+        table log { cart: int, action: str <=6; };
+        event cart_created (&carts, "insert") { &log(cart = self.id, action = "insert") };
+        event cart_changed (&carts.items, "change") {
+            &carts.{self.id = self.id}.touched = self.items;
+        };
+        event cart_deleted (&carts, "delete") { &log(cart = self.id, action = "delete") }"#,
+        &mut executor,
+    )
+    .unwrap();
+
+    execute(r#"&carts(items = 1)"#, &mut executor).unwrap();
+    execute(r#"&carts.{self.id = 1}.items = 3"#, &mut executor).unwrap();
+    assert_eq!(
+        execute(r#"&carts.touched"#, &mut executor).unwrap(),
+        bson!([3_i64])
+    );
+    execute(r#"delete &carts.{self.id = 1}"#, &mut executor).unwrap();
+    assert_eq!(
+        execute(r#"&log.action"#, &mut executor).unwrap(),
+        bson!(["insert", "delete"])
+    );
+
+    // A failing event undoes the write that triggered it.
+    execute(
+        r#"event cart_created (&carts, "insert") { &log(cart = self.id, action = "created") }"#,
+        &mut executor,
+    )
+    .unwrap();
+    assert!(execute(r#"&carts(items = 2)"#, &mut executor).is_err());
+    assert_eq!(execute(r#"&carts"#, &mut executor).unwrap(), bson!([]));
+
+    // Events triggering themselves are stopped.
+    execute(
+        r#"event cart_changed (&carts.items, "change") {
+            &carts.{self.id = self.id}.items = self.items + 1;
+        }"#,
+        &mut executor,
+    )
+    .unwrap();
+    execute(
+        r#"event cart_created (&carts, "insert") { &log(cart = self.id, action = "insert") }"#,
+        &mut executor,
+    )
+    .unwrap();
+    execute(r#"&carts(items = 1)"#, &mut executor).unwrap();
+    let error = execute(r#"&carts.items = 10"#, &mut executor).unwrap_err();
+    assert!(error.to_string().contains("nested events"));
+    assert_eq!(
+        execute(r#"&carts.items"#, &mut executor).unwrap(),
+        bson!([1_i64])
+    );
+}
+
+#[test]
+fn test_packages() {
+    let storage = Storage::open(&temporary_datablaze("packages"))
+        .unwrap()
+        .shared();
+    let packages = storage.lock().unwrap().packages_path();
+    let write_package = |name: &str, code: &str| {
+        std::fs::write(packages.join(format!("{}.blz", name)), code).unwrap();
+    };
+    write_package(
+        "geo",
+        r#"package geo;
+        table countries { name: str; };
+        fin default_country = "Norway";
+        fin continent = "Europe""#,
+    );
+    write_package(
+        "users",
+        r#"package users;
+        import geo:default_country;
+        table accounts { country: str = default_country; };
+        event account_created (&accounts, "insert") { null }"#,
+    );
+    write_package("billing", "package billing; table accounts { total: int; }");
+    write_package(
+        "audit",
+        r#"package audit; event account_created (&accounts, "insert") { null }"#,
+    );
+    write_package("ping", "package ping; import pong:all");
+    write_package("pong", "package pong; import ping:all");
+    write_package("misnamed", "package other");
+
+    let mut executor = Executor::with_storage(storage.clone()).with_packages(packages);
+    execute("import users:accounts, geo:continent", &mut executor).unwrap();
+    assert_eq!(
+        execute("continent", &mut executor).unwrap(),
+        Bson::String("Europe".to_string())
+    );
+    // Variables of a package stay in its namespace unless they're imported.
+    assert!(execute("default_country", &mut executor).is_err());
+
+    let error = execute("import users:profiles", &mut executor).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Package 'users' has no 'profiles'"));
+    let error = execute("import ping:all", &mut executor).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("in a cycle: ping -> pong -> ping"));
+    assert!(execute("import misnamed:all", &mut executor).is_err());
+
+    // Tables and events belong to the package declaring them, and no one else may
+    // declare them again.
+    let error = execute("import billing:all", &mut executor).unwrap_err();
+    assert!(error.to_string().contains(
+        "Table 'accounts' is declared by package users, so it can't be declared by package billing"
+    ));
+    let error = execute("import audit:all", &mut executor).unwrap_err();
+    assert!(error.to_string().contains(
+        "Event 'account_created' is declared by package users, so it can't be declared by package audit"
+    ));
+    let error = execute("table countries { name: str; }", &mut executor).unwrap_err();
+    assert!(error.to_string().contains(
+        "Table 'countries' is declared by package geo, so it can't be declared outside of it"
+    ));
+
+    // Sessions of the server import packages from the datablaze's `packages` folder.
+    let (address, _) = spawn_server(storage, connection_pool(), server_bz::serve);
+    let mut connection = Connection::connect(address, "admin", "secret").unwrap();
+    assert_eq!(
+        connection
+            .execute("import users:accounts, geo:all; &accounts(); default_country")
+            .unwrap(),
+        Bson::String("Norway".to_string())
+    );
+    assert_eq!(
+        connection.execute("&accounts.country").unwrap(),
+        bson!(["Norway"])
+    );
+}
+
+#[test]
+fn test_schema_catalog() {
+    let datablaze = temporary_datablaze("schema_catalog");
+    let mut executor = Executor::with_storage(Storage::open(&datablaze).unwrap().shared());
+    execute(
+        r#"enum Gender: str { Male, Female, Unspecified };
+        table countries: str { name: str <=50; };
+        table accounts: uuid {
+            name: str <=30 = "User",
+            gender: Gender = Gender.Unspecified,
+            country: &countries? nullify;
+        };
+        function get_population_of(country_name: str): int {
+            count(&accounts.{self.country = country_name})
+        };
+        event renamed (&accounts.name, "change") { null }"#,
+        &mut executor,
+    )
+    .unwrap();
+    assert!(datablaze.join("model").join("catalog.bson").exists());
+    drop(executor);
+
+    // Declarations come from the catalog once the datablaze is opened again.
+    let mut executor = Executor::with_storage(Storage::open(&datablaze).unwrap().shared());
+    assert_eq!(
+        execute("Gender.Female", &mut executor).unwrap(),
+        Bson::String("Female".to_string())
+    );
+    assert_eq!(
+        execute("inspect all", &mut executor).unwrap(),
+        Bson::String(
+            [
+                "catalog v1 revision 5",
+                r#"table accounts: uuid4 { name: str <= 30 = "User", gender: Gender = Gender.Unspecified, country: &countries? nullify }"#,
+                "table countries: str { name: str <= 50 }",
+                "enum Gender: str { Male, Female, Unspecified }",
+                "function get_population_of(country_name: str): int",
+                r#"event renamed (&accounts.name, "change")"#,
+            ]
+            .join("\n")
+        )
+    );
+    assert_eq!(
+        execute("inspect countries", &mut executor).unwrap(),
+        Bson::String("table countries: str { name: str <= 50 }".to_string())
+    );
+}
+
+#[test]
+fn test_schema_migrations() {
+    let datablaze = temporary_datablaze("schema_migrations");
+    let mut executor = Executor::with_storage(Storage::open(&datablaze).unwrap().shared());
+    execute(
+        r#"table accounts { name: str, bio: str?, age: str; };
+        index accounts.bio;
+        &accounts(name = "Ann", bio = "Hi", age = "30");
+        &accounts(name = "Bob", age = "41")"#,
+        &mut executor,
+    )
+    .unwrap();
+
+    assert_eq!(
+        execute(
+            r#"table accounts {
+                name: str <=10,
+                about: str? from bio,
+                age: int,
+                score: int = self.age * 2;
+            }"#,
+            &mut executor
+        )
+        .unwrap(),
+        Bson::String(
+            "migrate accounts: alter name, rename bio to about, retype age from str to int, add score"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        execute("&accounts.score", &mut executor).unwrap(),
+        bson!([60_i64, 82_i64])
+    );
+    assert_eq!(
+        execute(r#"inspect &accounts.{self.about = "Hi"}"#, &mut executor).unwrap(),
+        Bson::String(r#"IndexScan accounts.about = "Hi""#.to_string())
+    );
+
+    // Rows written while defaults are evaluated would be lost, so the migration is refused.
+    let error = execute(
+        r#"table accounts {
+            name: str <=10,
+            about: str?,
+            age: int,
+            score: int,
+            badge: int = &accounts(name = "Eve", age = 20, score = 0);
+        }"#,
+        &mut executor,
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("declare it again"));
+    assert_eq!(
+        execute("&accounts.name", &mut executor).unwrap(),
+        bson!(["Ann", "Bob", "Eve", "Eve"])
+    );
+    execute(r#"delete &accounts.{self.name = "Eve"}"#, &mut executor).unwrap();
+
+    // Tightened constraints are checked against the stored rows.
+    let error = execute(
+        "table accounts { name: str <=2, about: str?, age: int, score: int; }",
+        &mut executor,
+    )
+    .unwrap_err();
+    assert_eq!(
+        ConstraintViolation::from_error(&error).unwrap().rule,
+        "<= 2"
+    );
+
+    // Dropping values is refused unless it's forced.
+    let error = execute("table accounts { name: str, age: int; }", &mut executor).unwrap_err();
+    assert!(error.to_string().contains("drop"));
+    assert_eq!(
+        execute("table! accounts { name: str, age: int; }", &mut executor).unwrap(),
+        Bson::String("migrate accounts: alter name, drop about, drop score".to_string())
+    );
+    drop(executor);
+    let mut executor = Executor::with_storage(Storage::open(&datablaze).unwrap().shared());
+    assert_eq!(
+        execute("&accounts.{self.id = 1}", &mut executor).unwrap(),
+        bson!([{"id": 1_i64, "name": "Ann", "age": 30_i64}])
+    );
+}
+
+#[test]
+fn test_datetime() {
+    let storage = Storage::open(&temporary_datablaze("datetime")).unwrap();
+    let mut executor = Executor::with_storage(storage.shared());
+    execute(
+        r#"table orders { placed_at: datetime = "now", due_at: datetime?; };
+        index orders.placed_at;
+        &orders(placed_at = "2024-02-28T22:30:00Z", due_at = "2024-03-01");
+        &orders()"#,
+        &mut executor,
+    )
+    .unwrap();
+
+    let placed_at = execute("&orders.{self.id = 1}.placed_at", &mut executor).unwrap();
+    assert_eq!(
+        placed_at,
+        bson!([bson::DateTime::parse_rfc3339_str("2024-02-28T22:30:00Z").unwrap()])
+    );
+    let Bson::Array(defaults) = execute("&orders.{self.id = 2}.placed_at", &mut executor).unwrap()
+    else {
+        panic!("an array is expected");
+    };
+    let Bson::DateTime(default) = defaults[0] else {
+        panic!("a datetime is expected");
+    };
+    assert!(bson::DateTime::now().timestamp_millis() - default.timestamp_millis() < 60_000);
+
+    assert_eq!(
+        execute(
+            r#"&orders.{self.placed_at < "2024-03-01T00:00:00+01:00"}.id"#,
+            &mut executor
+        )
+        .unwrap(),
+        bson!([1_i64])
+    );
+    assert_eq!(
+        execute(
+            r#"format_datetime(datetime("2024-02-28T22:30:00Z") + days(1) + hours(2), "%Y-%m-%d %H:%M")"#,
+            &mut executor
+        )
+        .unwrap(),
+        Bson::String("2024-03-01 00:30".to_string())
+    );
+    assert_eq!(
+        execute(
+            r#"fin gap = datetime("2024-03-01") - datetime("2024-02-28T22:30:00Z");
+            gap / minutes(1)"#,
+            &mut executor
+        )
+        .unwrap(),
+        Bson::Double(1530.0)
+    );
+    let error = execute(r#"&orders(placed_at = "yesterday")"#, &mut executor).unwrap_err();
+    assert_eq!(
+        ConstraintViolation::from_error(&error).unwrap().rule,
+        "type datetime"
+    );
+}
+
+#[test]
+fn test_geo() {
+    let storage = Storage::open(&temporary_datablaze("geo")).unwrap();
+    let mut executor = Executor::with_storage(storage.shared());
+    execute(
+        r#"table places { name: str, location: geo; };
+        index places.location;
+        &places(name = "Oslo", location = point(10.75, 59.91));
+        &places(name = "Bergen", location = point(5.32, 60.39));
+        &places(name = "Tokyo", location = point(139.69, 35.69))"#,
+        &mut executor,
+    )
+    .unwrap();
+
+    let query = "&places.{self.location within box(4, 58, 12, 62)}";
+    let Bson::String(plan) = execute(&format!("inspect {}", query), &mut executor).unwrap() else {
+        panic!("a plan is expected");
+    };
+    assert!(plan.starts_with("IndexScan places.location within"));
+    assert_eq!(
+        execute(&format!("{}.name", query), &mut executor).unwrap(),
+        bson!(["Bergen", "Oslo"])
+    );
+    assert_eq!(
+        execute(
+            "&places.{self.location within polygon(point(135, 30), point(145, 30), point(140, 40))}.name",
+            &mut executor
+        )
+        .unwrap(),
+        bson!(["Tokyo"])
+    );
+
+    let Bson::Double(meters) = execute(
+        "distance(point(10.75, 59.91), point(5.32, 60.39))",
+        &mut executor,
+    )
+    .unwrap() else {
+        panic!("a distance is expected");
+    };
+    assert!((meters - 305_000.0).abs() < 5_000.0);
+    assert_eq!(
+        execute(
+            "within(point(10.75, 59.91), box(4, 58, 12, 62))",
+            &mut executor
+        )
+        .unwrap(),
+        Bson::Boolean(true)
+    );
+
+    let error = execute(r#"&places(name = "Nowhere", location = 5)"#, &mut executor).unwrap_err();
+    assert_eq!(
+        ConstraintViolation::from_error(&error).unwrap().rule,
+        "type geo"
+    );
+}
+
+#[test]
+fn test_standard_library() {
+    let storage = Storage::open(&temporary_datablaze("standard_library")).unwrap();
+    let mut executor = Executor::with_storage(storage.shared());
+    execute(
+        r#"table accounts { name: str <= 30 = format("User{}", self.id), age: int?; };
+        &accounts(age = 31);
+        &accounts(name = "Ingrid", age = 24);
+        &accounts(name = "Yuki")"#,
+        &mut executor,
+    )
+    .unwrap();
+
+    let cases = [
+        (r#"&accounts.{self.id = 1}.name"#, bson!(["User1"])),
+        (r#"upper(format("{}-{}", "ab", 7))"#, bson!("AB-7")),
+        (r#"len(split(lower("A,B,C"), ","))"#, bson!(3_i64)),
+        (r#"contains("Ingrid", "gri")"#, bson!(true)),
+        ("abs(3 - 10)", bson!(7_i64)),
+        ("round(2.345, 2)", bson!(2.35)),
+        ("pow(2, 10)", bson!(1024_i64)),
+        ("max(3, 9.5, 4)", bson!(9.5)),
+        ("count(&accounts.age)", bson!(2_i64)),
+        ("sum(&accounts.age)", bson!(55_i64)),
+        ("avg(&accounts.age)", bson!(27.5)),
+        ("min(&accounts.name)", bson!("Ingrid")),
+        (r#"int("42") + int(2.9)"#, bson!(44_i64)),
+        ("str(1.5)", bson!("1.5")),
+        ("inspect round", bson!("round(num, int?) -> num")),
+        ("inspect format", bson!("format(str, any...) -> str")),
+    ];
+    for (code, expected) in cases {
+        assert_eq!(execute(code, &mut executor).unwrap(), expected, "{}", code);
+    }
+
+    let error = execute("lower(5)", &mut executor).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("'lower' expects str as argument 1, but 5 is given"));
+    let error = execute(r#"format("{} {}", 1)"#, &mut executor).unwrap_err();
+    assert!(error.to_string().contains("2 placeholders, but 1 values"));
+}
+
+#[test]
+fn test_grouping() {
+    let storage = Storage::open(&temporary_datablaze("grouping")).unwrap();
+    let mut executor = Executor::with_storage(storage.shared());
+    execute(
+        r#"table sellers { name: str };
+        table sales { seller: &sellers, year: int, amount: int? };
+        fin ole = &sellers(name = "Ole");
+        fin yuki = &sellers(name = "Yuki");
+        &sales(seller = ole, year = 2023, amount = 40);
+        &sales(seller = ole, year = 2024, amount = 70);
+        &sales(seller = yuki, year = 2024, amount = 30);
+        &sales(seller = yuki, year = 2024, amount = 45);
+        &sales(seller = yuki, year = 2024)"#,
+        &mut executor,
+    )
+    .unwrap();
+
+    let per_seller = execute(
+        "&sales.{self.year = 2024}.group(seller){total: sum(amount), deals: count(), top: max(amount), mean: avg(amount)}",
+        &mut executor,
+    )
+    .unwrap();
+    assert_eq!(
+        per_seller,
+        bson!([
+            {"seller": {"$ref": "sellers", "$id": 1_i64}, "total": 70_i64, "deals": 1_i64, "top": 70_i64, "mean": 70.0},
+            {"seller": {"$ref": "sellers", "$id": 2_i64}, "total": 75_i64, "deals": 3_i64, "top": 45_i64, "mean": 37.5},
+        ])
+    );
+
+    let cases = [
+        (
+            "&sales.group(seller){total: sum(amount)}.{self.total > 100}.seller.name",
+            bson!(["Ole"]),
+        ),
+        (
+            "&sales.group(year){sales: count(amount)}.sales",
+            bson!([1_i64, 3_i64]),
+        ),
+        ("&sales.group(){total: sum(amount)}.total", bson!([185_i64])),
+    ];
+    for (code, expected) in cases {
+        assert_eq!(execute(code, &mut executor).unwrap(), expected, "{}", code);
+    }
+
+    assert!(execute("&sellers.group(){total: sum(name)}", &mut executor).is_err());
+}
+
+#[test]
+fn test_ordering_and_pagination() {
+    let storage = Storage::open(&temporary_datablaze("ordering")).unwrap();
+    let mut executor = Executor::with_storage(storage.shared());
+    execute(
+        r#"table products { title: str, price: int, created_at: datetime };
+        index products.created_at;
+        &products(title = "lamp", price = 30, created_at = "2024-03-02");
+        &products(title = "chair", price = 50, created_at = "2024-03-01");
+        &products(title = "desk", price = 30, created_at = "2024-03-04");
+        &products(title = "bed", price = 90, created_at = "2024-03-03");
+        &products(title = "rug", price = 10, created_at = "2024-03-03")"#,
+        &mut executor,
+    )
+    .unwrap();
+
+    let cases = [
+        (
+            "&products.order(price desc, title).title",
+            bson!(["bed", "chair", "desk", "lamp", "rug"]),
+        ),
+        (
+            "&products.order(created_at, title desc).offset(1).limit(3).title",
+            bson!(["lamp", "rug", "bed"]),
+        ),
+        (
+            "fin page = 1; &products.order(created_at desc).offset(page * 2).limit(2).title",
+            bson!(["rug", "lamp"]),
+        ),
+        (
+            "&products.{self.price < 60}.order(created_at desc).limit(2).title",
+            bson!(["desk", "rug"]),
+        ),
+        (
+            "&products.group(price){count: count()}.order(count desc, price).limit(1).price",
+            bson!([30_i64]),
+        ),
+        (
+            "inspect &products.order(created_at desc).limit(2)",
+            bson!("IndexOrder products.created_at desc"),
+        ),
+        ("inspect &products.order(price)", bson!("FullScan products")),
+    ];
+    for (code, expected) in cases {
+        assert_eq!(execute(code, &mut executor).unwrap(), expected, "{}", code);
+    }
+
+    let error = execute(r#"&products.limit("ten")"#, &mut executor).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("'limit' takes a non-negative integer"));
+}
+
+#[test]
+fn test_reference_joins() {
+    let storage = Storage::open(&temporary_datablaze("joins")).unwrap();
+    let mut executor = Executor::with_storage(storage.shared());
+    execute(
+        r#"table countries { name: str };
+        table accounts { name: str, country: &countries };
+        table products { title: str, seller: &accounts };
+        table carts { products: &products[] };
+        index products.seller;
+        fin norway = &countries(name = "Norway");
+        fin japan = &countries(name = "Japan");
+        fin ole = &accounts(name = "Ole", country = norway);
+        fin yuki = &accounts(name = "Yuki", country = japan);
+        fin ingrid = &accounts(name = "Ingrid", country = norway);
+        &products(title = "lamp", seller = ole);
+        &products(title = "chair", seller = yuki);
+        &products(title = "desk", seller = ingrid);
+        &products(title = "rug", seller = ole);
+        &carts(products = &products.{self.title = "chair"}.id);
+        &carts(products = &products.{self.seller = ole}.id)"#,
+        &mut executor,
+    )
+    .unwrap();
+
+    let query = r#"&products.{self.seller.country.name = "Norway"}"#;
+    assert_eq!(
+        execute(&format!("{}.title", query), &mut executor).unwrap(),
+        bson!(["lamp", "rug", "desk"])
+    );
+    assert_eq!(
+        execute(&format!("inspect {}", query), &mut executor).unwrap(),
+        bson!(
+            "IndexScan products.seller = [1, 3]\n  join products.seller: FullScan accounts\n    join accounts.country: FullScan countries"
+        )
+    );
+
+    let cases = [
+        (
+            r#"&products.{self.seller.name != "Ole", self.title != "desk"}.title"#,
+            bson!(["chair"]),
+        ),
+        (
+            r#"&accounts.{self.country.name = "Norway"}.referenced_by(products.seller).title"#,
+            bson!(["lamp", "rug", "desk"]),
+        ),
+        (
+            r#"&carts.{self.products.seller.name = "Yuki"}.id"#,
+            bson!([1_i64]),
+        ),
+        (
+            r#"&products.{self.title = "rug"}.referenced_by(carts.products).id"#,
+            bson!([2_i64]),
+        ),
+        (
+            r#"&products.seller.{self.country.name = "Japan"}.name"#,
+            bson!(["Yuki"]),
+        ),
+    ];
+    for (code, expected) in cases {
+        assert_eq!(execute(code, &mut executor).unwrap(), expected, "{}", code);
+    }
+
+    let error = execute("&accounts.referenced_by(products.title)", &mut executor).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("'products.title' isn't a reference column"));
+}
+
+#[test]
+fn test_wire_protocol() {
+    let address = start_server("wire_protocol");
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    let response = auth::login(&mut stream, "admin", "secret").unwrap();
+    let Response::Success(Bson::Document(result)) = response else {
+        panic!("logging in is expected to succeed");
+    };
+    assert_eq!(result.get_array("roles").unwrap(), &vec![bson!("admin")]);
+    let response = send_code(&mut stream, "table notes { text: str <= 5000 }");
+    assert_eq!(response, Response::Success(Bson::Null));
+    let long_text = "a".repeat(3000);
+    let response = send_code(
+        &mut stream,
+        &format!(r#"&notes(text = "{}"); &notes.text"#, long_text),
+    );
+    assert_eq!(response, Response::Success(bson!([long_text])));
+
+    let failure = |response: Response| match response {
+        Response::Failure(report) => report,
+        Response::Success(value) => panic!("an error is expected instead of {}", value),
+    };
+    let report = failure(send_code(&mut stream, "&notes(text = "));
+    assert_eq!(report.kind, "Syntax");
+    assert!(!report.message.contains('\u{1b}'));
+    let report = failure(send_code(
+        &mut stream,
+        &format!(r#"&notes(text = "{}")"#, "a".repeat(5001)),
+    ));
+    assert_eq!(report.kind, "Constraint");
+    assert_eq!(report.violation.unwrap().rule, "<= 5000");
+    let report = failure(send(
+        &mut stream,
+        Request::Authenticate {
+            proof: "00".repeat(32),
+        },
+    ));
+    assert_eq!(report.kind, "Authentication");
+    let mut stream = TcpStream::connect(address).unwrap();
+    let report = failure(auth::login(&mut stream, "admin", "wrong").unwrap());
+    assert_eq!(report.kind, "Authentication");
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    std::io::Write::write_all(&mut stream, &i32::MAX.to_le_bytes()).unwrap();
+    let report = failure(
+        Response::from_document(&protocol::read_frame(&mut stream).unwrap().unwrap()).unwrap(),
+    );
+    assert_eq!(report.kind, "Protocol");
+}
+
+#[test]
+fn test_sessions() {
+    let address = start_server("sessions");
+
+    let connect = || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        assert!(matches!(
+            auth::login(&mut stream, "admin", "secret").unwrap(),
+            Response::Success(_)
+        ));
+        stream
+    };
+
+    let mut unauthenticated = TcpStream::connect(address).unwrap();
+    let Response::Failure(report) = send_code(&mut unauthenticated, "1") else {
+        panic!("an unauthenticated request is expected to fail");
+    };
+    assert_eq!(report.kind, "Authentication");
+
+    let mut first = connect();
+    send_code(&mut first, "table notes { text: str }");
+    send_code(&mut first, "mut greeting: str = \"hello\";");
+    assert_eq!(
+        send_code(&mut first, "greeting"),
+        Response::Success(bson!("hello"))
+    );
+    send_code(&mut first, "package notebook");
+    let Response::Failure(report) = send_code(&mut first, "package other") else {
+        panic!("the session is expected to stay in its package");
+    };
+    assert_eq!(report.kind, "Runtime");
+
+    send(&mut first, Request::Begin);
+    send_code(&mut first, "&notes(text = \"draft\")");
+    send(&mut first, Request::Rollback);
+    assert_eq!(
+        send_code(&mut first, "&notes.text"),
+        Response::Success(bson!([]))
+    );
+
+    // Other sessions are turned away while the first one's transaction is open, rather
+    // than left waiting for it.
+    send(&mut first, Request::Begin);
+    send_code(&mut first, "&notes(text = \"kept\")");
+    let mut second = connect();
+    let Response::Failure(report) = send_code(&mut second, "&notes.text") else {
+        panic!("the second session is expected to be turned away");
+    };
+    assert_eq!(report.kind, "Unavailable");
+    assert_eq!(
+        send(&mut first, Request::Commit),
+        Response::Success(Bson::Null)
+    );
+    assert_eq!(
+        send_code(&mut second, "&notes.text"),
+        Response::Success(bson!(["kept"]))
+    );
+
+    // Declarations can't be rolled back, so they're refused inside a transaction.
+    send(&mut first, Request::Begin);
+    let Response::Failure(report) = send_code(&mut first, "table drafts { text: str }") else {
+        panic!("declaring a table is expected to fail inside a transaction");
+    };
+    assert_eq!(report.kind, "Storage");
+    assert!(matches!(
+        send_code(&mut second, "&drafts.text"),
+        Response::Failure(_)
+    ));
+
+    // Closing a connection rolls back its open transaction.
+    send(&mut first, Request::Begin);
+    send_code(&mut first, "&notes(text = \"abandoned\")");
+    drop(first);
+    let mut third = connect();
+    assert_eq!(
+        send_code(&mut third, "&notes.text"),
+        Response::Success(bson!(["kept"]))
+    );
+}
+
+#[test]
+fn test_http() {
+    let storage = Storage::open(&temporary_datablaze("http"))
+        .unwrap()
+        .shared();
+    let (address, _) = spawn_server(storage, connection_pool(), http::serve);
+
+    let stream = TcpStream::connect(address).unwrap();
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = std::io::BufReader::new(stream);
+    let mut send = |request: String| {
+        use std::io::{BufRead, Read, Write};
+        writer.write_all(request.as_bytes()).unwrap();
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).unwrap();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            match line.trim_end().split_once(": ") {
+                Some(("Content-Length", value)) => length = value.parse().unwrap(),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        let status: u16 = status_line.split(' ').nth(1).unwrap().parse().unwrap();
+        (
+            status,
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+    // Every request but the health check answers a challenge of its own.
+    fn authorized(
+        send: &mut impl FnMut(String) -> (u16, serde_json::Value),
+        request: &str,
+        password: &str,
+    ) -> (u16, serde_json::Value) {
+        let nonce = auth::new_nonce();
+        let (status, body) = send(format!(
+            "POST /challenge HTTP/1.1\r\nNonce: {}\r\nContent-Length: 0\r\n\r\n",
+            nonce
+        ));
+        assert_eq!(status, 200);
+        let challenge = auth::Challenge {
+            user: "admin".to_string(),
+            nonce: body["nonce"].as_str().unwrap().to_string(),
+            salt: body["salt"].as_str().unwrap().to_string(),
+            iterations: body["iterations"].as_u64().unwrap() as u32,
+        };
+        assert!(challenge.nonce.starts_with(&nonce));
+        let keys = Keys::derive(password, &challenge.salt, challenge.iterations);
+        let login = format!(
+            "\r\nNonce: {}\r\nProof: {}\r\n",
+            challenge.nonce,
+            challenge.proof(&keys)
+        );
+        send(request.replacen("\r\n", &login, 1))
+    }
+    let query = |code: &str| {
+        format!(
+            "POST /query HTTP/1.1\r\nHost: localhost:3300\r\nContent-Length: {}\r\n\r\n{}",
+            code.len(),
+            code
+        )
+    };
+
+    let (status, body) = send("GET /health HTTP/1.1\r\n\r\n".to_string());
+    assert_eq!((status, body["status"].as_str()), (200, Some("ok")));
+    let (status, _) = authorized(
+        &mut send,
+        &query("table notes { text: str <= 20 }"),
+        "secret",
+    );
+    assert_eq!(status, 200);
+    let (status, body) = authorized(
+        &mut send,
+        &query(r#"&notes(text = "a: b"); &notes.text"#),
+        "secret",
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body["result"], serde_json::json!(["a: b"]));
+
+    // The same script sent in chunks, as JSON.
+    let chunked = "POST /query HTTP/1.1\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n9\r\n{\"code\": \r\ne\r\n\"&notes.text\"}\r\n0\r\n\r\n";
+    let (status, body) = authorized(&mut send, chunked, "secret");
+    assert_eq!(status, 200);
+    assert_eq!(body["result"], serde_json::json!(["a: b"]));
+    let json = r#"{"code": "&notes(text = ?); &notes.text", "params": ["c"]}"#;
+    let request = format!(
+        "POST /query HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        json.len(),
+        json
+    );
+    let (status, body) = authorized(&mut send, &request, "secret");
+    assert_eq!(status, 200);
+    assert_eq!(body["result"], serde_json::json!(["a: b", "c"]));
+
+    let (status, body) = authorized(&mut send, &query("&notes(text = "), "secret");
+    assert_eq!(
+        (status, body["error"]["kind"].as_str()),
+        (400, Some("Syntax"))
+    );
+    let (status, body) = authorized(
+        &mut send,
+        &query(r#"&notes(text = "far too long for a note")"#),
+        "secret",
+    );
+    assert_eq!(
+        (status, body["error"]["rule"].as_str()),
+        (409, Some("<= 20"))
+    );
+    let (status, _) = authorized(&mut send, "GET /metadata HTTP/1.1\r\n\r\n", "wrong");
+    assert_eq!(status, 401);
+    let (status, _) = send("GET /metadata HTTP/1.1\r\n\r\n".to_string());
+    assert_eq!(status, 401);
+    let (status, body) = authorized(&mut send, "GET /metadata HTTP/1.1\r\n\r\n", "secret");
+    assert_eq!(status, 200);
+    assert!(body["tables"]["notes"]["columns"].is_array());
+    let (status, _) = send("GET /query HTTP/1.1\r\n\r\n".to_string());
+    assert_eq!(status, 405);
+    let (status, _) = send("GET /nowhere HTTP/1.1\r\nConnection: close\r\n\r\n".to_string());
+    assert_eq!(status, 404);
+}
+
+#[test]
+fn test_connection_pool() {
+    let storage = Storage::open(&temporary_datablaze("connection_pool"))
+        .unwrap()
+        .shared();
+    let pool = ConnectionPool::new(1, std::time::Duration::from_millis(300));
+    let (address, _) = spawn_server(storage, pool, server_bz::serve);
+
+    let challenge = || Request::Challenge {
+        user: "admin".to_string(),
+        nonce: auth::new_nonce(),
+    };
+
+    // The only worker serves the first connection, and the second one is turned away
+    // rather than left waiting.
+    let mut first = TcpStream::connect(address).unwrap();
+    assert!(matches!(
+        auth::login(&mut first, "admin", "secret").unwrap(),
+        Response::Success(_)
+    ));
+    let mut second = TcpStream::connect(address).unwrap();
+    let Some(Response::Failure(report)) = protocol::read_frame(&mut second)
+        .unwrap()
+        .map(|frame| Response::from_document(&frame).unwrap())
+    else {
+        panic!("the second connection is expected to be rejected");
+    };
+    assert_eq!(report.kind, "Unavailable");
+
+    // The idle first connection is closed, which makes room for another one.
+    assert_eq!(protocol::read_frame(&mut first).unwrap(), None);
+    let mut second = (0..50)
+        .find_map(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            let mut stream = TcpStream::connect(address).ok()?;
+            protocol::write_frame(&mut stream, &challenge().to_document()).ok()?;
+            let frame = protocol::read_frame(&mut stream).ok()??;
+            let response = Response::from_document(&frame).ok()?;
+            matches!(response, Response::Success(_)).then_some(stream)
+        })
+        .expect("a connection is expected to be served once the first one is closed");
+    auth::login(&mut second, "admin", "secret").unwrap();
+    assert_eq!(
+        send_code(&mut second, "1"),
+        Response::Success(Bson::Int64(1))
+    );
+
+    // Settings that aren't numbers are reported rather than panicking.
+    for setting in ["-timeout", "-max_connections"] {
+        let arguments = vec![setting.to_string(), "soon".to_string()];
+        let error = Config::parse_arguments(arguments).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn test_graceful_shutdown() {
+    let datablaze = temporary_datablaze("graceful_shutdown");
+    let storage = Storage::open(&datablaze).unwrap().shared();
+    let pool = connection_pool();
+    let (address, server) = spawn_server(storage.clone(), pool.clone(), server_bz::serve);
+
+    let connect = || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        auth::login(&mut stream, "admin", "secret").unwrap();
+        stream
+    };
+
+    let mut first = connect();
+    send_code(&mut first, "table notes { text: str }");
+    send_code(&mut first, r#"&notes(text = "kept")"#);
+    send(&mut first, Request::Begin);
+    send_code(&mut first, r#"&notes(text = "unfinished")"#);
+
+    let mut admin = TcpStream::connect(address).unwrap();
+    let Response::Failure(report) = send(&mut admin, Request::Shutdown) else {
+        panic!("shutting down is expected to need a password");
+    };
+    assert_eq!(report.kind, "Authentication");
+    let mut admin = connect();
+    assert_eq!(
+        send(&mut admin, Request::Shutdown),
+        Response::Success(Bson::Null)
+    );
+    server.join().unwrap().unwrap();
+    server_bz::drain(&pool, &storage).unwrap();
+
+    // The open connections have been ended, and their transactions rolled back.
+    assert_eq!(protocol::read_frame(&mut first).unwrap(), None);
+    assert!(TcpStream::connect(address).is_err());
+    drop(storage);
+    let storage = Storage::open(&datablaze).unwrap();
+    let rows: Vec<&str> = storage
+        .get_table("notes")
+        .unwrap()
+        .rows
+        .values()
+        .map(|row| row.get_str("text").unwrap())
+        .collect();
+    assert_eq!(rows, ["kept"]);
+}
+
+#[test]
+fn test_access_control() {
+    let datablaze = temporary_datablaze("access_control");
+    let storage = Storage::open(&datablaze).unwrap().shared();
+    let (address, _) = spawn_server(storage, connection_pool(), server_bz::serve);
+
+    fn failure_kind(response: Response) -> String {
+        match response {
+            Response::Failure(report) => report.kind,
+            Response::Success(value) => panic!("an error is expected instead of {}", value),
+        }
+    }
+    let connect = |user: &str, password: &str| {
+        let mut stream = TcpStream::connect(address).unwrap();
+        let response = auth::login(&mut stream, user, password).unwrap();
+        (stream, response)
+    };
+
+    let (mut admin, _) = connect("admin", "secret");
+    let response = send_code(
+        &mut admin,
+        r#"table sales { amount: int };
+        table salaries { amount: int };
+        &sales(amount = 120);
+        &salaries(amount = 5000);
+        function double(value: int): int { value * 2 };
+        role analysts;
+        grant read on table sales to analysts;
+        grant execute on function double to analysts;
+        user alice = "wonderland": analysts"#,
+    );
+    assert_eq!(response, Response::Success(Bson::Null));
+
+    let (_, response) = connect("alice", "looking glass");
+    assert_eq!(failure_kind(response), "Authentication");
+    let (mut alice, response) = connect("alice", "wonderland");
+    assert!(matches!(response, Response::Success(_)));
+    assert_eq!(
+        send_code(&mut alice, "&sales.amount"),
+        Response::Success(bson!([Bson::Int64(120)]))
+    );
+    assert_eq!(
+        send_code(&mut alice, "double(21)"),
+        Response::Success(Bson::Int64(42))
+    );
+    for forbidden in [
+        "&salaries.amount",
+        "&sales(amount = 1)",
+        "table notes { text: str }",
+        r#"user mallory = "x": admin"#,
+        "inspect all",
+    ] {
+        assert_eq!(failure_kind(send_code(&mut alice, forbidden)), "Access");
+    }
+    assert_eq!(failure_kind(send(&mut alice, Request::Shutdown)), "Access");
+
+    send_code(&mut admin, "grant write on table sales to analysts");
+    assert!(matches!(
+        send_code(&mut alice, "&sales(amount = 1)"),
+        Response::Success(_)
+    ));
+
+    // Writes through links need write permission on the tables the links lead to.
+    send_code(
+        &mut admin,
+        r#"table sellers { name: str };
+        table products { title: str, seller: &sellers cascade };
+        &products(title = "lamp", seller = &sellers(name = "Ole"));
+        grant read on table sellers to analysts;
+        grant read on table products to analysts;
+        grant write on table products to analysts"#,
+    );
+    for forbidden in [
+        "delete &products.seller",
+        r#"&products.seller.name = "Mallory""#,
+    ] {
+        assert_eq!(failure_kind(send_code(&mut alice, forbidden)), "Access");
+    }
+    assert_eq!(
+        send_code(&mut admin, "&products.seller.name"),
+        Response::Success(bson!(["Ole"]))
+    );
+    assert!(matches!(
+        send_code(&mut alice, r#"&products.title = "chair""#),
+        Response::Success(_)
+    ));
+
+    // Passwords are stored only as keys derived from them with a salt.
+    let stored = std::fs::read(datablaze.join("model/access.bson")).unwrap();
+    assert!(!String::from_utf8_lossy(&stored).contains("wonderland"));
+    let storage = Storage::open(&datablaze).unwrap();
+    let alice = &storage.access().users["alice"];
+    assert_ne!(alice.salt, storage.access().users["admin"].salt);
+
+    // A server given no password makes one up for the admin of a new datablaze.
+    let config = |arguments: &[&str]| {
+        Config::parse_arguments(
+            arguments
+                .iter()
+                .map(|argument| argument.to_string())
+                .collect(),
+        )
+        .unwrap()
+    };
+    assert_eq!(config(&["server", "-port", "4000"]).password, None);
+    assert_eq!(
+        config(&["server", "-password", "chosen"])
+            .password
+            .as_deref(),
+        Some("chosen")
+    );
+    let password = auth::new_password();
+    assert_eq!(password.len(), 24);
+    assert_ne!(password, auth::new_password());
+}
+
+#[test]
+fn test_challenge_response() {
+    let address = start_server("challenge_response");
+
+    // Keeps what the client writes, to show the password never crosses the wire.
+    struct Recorded {
+        stream: TcpStream,
+        written: Vec<u8>,
+    }
+    impl std::io::Read for Recorded {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            self.stream.read(buffer)
+        }
+    }
+    impl std::io::Write for Recorded {
+        fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+            self.written.extend_from_slice(buffer);
+            self.stream.write(buffer)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.stream.flush()
+        }
+    }
+    let connect = || TcpStream::connect(address).unwrap();
+    fn failure(response: Response) -> protocol::ErrorReport {
+        match response {
+            Response::Failure(report) => report,
+            Response::Success(value) => panic!("an error is expected instead of {}", value),
+        }
+    }
+    fn challenge(stream: &mut TcpStream, user: &str) -> auth::Challenge {
+        let request = Request::Challenge {
+            user: user.to_string(),
+            nonce: auth::new_nonce(),
+        };
+        let Response::Success(Bson::Document(document)) = send(stream, request) else {
+            panic!("a challenge is expected");
+        };
+        auth::Challenge::from_document(user, &document).unwrap()
+    }
+
+    let mut recorded = Recorded {
+        stream: connect(),
+        written: vec![],
+    };
+    let response = auth::login(&mut recorded, "admin", "secret").unwrap();
+    assert!(matches!(response, Response::Success(_)));
+    assert!(!String::from_utf8_lossy(&recorded.written).contains("secret"));
+
+    // A proof answers only the challenge it was computed for.
+    let mut first = connect();
+    let first_challenge = challenge(&mut first, "admin");
+    let keys = Keys::derive("secret", &first_challenge.salt, first_challenge.iterations);
+    let proof = first_challenge.proof(&keys);
+    let mut second = connect();
+    let second_challenge = challenge(&mut second, "admin");
+    assert_eq!(second_challenge.salt, first_challenge.salt);
+    assert_ne!(second_challenge.nonce, first_challenge.nonce);
+    let replayed = Request::Authenticate {
+        proof: proof.clone(),
+    };
+    assert_eq!(failure(send(&mut second, replayed)).kind, "Authentication");
+    let Response::Success(Bson::Document(result)) =
+        send(&mut first, Request::Authenticate { proof })
+    else {
+        panic!("the proof is expected to answer its challenge");
+    };
+    assert_eq!(
+        result.get_str("signature").unwrap(),
+        first_challenge.signature(&keys.server_key)
+    );
+
+    // A declared user logs in with the password it was declared with, and no other.
+    let mut admin = connect();
+    auth::login(&mut admin, "admin", "secret").unwrap();
+    let declaration = Request::Execute {
+        code: r#"user alice = "wonderland""#.to_string(),
+        params: Params::default(),
+    };
+    assert!(matches!(
+        send(&mut admin, declaration),
+        Response::Success(_)
+    ));
+    let mut alice = connect();
+    let report = failure(auth::login(&mut alice, "alice", "secret").unwrap());
+    assert_eq!(report.message, "The user or the password is wrong");
+    assert!(matches!(
+        auth::login(&mut alice, "alice", "wonderland").unwrap(),
+        Response::Success(_)
+    ));
+
+    // Users that don't exist get challenges like the others, which can't be answered.
+    let mut stranger = connect();
+    let salt = challenge(&mut stranger, "nobody").salt;
+    assert_eq!(challenge(&mut stranger, "nobody").salt, salt);
+    let report = failure(auth::login(&mut stranger, "nobody", "secret").unwrap());
+    assert_eq!(report.message, "The user or the password is wrong");
+
+    // Repeated failures lock the user out for a while, even with the right password.
+    let mut guesser = connect();
+    for guess in ["1", "2", "3", "4"] {
+        let report = failure(auth::login(&mut guesser, "admin", guess).unwrap());
+        assert_eq!(report.message, "The user or the password is wrong");
+    }
+    failure(auth::login(&mut guesser, "admin", "5").unwrap());
+    let report = failure(auth::login(&mut guesser, "admin", "secret").unwrap());
+    assert_eq!(report.kind, "Authentication");
+    assert!(report.message.starts_with("Too many failed attempts"));
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    assert!(matches!(
+        auth::login(&mut guesser, "admin", "secret").unwrap(),
+        Response::Success(_)
+    ));
+}
+
+#[test]
+fn test_client() {
+    let address = start_server("client");
+
+    let error = Connection::connect(address, "admin", "wrong")
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    assert_eq!(ErrorReport::from_error(&error).kind, "Authentication");
+
+    let mut connection = Connection::connect(address, "admin", "secret").unwrap();
+    assert_eq!(connection.roles(), ["admin"]);
+    connection
+        .execute("table events { title: str, starts_at: datetime, seats: int }")
+        .unwrap();
+
+    // Parameters keep their types and are never parsed as code.
+    let starts_at = bson::DateTime::parse_rfc3339_str("2024-05-01T18:00:00Z").unwrap();
+    let title = r#"Launch"); delete &events; ("#;
+    connection
+        .execute_with(
+            "&events(title = $title, starts_at = $starts_at, seats = $seats)",
+            doc! { "title": title, "starts_at": starts_at, "seats": 40_i64 },
+        )
+        .unwrap();
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Event {
+        title: String,
+        starts_at: bson::DateTime,
+        seats: i64,
+    }
+    let events: Vec<Event> = connection.query("&events", doc! {}).unwrap();
+    assert_eq!(
+        events,
+        [Event {
+            title: title.to_string(),
+            starts_at,
+            seats: 40
+        }]
+    );
+    let seats: Vec<i64> = connection.query("&events.seats", doc! {}).unwrap();
+    assert_eq!(seats, [40]);
+
+    // Results have to fit the types they're decoded into.
+    let error = connection
+        .query::<Vec<String>>("&events.seats", doc! {})
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    let error = connection.execute("&events(title = ").unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(ErrorReport::from_error(&error).kind, "Syntax");
+
+    // A failing transaction is rolled back.
+    let error = connection
+        .transaction(|connection| {
+            connection.execute(r#"&events(title = "Draft", starts_at = $starts_at, seats = 1)"#)
+        })
+        .unwrap_err();
+    assert_eq!(ErrorReport::from_error(&error).kind, "Runtime");
+    assert!(!connection.in_transaction());
+    connection
+        .transaction(|connection| {
+            connection.execute_with(
+                r#"&events(title = "Meetup", starts_at = ?, seats = ?)"#,
+                vec![Bson::DateTime(starts_at), Bson::Int64(12)],
+            )
+        })
+        .unwrap();
+    let seats: Vec<i64> = connection.query("&events.seats", doc! {}).unwrap();
+    assert_eq!(seats, [40, 12]);
+
+    // The pool lends at most two connections and reuses those given back.
+    let pool = ClientPool::new(
+        address,
+        "admin",
+        "secret",
+        2,
+        std::time::Duration::from_millis(300),
+    )
+    .unwrap();
+    let first = pool.get().unwrap();
+    let mut second = pool.get().unwrap();
+    second.begin().unwrap();
+    let error = pool.get().err().unwrap();
+    assert_eq!(ErrorReport::from_error(&error).kind, "Unavailable");
+    drop(first);
+    drop(second);
+    assert_eq!((pool.open(), pool.idle()), (2, 2));
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let pool = pool.clone();
+            std::thread::spawn(move || {
+                let mut connection = pool.get().unwrap();
+                assert!(!connection.in_transaction());
+                connection
+                    .query::<Vec<i64>>("&events.seats", doc! {})
+                    .unwrap()
+            })
+        })
+        .collect();
+    for worker in workers {
+        assert_eq!(worker.join().unwrap(), [40, 12]);
+    }
+    assert_eq!(pool.open(), 2);
+}
+
+#[test]
+fn test_parameterized_queries() {
+    let storage = Storage::open(&temporary_datablaze("parameterized_queries"))
+        .unwrap()
+        .shared();
+    session::bootstrap_admin(&storage, "secret").unwrap();
+    let mut session = Session::new(storage);
+    let Response::Success(Bson::Document(challenge)) = session.handle(Request::Challenge {
+        user: "admin".to_string(),
+        nonce: auth::new_nonce(),
+    }) else {
+        panic!("a challenge is expected");
+    };
+    let challenge = auth::Challenge::from_document("admin", &challenge).unwrap();
+    let keys = Keys::derive("secret", &challenge.salt, challenge.iterations);
+    let proof = challenge.proof(&keys);
+    assert!(matches!(
+        session.handle(Request::Authenticate { proof }),
+        Response::Success(_)
+    ));
+    let mut execute = |code: &str, params: Params| match session.handle(Request::Execute {
+        code: code.to_string(),
+        params,
+    }) {
+        Response::Success(value) => Ok(value),
+        Response::Failure(report) => Err(report.message),
+    };
+
+    execute("table books { title: str, pages: int }", Params::default()).unwrap();
+    let insert = "&books(title = $title, pages = $pages)";
+    for (title, pages) in [
+        ("Dune", 412),
+        ("Solaris", 204),
+        (r#"x"); delete &books; ("#, 1),
+    ] {
+        execute(insert, doc! { "title": title, "pages": pages }.into()).unwrap();
+    }
+    assert_eq!(
+        execute(
+            "&books.{self.pages >= ?, self.pages < ?}.title",
+            vec![bson!(200), bson!(500)].into()
+        ),
+        Ok(bson!(["Dune", "Solaris"]))
+    );
+    assert_eq!(
+        execute(
+            "&books.{self.pages = $pages}.title",
+            doc! { "pages": 1 }.into()
+        ),
+        Ok(bson!([r#"x"); delete &books; ("#]))
+    );
+
+    // Placeholders and values have to match.
+    assert_eq!(
+        execute(insert, doc! { "title": "Ubik" }.into()),
+        Err("No value is bound to $pages".to_string())
+    );
+    assert_eq!(
+        execute(
+            insert,
+            doc! { "title": "Ubik", "pages": 1, "page": 2 }.into()
+        ),
+        Err("The script has no placeholder $page".to_string())
+    );
+    assert_eq!(
+        execute("?", vec![bson!(1), bson!(2)].into()),
+        Err("The script has 1 ? placeholders, but 2 values are given".to_string())
+    );
+    assert_eq!(
+        execute("$title", Params::default()),
+        Err("No value is bound to $title".to_string())
+    );
+
+    // The insertion is parsed once for its five runs, whatever its parameters.
+    let statements = session.statements();
+    assert_eq!(statements.hits(), 4);
+    let cached = statements.len();
+    for pages in 0..session::STATEMENT_CACHE_SIZE {
+        session.handle(Request::Execute {
+            code: format!("&books.{{self.pages = {}}}.title", pages),
+            params: Params::default(),
+        });
+    }
+    assert!(cached < session::STATEMENT_CACHE_SIZE);
+    assert_eq!(session.statements().len(), session::STATEMENT_CACHE_SIZE);
+}
+
+#[test]
+fn test_cursors() {
+    let address = start_server("cursors");
+    let mut connection = Connection::connect(address, "admin", "secret").unwrap();
+    let mut writer = Connection::connect(address, "admin", "secret").unwrap();
+    connection.execute("table items { n: int }").unwrap();
+    connection
+        .transaction(|connection| {
+            for n in 0..250 {
+                connection.execute_with("&items(n = ?)", vec![Bson::Int64(n)])?;
+            }
+            Ok(())
+        })
+        .unwrap();
+
+    // Batches come in order until the result runs out.
+    let mut cursor = connection
+        .cursor("&items.{self.n >= ?}.n", vec![Bson::Int64(10)], 100)
+        .unwrap();
+    let mut sizes = vec![];
+    let mut values = vec![];
+    while let Some(batch) = cursor.next_batch().unwrap() {
+        sizes.push(batch.len());
+        values.extend(batch);
+    }
+    assert_eq!(sizes, [100, 100, 40]);
+    assert!(cursor.is_done());
+    assert_eq!(values, (10..250).map(Bson::Int64).collect::<Vec<_>>());
+    drop(cursor);
+
+    // A query read lazily sees the rows written after the batches it has handed out.
+    let mut cursor = connection
+        .cursor("&items.{self.n < 5}.n", Params::default(), 2)
+        .unwrap();
+    let first = cursor.next_batch().unwrap().unwrap();
+    assert_eq!(first, [bson!(0_i64), bson!(1_i64)]);
+    assert!(!cursor.is_done());
+    writer.execute("&items(n = 4)").unwrap();
+    let rest: Vec<Bson> = cursor.map(Result::unwrap).collect();
+    assert_eq!(
+        rest,
+        bson!([2_i64, 3_i64, 4_i64, 4_i64])
+            .as_array()
+            .unwrap()
+            .clone()
+    );
+
+    // A cursor walking an index goes on from the last row it's seen, however the rows
+    // before it change.
+    connection
+        .execute("table scores { n: int }; index scores.n")
+        .unwrap();
+    for n in [3, 0, 4, 1, 2] {
+        connection
+            .execute_with("&scores(n = ?)", vec![Bson::Int64(n)])
+            .unwrap();
+    }
+    let mut cursor = connection
+        .cursor("&scores.{self.n >= 0}.n", Params::default(), 2)
+        .unwrap();
+    let first = cursor.next_batch().unwrap().unwrap();
+    assert_eq!(first, [bson!(0_i64), bson!(1_i64)]);
+    writer
+        .execute("delete &scores.{self.n = 0}; &scores(n = 5)")
+        .unwrap();
+    let rest: Vec<i64> = cursor.decode().map(Result::unwrap).collect();
+    assert_eq!(rest, [2, 3, 4, 5]);
+
+    // A cursor kept waiting by another session's transaction stays open until it ends.
+    let mut cursor = connection
+        .cursor("&items.{self.n < 6}.n", Params::default(), 2)
+        .unwrap();
+    assert_eq!(cursor.next_batch().unwrap().unwrap().len(), 2);
+    writer.begin().unwrap();
+    let error = cursor.next_batch().err().unwrap();
+    assert_eq!(ErrorReport::from_error(&error).kind, "Unavailable");
+    assert!(!cursor.is_done());
+    writer.rollback().unwrap();
+    let rest: Vec<i64> = cursor.decode().map(Result::unwrap).collect();
+    assert_eq!(rest, [2, 3, 4, 5, 4]);
+
+    // Offsets and limits are applied as the values come; other steps are computed whole.
+    let page: Vec<Bson> = connection
+        .cursor("&items.n.offset(3).limit(5)", Params::default(), 2)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(page, (3..8).map(Bson::Int64).collect::<Vec<_>>());
+    let largest: Vec<i64> = connection
+        .cursor("&items.order(n desc).limit(3).n", Params::default(), 2)
+        .unwrap()
+        .decode()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(largest, [249, 248, 247]);
+    let scripted: Vec<Bson> = connection
+        .cursor(
+            "fin small = &items.{self.n < 3}.n; small",
+            Params::default(),
+            2,
+        )
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(scripted, [bson!(0_i64), bson!(1_i64), bson!(2_i64)]);
+    let error = connection
+        .cursor("1 + 1", Params::default(), 2)
+        .err()
+        .unwrap();
+    assert_eq!(ErrorReport::from_error(&error).kind, "Runtime");
+
+    // Dropping a cursor before it's done cancels it, so a session doesn't run out of them.
+    for _ in 0..session::MAX_OPEN_CURSORS + 1 {
+        let mut cursor = connection.cursor("&items.n", Params::default(), 1).unwrap();
+        assert_eq!(cursor.next_batch().unwrap(), Some(vec![bson!(0_i64)]));
+    }
+    let cursor = connection.cursor("&items.n", Params::default(), 1).unwrap();
+    cursor.cancel().unwrap();
+    assert_eq!(
+        connection.execute("&items.n.limit(1)").unwrap(),
+        bson!([0_i64])
+    );
 }